@@ -0,0 +1,377 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use graphql_client::{GraphQLQuery, Response};
+use reqwest::{header, Client, StatusCode};
+use serde::Deserialize;
+use serde_json::Value;
+use thiserror::Error;
+use tracing::warn;
+
+use crate::strings::url::{ANILIST_API, ANILIST_AUTHORIZE};
+
+/// AniList allows about 90 requests a minute, it lowers that when it is under load
+const REQUESTS_PER_MINUTE: f64 = 90.0;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// 429s and 5xx are retried this many times, waiting twice as long every time
+const MAX_RETRIES: u32 = 3;
+const BASE_BACKOFF: Duration = Duration::from_millis(500);
+/// Waited after a 429 without a `Retry-After`
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(60);
+/// Media barely changes, so pages and media are served from the cache this long
+const CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+const CACHE_CAPACITY: usize = 1000;
+
+#[derive(Error, Debug)]
+pub enum AniListError {
+    #[error("AniList found nothing")]
+    NotFound,
+    #[error("AniList refused the token")]
+    Unauthorized,
+    #[error("AniList is rate limiting us")]
+    RateLimited,
+    #[error("AniList rejected the query: {0}")]
+    InvalidQuery(String),
+    #[error("Request error")]
+    Network(#[from] reqwest::Error),
+    #[error("AniList answered {0}")]
+    Unavailable(StatusCode),
+    #[error("Invalid response")]
+    InvalidResponse(#[from] serde_json::Error),
+}
+
+impl AniListError {
+    /// Sorts the GraphQL errors of a response, unknown ids come back as "Not Found."
+    pub fn from_graphql(errors: &[graphql_client::Error]) -> Self {
+        if errors
+            .iter()
+            .any(|error| error.message.starts_with("Not Found"))
+        {
+            return AniListError::NotFound;
+        }
+        if errors.iter().any(|error| {
+            error.message.starts_with("Invalid token") || error.message == "Unauthorized."
+        }) {
+            return AniListError::Unauthorized;
+        }
+        AniListError::InvalidQuery(
+            errors
+                .iter()
+                .map(|error| error.message.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+        )
+    }
+}
+
+tokio::task_local! {
+    /// How long the requests of the current task may wait on the rate limit, see `with_max_wait`
+    static MAX_WAIT: Duration;
+}
+
+/// Runs `future` with its AniList requests failing with `RateLimited` rather than waiting on the
+/// rate limit for longer than `max_wait`, for interactions Discord only waits seconds for
+pub async fn with_max_wait<F: Future>(max_wait: Duration, future: F) -> F::Output {
+    MAX_WAIT.scope(max_wait, future).await
+}
+
+/// A token bucket refilled at `REQUESTS_PER_MINUTE`, lowered to what AniList reports is left
+pub struct RateLimiter {
+    tokens: f64,
+    refilled_at: Instant,
+    blocked_until: Option<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        RateLimiter {
+            tokens: REQUESTS_PER_MINUTE,
+            refilled_at: Instant::now(),
+            blocked_until: None,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.refilled_at);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * REQUESTS_PER_MINUTE / 60.0)
+            .min(REQUESTS_PER_MINUTE);
+        self.refilled_at = now;
+    }
+
+    /// Takes a token, how long to wait before sending. Tokens may go negative so waiting
+    /// requests queue up instead of all going at once when the bucket refills. `None`, and no
+    /// token taken, if that is longer than `max_wait`.
+    pub fn reserve(&mut self, now: Instant, max_wait: Option<Duration>) -> Option<Duration> {
+        self.refill(now);
+        let tokens = self.tokens - 1.0;
+        let refill_wait = if tokens < 0.0 {
+            Duration::from_secs_f64(-tokens * 60.0 / REQUESTS_PER_MINUTE)
+        } else {
+            Duration::from_secs(0)
+        };
+        let blocked_wait = self.blocked_until.map_or(Duration::from_secs(0), |until| {
+            until.saturating_duration_since(now)
+        });
+        let wait = refill_wait.max(blocked_wait);
+        if matches!(max_wait, Some(max_wait) if wait > max_wait) {
+            return None;
+        }
+        self.tokens = tokens;
+        Some(wait)
+    }
+
+    /// Trusts `X-RateLimit-Remaining` when it has fewer requests left than the bucket
+    pub fn observe_remaining(&mut self, remaining: f64) {
+        self.tokens = self.tokens.min(remaining);
+    }
+
+    pub fn block_for(&mut self, now: Instant, duration: Duration) {
+        self.blocked_until = Some(now + duration);
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct CacheEntry {
+    response: Value,
+    expires_at: Instant,
+}
+
+/// Answers kept for `ttl`, the ones closest to expiring make room once `capacity` is reached
+pub struct ResponseCache {
+    entries: HashMap<String, CacheEntry>,
+    capacity: usize,
+    ttl: Duration,
+}
+
+impl ResponseCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        ResponseCache {
+            entries: HashMap::new(),
+            capacity,
+            ttl,
+        }
+    }
+
+    pub fn get(&self, key: &str, now: Instant) -> Option<Value> {
+        self.entries
+            .get(key)
+            .filter(|entry| entry.expires_at > now)
+            .map(|entry| entry.response.clone())
+    }
+
+    pub fn insert(&mut self, key: String, response: Value, now: Instant) {
+        if self.entries.len() >= self.capacity {
+            self.entries.retain(|_, entry| entry.expires_at > now);
+        }
+        // still full of fresh entries, make room by dropping the oldest
+        if self.entries.len() >= self.capacity {
+            if let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.expires_at)
+                .map(|(key, _)| key.clone())
+            {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(
+            key,
+            CacheEntry {
+                response,
+                expires_at: now + self.ttl,
+            },
+        );
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// The one AniList client of the bot, shared through the `TypeMap` so connections are reused
+/// and every request counts against the same rate limit
+pub struct AniListClient {
+    http: Client,
+    api_url: String,
+    /// The AniList API client users authorize to link their account, linking is off without it
+    oauth_client_id: Option<String>,
+    limiter: Mutex<RateLimiter>,
+    cache: Mutex<ResponseCache>,
+}
+
+impl AniListClient {
+    pub fn new() -> Self {
+        AniListClient {
+            http: Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .expect("Can not build the AniList HTTP client"),
+            api_url: ANILIST_API.to_owned(),
+            oauth_client_id: None,
+            limiter: Mutex::new(RateLimiter::new()),
+            cache: Mutex::new(ResponseCache::new(CACHE_CAPACITY, CACHE_TTL)),
+        }
+    }
+
+    /// Sends the queries to `api_url` instead, like a local mock server
+    pub fn with_api_url(mut self, api_url: impl Into<String>) -> Self {
+        self.api_url = api_url.into();
+        self
+    }
+
+    pub fn with_oauth_client_id(mut self, client_id: impl Into<String>) -> Self {
+        self.oauth_client_id = Some(client_id.into());
+        self
+    }
+
+    /// Where users get a token for `query_as`, `None` if linking is off
+    pub fn authorize_url(&self) -> Option<String> {
+        self.oauth_client_id.as_ref().map(|client_id| {
+            format!(
+                "{}?client_id={}&response_type=token",
+                ANILIST_AUTHORIZE, client_id
+            )
+        })
+    }
+
+    /// Sends `Q` with `variables`, answering from the cache when the same query was answered
+    /// without errors within `CACHE_TTL`
+    pub async fn query<Q: GraphQLQuery>(
+        &self,
+        variables: Q::Variables,
+    ) -> Result<Response<Q::ResponseData>, AniListError> {
+        self.send::<Q>(variables, None).await
+    }
+
+    /// Sends `Q` as the user `token` belongs to, for mutations and private lists. The answers
+    /// are never cached.
+    pub async fn query_as<Q: GraphQLQuery>(
+        &self,
+        variables: Q::Variables,
+        token: &str,
+    ) -> Result<Response<Q::ResponseData>, AniListError> {
+        self.send::<Q>(variables, Some(token)).await
+    }
+
+    async fn send<Q: GraphQLQuery>(
+        &self,
+        variables: Q::Variables,
+        token: Option<&str>,
+    ) -> Result<Response<Q::ResponseData>, AniListError> {
+        let body = Q::build_query(variables);
+        let key = format!(
+            "{}:{}",
+            body.operation_name,
+            serde_json::to_string(&body.variables)?
+        );
+        if token.is_none() {
+            let cached = self.cache.lock().unwrap().get(&key, Instant::now());
+            if let Some(response) = cached {
+                return Ok(Response::deserialize(&response)?);
+            }
+        }
+        let body = serde_json::to_vec(&body)?;
+        let deadline = MAX_WAIT
+            .try_with(|max_wait| Instant::now() + *max_wait)
+            .ok();
+
+        let mut attempt = 0;
+        loop {
+            let now = Instant::now();
+            let max_wait = deadline.map(|deadline| deadline.saturating_duration_since(now));
+            let wait = self
+                .limiter
+                .lock()
+                .unwrap()
+                .reserve(now, max_wait)
+                .ok_or(AniListError::RateLimited)?;
+            if wait > Duration::from_secs(0) {
+                tokio::time::sleep(wait).await;
+            }
+
+            let mut request = self
+                .http
+                .post(&self.api_url)
+                .header(header::CONTENT_TYPE, "application/json")
+                .header(header::ACCEPT, "application/json")
+                .body(body.clone());
+            if let Some(token) = token {
+                request = request.bearer_auth(token);
+            }
+            let error = match request.send().await {
+                Ok(response) => {
+                    if let Some(remaining) = header_number(&response, "X-RateLimit-Remaining") {
+                        self.limiter.lock().unwrap().observe_remaining(remaining);
+                    }
+
+                    let status = response.status();
+                    if status == StatusCode::TOO_MANY_REQUESTS {
+                        let retry_after = header_number(&response, "Retry-After")
+                            .map_or(DEFAULT_RETRY_AFTER, Duration::from_secs_f64);
+                        self.limiter
+                            .lock()
+                            .unwrap()
+                            .block_for(Instant::now(), retry_after);
+                        AniListError::RateLimited
+                    } else if status.is_server_error() {
+                        AniListError::Unavailable(status)
+                    } else {
+                        // unknown ids come back as a 404 with GraphQL errors, which is an answer
+                        let response: Value = serde_json::from_str(&response.text().await?)?;
+                        let parsed = Response::deserialize(&response)?;
+                        if parsed.errors.is_none() && token.is_none() {
+                            self.cache
+                                .lock()
+                                .unwrap()
+                                .insert(key, response, Instant::now());
+                        }
+                        return Ok(parsed);
+                    }
+                }
+                Err(why) if why.is_timeout() || why.is_connect() => AniListError::Network(why),
+                Err(why) => return Err(AniListError::Network(why)),
+            };
+
+            attempt += 1;
+            if attempt > MAX_RETRIES {
+                return Err(error);
+            }
+            let backoff = BASE_BACKOFF * 2_u32.pow(attempt - 1);
+            if matches!(deadline, Some(deadline) if Instant::now() + backoff > deadline) {
+                return Err(error);
+            }
+            warn!(
+                "AniList request failed ({}), retry {} in {:?}",
+                error, attempt, backoff
+            );
+            tokio::time::sleep(backoff).await;
+        }
+    }
+}
+
+impl Default for AniListClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn header_number(response: &reqwest::Response, name: &str) -> Option<f64> {
+    response
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+}
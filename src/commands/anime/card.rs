@@ -0,0 +1,497 @@
+use std::sync::Arc;
+
+use serenity::{
+    builder::{CreateComponents, CreateEmbed},
+    framework::standard::CommandResult,
+    model::{
+        channel::Message,
+        id::UserId,
+        interactions::{
+            message_component::{ActionRowComponent, InputTextStyle, MessageComponentInteraction},
+            modal::ModalSubmitInteraction,
+            InteractionResponseType,
+        },
+    },
+    prelude::Context,
+};
+
+use crate::{
+    custom_id::{CardAction, CardState, ComponentId},
+    db::watchlist::{WatchInfo, WatchListStore, WatchStatus, MAX_RATING},
+    error::{BotError, BotResult},
+    extentions::{ClientContextExt, MessageComponentInteractionExt, ModalSubmitInteractionExt},
+    media::{Media, MediaType},
+    metadata::MediaLookup,
+    paginator::MediaPaginator,
+    query::MediaQuery,
+};
+
+/// The search a card was looked up with is kept in its footer after this prefix
+const SEARCH_FOOTER_PREFIX: &str = "🔎 ";
+/// Discord caps modal titles at 45 characters
+const MODAL_TITLE_LENGTH: usize = 45;
+
+/// A media card looked up by `owner`, shared by the prefix and slash commands. Posted cards keep
+/// their state in the components' custom ids and are rebuilt on every interaction.
+pub struct MediaCard {
+    owner: UserId,
+    search: String,
+    media_paginator: MediaPaginator,
+    current_media: Media,
+    owner_info: Option<WatchInfo>,
+    watch_info_repo: Arc<dyn WatchListStore>,
+    show_results: bool,
+}
+
+impl MediaCard {
+    /// Resolves the exact media or queries the first page of the search, `None` if nothing was
+    /// found
+    pub async fn new(
+        ctx: &Context,
+        owner: UserId,
+        search: &str,
+        query: MediaQuery,
+    ) -> BotResult<Option<Self>> {
+        let metadata = ctx.get_metadata().await;
+        let media_paginator = match query {
+            MediaQuery::Find(lookup) => match MediaPaginator::find(metadata, lookup).await? {
+                Some(media_paginator) => media_paginator,
+                None => return Ok(None),
+            },
+            MediaQuery::Search(search) => MediaPaginator::new(metadata, search).await?,
+        };
+        Self::from_paginator(ctx, owner, search.to_owned(), media_paginator).await
+    }
+
+    /// Rebuilds a posted `card` from the `state` of one of its components, `None` if the media
+    /// can no longer be found or adult media became hidden
+    pub async fn restore(
+        ctx: &Context,
+        state: &CardState,
+        card: &Message,
+        hide_adult: bool,
+    ) -> BotResult<Option<Self>> {
+        let search = card
+            .embeds
+            .first()
+            .and_then(|embed| embed.footer.as_ref())
+            .and_then(|footer| footer.text.strip_prefix(SEARCH_FOOTER_PREFIX))
+            .unwrap_or_default()
+            .to_owned();
+
+        // query the page the card was on, unless the results shifted since it was posted
+        let metadata = ctx.get_metadata().await;
+        let mut media_paginator = None;
+        if let Ok(MediaQuery::Search(mut media_search)) =
+            MediaQuery::parse(&search, state.media_type)
+        {
+            media_search.page = state.page;
+            if hide_adult {
+                media_search.is_adult = Some(false);
+            }
+            let mut paginator = MediaPaginator::new(metadata.clone(), media_search).await?;
+            if paginator.jump_to(state.index).map(|media| media.id) == Some(state.media_id) {
+                media_paginator = Some(paginator);
+            }
+        }
+        let media_paginator = match media_paginator {
+            Some(media_paginator) => media_paginator,
+            None => match MediaPaginator::find(
+                metadata,
+                MediaLookup {
+                    is_adult: if hide_adult { Some(false) } else { None },
+                    ..MediaLookup::by_id(state.media_id, state.media_type)
+                },
+            )
+            .await?
+            {
+                Some(media_paginator) => media_paginator,
+                None => return Ok(None),
+            },
+        };
+
+        let card = Self::from_paginator(ctx, state.owner, search, media_paginator).await?;
+        Ok(card.map(|card| MediaCard {
+            show_results: state.show_results,
+            ..card
+        }))
+    }
+
+    async fn from_paginator(
+        ctx: &Context,
+        owner: UserId,
+        search: String,
+        media_paginator: MediaPaginator,
+    ) -> BotResult<Option<Self>> {
+        let current_media = match media_paginator.current_page() {
+            Some(media) => media,
+            None => return Ok(None),
+        };
+
+        let watch_info_repo = ctx.get_watch_list().await;
+        let owner_info = watch_info_repo
+            .find_watch_info(current_media.id, owner)
+            .await
+            .map_err(BotError::Storage)?;
+        Ok(Some(MediaCard {
+            owner,
+            search,
+            media_paginator,
+            current_media,
+            owner_info,
+            watch_info_repo,
+            show_results: false,
+        }))
+    }
+
+    pub fn not_found_message(media_type: &MediaType) -> String {
+        format!(
+            "Cannot find any {}...",
+            match media_type {
+                MediaType::Manga => "manga",
+                _ => "anime",
+            }
+        )
+    }
+
+    fn state(&self) -> CardState {
+        let (page, index) = self.media_paginator.position();
+        CardState {
+            owner: self.owner,
+            media_id: self.current_media.id,
+            media_type: self.current_media.media_type,
+            page,
+            index,
+            show_results: self.show_results,
+        }
+    }
+
+    pub fn embed(&self) -> CreateEmbed {
+        let mut embed = if self.show_results {
+            self.media_paginator.results_embed()
+        } else {
+            self.current_media.embed_card(self.owner_info.as_ref())
+        };
+        embed.footer(|f| f.text(format!("{}{}", SEARCH_FOOTER_PREFIX, self.search)));
+        embed
+    }
+
+    pub fn components(&self) -> CreateComponents {
+        self.media_paginator
+            .components(self.owner_info.as_ref(), &self.state())
+    }
+
+    /// Moves the card to `media`, the card then shows the owner's entry for it
+    async fn show(&mut self, media: Media) -> BotResult<()> {
+        self.owner_info = self
+            .watch_info_repo
+            .find_watch_info(media.id, self.owner)
+            .await
+            .map_err(BotError::Storage)?;
+        self.current_media = media;
+        Ok(())
+    }
+}
+
+/// Handles a component on a posted card, buttons act on the list of whoever pressed them so a
+/// card doubles as a shared "add to my list" widget
+pub async fn handle_component(
+    ctx: &Context,
+    interaction: &MessageComponentInteraction,
+    action: CardAction,
+    state: CardState,
+    hide_adult: bool,
+) -> CommandResult {
+    // only the owner flips through the card
+    if action.is_navigation() && interaction.user.id != state.owner {
+        interaction
+            .reply_error(
+                ctx,
+                format!(
+                    "Only <@{}> can flip through this card, look it up yourself to browse 🔎",
+                    state.owner
+                ),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    // Ask for a score, the submission is handled by `handle_rating`
+    if action == CardAction::Rate {
+        let title = interaction
+            .message
+            .embeds
+            .first()
+            .and_then(|embed| embed.title.clone())
+            .unwrap_or_default();
+        interaction
+            .create_interaction_response(&ctx, |resp| {
+                resp.kind(InteractionResponseType::Modal)
+                    .interaction_response_data(|data| {
+                        data.custom_id(ComponentId::Card(CardAction::RateModal, state))
+                            .title(
+                                format!("Rate {}", title)
+                                    .chars()
+                                    .take(MODAL_TITLE_LENGTH)
+                                    .collect::<String>(),
+                            )
+                            .components(|c| {
+                                c.create_action_row(|ar| {
+                                    ar.create_input_text(|it| {
+                                        it.custom_id("RATING")
+                                            .style(InputTextStyle::Short)
+                                            .label(format!("Your score (1-{})", MAX_RATING))
+                                            .placeholder(MAX_RATING)
+                                            .min_length(1)
+                                            .max_length(2)
+                                            .required(true)
+                                    })
+                                })
+                            })
+                    })
+            })
+            .await?;
+        return Ok(());
+    }
+
+    if action.is_navigation() {
+        // restoring re-runs the card's search, that may take longer than Discord waits
+        interaction.ack(ctx).await?;
+        let mut card =
+            match MediaCard::restore(ctx, &state, &interaction.message, hide_adult).await? {
+                Some(card) => card,
+                None => {
+                    interaction
+                        .followup_error(ctx, "This media is gone from AniList")
+                        .await?;
+                    return Ok(());
+                }
+            };
+        let next_media = match action {
+            CardAction::PrevPage => card.media_paginator.prev_page().await?,
+            CardAction::NextPage => card.media_paginator.next_page().await?,
+            CardAction::JumpTo => {
+                // picking a result opens its card
+                card.show_results = false;
+                interaction
+                    .data
+                    .values
+                    .first()
+                    .and_then(|index| index.parse().ok())
+                    .and_then(|index| card.media_paginator.jump_to(index))
+            }
+            _ => {
+                card.show_results = !card.show_results;
+                None
+            }
+        };
+        if let Some(media) = next_media {
+            card.show(media).await?;
+        } else if action != CardAction::Results {
+            return Ok(());
+        }
+
+        // Update message on page change
+        interaction
+            .edit_original_interaction_response(&ctx.http, |r| {
+                r.set_embed(card.embed()).components(|c| {
+                    *c = card.components();
+                    c
+                })
+            })
+            .await?;
+        return Ok(());
+    }
+
+    let media = match card_media(ctx, &state, hide_adult).await? {
+        Some(media) => media,
+        None => {
+            interaction
+                .reply_error(ctx, "This media is gone from AniList")
+                .await?;
+            return Ok(());
+        }
+    };
+    let watch_list = ctx.get_watch_list().await;
+    let template_parser = liquid::ParserBuilder::with_stdlib().build()?;
+    let (watch_info, template) = match action {
+        CardAction::Status => {
+            let status =
+                WatchStatus::from(interaction.data.values.first().cloned().unwrap_or_default());
+            (
+                watch_list
+                    .set_status(&media, interaction.user.id, status)
+                    .await,
+                template_parser.parse("You {{ status_verb }} _it_ {{ status_emoji }}")?,
+            )
+        }
+        CardAction::Suggest => (
+            watch_list
+                .toggle_suggestion(&media, interaction.user.id)
+                .await,
+            template_parser
+                .parse("You are {{ suggestion_verb }} suggesting _it_ {{ suggestion_emoji }}")?,
+        ),
+        CardAction::ProgressInc | CardAction::ProgressDec => {
+            let amount = match action {
+                CardAction::ProgressInc => 1,
+                _ => -1,
+            };
+            (
+                watch_list
+                    .add_progress(&media, interaction.user.id, amount)
+                    .await,
+                template_parser.parse(
+                    "{% if finished %}You {{ status_verb }} _it_\
+                    {% else %}You are on {{ progress_unit }} **{{ progress }}**\
+                    {% endif %} {{ status_emoji }}",
+                )?,
+            )
+        }
+        _ => return Ok(()),
+    };
+
+    // Send correct reply message
+    match watch_info.map_err(BotError::Storage)? {
+        Some(info) => {
+            let variables = liquid::object!({
+                "suggestion_emoji": if info.suggests {"🌟"} else {""},
+                "suggestion_verb": if info.suggests {"now"} else {"no longer"},
+                "status_verb": info.watch_status.as_verb(&info.media_type),
+                "status_emoji": info.watch_status.as_emoji(&info.media_type),
+                "finished": info.watch_status == WatchStatus::Finished,
+                "progress_unit": info.progress_unit(),
+                "progress": info.progress_text(media.total_progress()),
+            });
+            let reply_message = template.render(&variables)?;
+            interaction
+                .reply_success(ctx, reply_message, media.title.clone())
+                .await?;
+
+            // the card shows the owner's status, progress and rating, it is redrawn from its
+            // search once the click is answered
+            if interaction.user.id == state.owner {
+                if let Some(mut card) =
+                    MediaCard::restore(ctx, &state, &interaction.message, hide_adult).await?
+                {
+                    card.owner_info = Some(info);
+                    interaction
+                        .message
+                        .clone()
+                        .edit(&ctx, |m| {
+                            m.set_embed(card.embed()).set_components(card.components())
+                        })
+                        .await?;
+                }
+            }
+        }
+        None => interaction.ack(ctx).await?,
+    }
+
+    Ok(())
+}
+
+/// Handles a rating modal opened from a card's Rate button
+pub async fn handle_rating(
+    ctx: &Context,
+    submission: &ModalSubmitInteraction,
+    state: CardState,
+    hide_adult: bool,
+) -> CommandResult {
+    // validate the score
+    let rating = submission
+        .data
+        .components
+        .iter()
+        .flat_map(|row| row.components.iter())
+        .find_map(|component| match component {
+            ActionRowComponent::InputText(input) if input.custom_id == "RATING" => {
+                input.value.trim().parse::<u8>().ok()
+            }
+            _ => None,
+        })
+        .filter(|rating| (1..=MAX_RATING).contains(rating));
+    let rating = match rating {
+        Some(rating) => rating,
+        None => {
+            submission
+                .reply_error(
+                    ctx,
+                    format!("A rating must be a number from 1 to {}", MAX_RATING),
+                )
+                .await?;
+            return Ok(());
+        }
+    };
+
+    // the modal remembers the media it was opened for, even if the card moved on since
+    let message = match &submission.message {
+        Some(message) => message,
+        None => return Ok(()),
+    };
+    let media = match card_media(ctx, &state, hide_adult).await? {
+        Some(media) => media,
+        None => {
+            submission
+                .reply_error(ctx, "This media is gone from AniList")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let info = ctx
+        .get_watch_list()
+        .await
+        .set_rating(&media, submission.user.id, rating)
+        .await
+        .map_err(BotError::Storage)?;
+    submission
+        .reply_success(
+            ctx,
+            format!("You rated _it_ **{}/{}** 🌠", rating, MAX_RATING),
+            media.title.clone(),
+        )
+        .await?;
+    // the card shows the owner's rating, if it is still on the rated media
+    let current_state = message
+        .components
+        .first()
+        .and_then(|row| row.components.first())
+        .and_then(|component| match component {
+            ActionRowComponent::Button(button) => button.custom_id.as_deref(),
+            _ => None,
+        })
+        .and_then(|custom_id| custom_id.parse::<ComponentId>().ok());
+    let on_rated_media = matches!(
+        current_state,
+        Some(ComponentId::Card(_, current)) if current.media_id == state.media_id
+            && !current.show_results
+    );
+    if submission.user.id == state.owner && on_rated_media {
+        if let Some(mut card) = MediaCard::restore(ctx, &state, message, hide_adult).await? {
+            card.owner_info = info;
+            message
+                .clone()
+                .edit(&ctx, |m| {
+                    m.set_embed(card.embed()).set_components(card.components())
+                })
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The media a card's `state` is on, found by id alone so buttons that change a watch list answer
+/// before Discord gives up. Only with adult media hidden is the cache passed over.
+async fn card_media(
+    ctx: &Context,
+    state: &CardState,
+    hide_adult: bool,
+) -> BotResult<Option<Media>> {
+    let lookup = MediaLookup {
+        is_adult: if hide_adult { Some(false) } else { None },
+        ..MediaLookup::by_id(state.media_id, state.media_type)
+    };
+    Ok(ctx.get_metadata().await.media(&lookup).await?)
+}
@@ -1,148 +1,76 @@
-use std::time::Duration;
-
-use anyhow::anyhow;
-use mongodm::ToRepository;
 use serenity::{
-    builder::CreateComponents,
     framework::standard::{macros::command, Args, CommandResult},
-    futures::StreamExt,
-    model::{channel::Message, interactions::InteractionResponseType},
+    model::channel::Message,
     prelude::Context,
 };
 
 use crate::{
-    db::watchlist::{WatchInfo, WatchListCollectionExt},
-    extentions::{ClientContextExt, MessageComponentInteractionExt},
-    graphql::lookup_media_page::Variables,
-    paginator::MediaPaginator,
+    commands::anime::card::MediaCard, error::BotError, extentions::ClientContextExt,
+    media::MediaType, query::MediaQuery,
 };
 
 #[command("anime")]
-#[usage("anime <anime name>")]
+#[description("Look up an anime by name, AniList link or id, or browse by filters")]
+#[usage(
+    "anime <anime name|anilist id|anilist url|mal:<mal id>> [year:<year>] [format:<tv,movie,..>] \
+    [genre:<genre,..>] [status:<releasing|finished|upcoming|cancelled|hiatus>] \
+    [sort:<score|popularity|trending|newest|oldest|title|favourites>] [adult:<yes|no>]"
+)]
+#[example("anime frieren")]
+#[example("anime year:2023 genre:comedy sort:score")]
 #[min_args(1)]
-pub async fn lookup(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+pub async fn lookup(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    lookup_media(ctx, msg, args, MediaType::Anime).await
+}
+
+#[command("manga")]
+#[description("Look up a manga or light novel by name, AniList link or id, or browse by filters")]
+#[usage(
+    "manga <manga or light novel name|anilist id|anilist url|mal:<mal id>> [year:<year>] \
+    [format:<manga,novel,one_shot>] [genre:<genre,..>] \
+    [status:<releasing|finished|upcoming|cancelled|hiatus>] \
+    [sort:<score|popularity|trending|newest|oldest|title|favourites>] [adult:<yes|no>]"
+)]
+#[example("manga \"Kaguya-sama\"")]
+#[example("manga format:novel status:releasing")]
+#[min_args(1)]
+pub async fn manga(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    lookup_media(ctx, msg, args, MediaType::Manga).await
+}
+
+async fn lookup_media(
+    ctx: &Context,
+    msg: &Message,
+    mut args: Args,
+    media_type: MediaType,
+) -> CommandResult {
     // validate arguments
-    let anime_name = args.trimmed().quoted().rest();
+    let media_name = args.trimmed().quoted().rest();
+
+    // parse the title and filters
+    let mut query = MediaQuery::parse(media_name, media_type).map_err(BotError::from)?;
 
-    // query anime page 1
-    let mut media_paginator = MediaPaginator::new(Variables {
-        search: anime_name.to_owned(),
-        ..Variables::default()
-    })
-    .await?;
+    let config = ctx.get_guild_config(msg.guild_id).await?;
+    if matches!(&config, Some(config) if config.hides_adult()) {
+        query.hide_adult();
+    }
 
-    let mut current_media = match media_paginator.current_page() {
-        Some(media) => media,
+    // find the exact media or query media page 1
+    let card = match MediaCard::new(ctx, msg.author.id, media_name, query).await? {
+        Some(card) => card,
         None => {
-            msg.reply(&ctx.http, "Cannot find any anime...").await?;
+            msg.reply(&ctx.http, MediaCard::not_found_message(&media_type))
+                .await?;
             return Ok(());
         }
     };
 
-    // send anime card
-    let mut anime_card = msg
-        .channel_id
+    // send media card, its components are handled by the interaction dispatcher
+    msg.channel_id
         .send_message(&ctx.http, |m| {
-            m.set_embed(current_media.embed_card())
-                .components(|c| c.add_action_row(media_paginator.action_row()))
+            m.set_embed(card.embed()).set_components(card.components())
         })
         .await?;
 
-    let watch_info_repo = ctx.get_db().await.repository::<WatchInfo>();
-    let template_parser = liquid::ParserBuilder::with_stdlib().build()?;
-
-    // listen for component interactions
-    let mut interaction_collector = anime_card
-        .await_component_interactions(&ctx)
-        .timeout(Duration::from_secs(60))
-        .build();
-    while let Some(interaction) = interaction_collector.next().await {
-        let mut next_media = None;
-        let (mut watch_info, mut template) = (None, None);
-
-        match interaction.data.custom_id.as_str() {
-            "PREV_PAGE" => next_media = media_paginator.prev_page().await,
-            "NEXT_PAGE" => next_media = media_paginator.next_page().await,
-            "WATCH" => {
-                (watch_info, template) = (
-                    watch_info_repo
-                        .toggle_consuming(&current_media, msg.author.id)
-                        .await
-                        .transpose(),
-                    Some(template_parser.parse("You {{ status_verb }} _it_ {{ status_emoji }}")?),
-                )
-            }
-            "FINISH" => {
-                (watch_info, template) = (
-                    watch_info_repo
-                        .toggle_finish(&current_media, msg.author.id)
-                        .await
-                        .transpose(),
-                    Some(template_parser.parse("You {{ status_verb }} _it_ {{ status_emoji }}")?),
-                )
-            }
-            "SUGGEST" => {
-                (watch_info, template) = (
-                    watch_info_repo
-                        .toggle_suggestion(&current_media, msg.author.id)
-                        .await
-                        .transpose(),
-                    Some(template_parser.parse(
-                        "You are {{ suggestion_verb }} suggesting _it_ {{ suggestion_emoji }}",
-                    )?),
-                )
-            }
-            _ => return Ok(()),
-        }
-
-        match interaction.data.custom_id.as_str() {
-            // Update message on page change
-            "NEXT_PAGE" | "PREV_PAGE" => match next_media {
-                Some(media) => {
-                    current_media = media;
-                    interaction
-                        .create_interaction_response(&ctx, |resp| {
-                            resp.kind(InteractionResponseType::UpdateMessage)
-                                .interaction_response_data(|data| {
-                                    data.set_embed(current_media.embed_card()).components(|c| {
-                                        c.add_action_row(media_paginator.action_row())
-                                    })
-                                })
-                        })
-                        .await?;
-                }
-                None => interaction.ack(ctx).await?,
-            },
-            // Send correct reply message
-            "WATCH" | "FINISH" | "SUGGEST" => match watch_info {
-                Some(Ok(info)) => {
-                    if let Some(template) = template {
-                        let variables = liquid::object!({
-                            "suggestion_emoji": if info.suggests {"🌟"} else {""},
-                            "suggestion_verb": if info.suggests {"now"} else {"no longer"},
-                            "status_verb": info.watch_status.as_verb(),
-                            "status_emoji": info.watch_status.as_emoji(),
-                        });
-                        let reply_message = template.render(&variables)?;
-                        interaction
-                            .reply_success(ctx, reply_message, current_media.get_title())
-                            .await?;
-                    }
-                }
-                Some(Err(why)) => {
-                    interaction.reply_error(ctx, "There is an error‼").await?;
-                    return Err(anyhow!("{:?}", why).into());
-                }
-                None => interaction.ack(ctx).await?,
-            },
-            _ => (),
-        }
-    }
-
-    // remove components after timeout
-    anime_card
-        .edit(&ctx, |m| m.set_components(CreateComponents::default()))
-        .await?;
-
     Ok(())
 }
@@ -1 +1,2 @@
+pub mod card;
 pub mod lookup;
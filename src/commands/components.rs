@@ -0,0 +1,75 @@
+use serenity::{
+    framework::standard::CommandResult,
+    model::{
+        interactions::{
+            message_component::MessageComponentInteraction, modal::ModalSubmitInteraction,
+        },
+        Timestamp,
+    },
+    prelude::Context,
+};
+use tracing::info;
+
+use crate::{
+    commands::{
+        anime::card,
+        watchlist::{list, suggestions},
+    },
+    custom_id::{CardAction, ComponentId},
+    extentions::{ClientContextExt, MessageComponentInteractionExt, ModalSubmitInteractionExt},
+};
+
+const OUTDATED_MESSAGE: &str = "This message is too old to use, look it up again please";
+
+/// Routes a component interaction to the card, list or suggestions its custom id belongs to
+pub async fn handle_component(
+    ctx: &Context,
+    interaction: MessageComponentInteraction,
+) -> CommandResult {
+    info!(
+        "Got component '{}' by user '{}'",
+        interaction.data.custom_id,
+        interaction.user.tag()
+    );
+
+    // the guild may let cards and lists time out
+    let config = ctx.get_guild_config(interaction.guild_id).await?;
+    let posted_at = interaction.message.timestamp.unix_timestamp();
+    let now = Timestamp::now().unix_timestamp();
+    if matches!(&config, Some(config) if config.is_timed_out(posted_at, now)) {
+        interaction.reply_error(ctx, OUTDATED_MESSAGE).await?;
+        return Ok(());
+    }
+
+    let hide_adult = matches!(&config, Some(config) if config.hides_adult());
+    match interaction.data.custom_id.parse::<ComponentId>() {
+        Ok(ComponentId::Card(action, state)) => {
+            card::handle_component(ctx, &interaction, action, state, hide_adult).await
+        }
+        Ok(ComponentId::List(action, state)) => {
+            list::handle_component(ctx, &interaction, action, state).await
+        }
+        Ok(ComponentId::Suggestions(action, page)) => {
+            suggestions::handle_component(ctx, &interaction, action, page).await
+        }
+        Err(_) => {
+            interaction.reply_error(ctx, OUTDATED_MESSAGE).await?;
+            Ok(())
+        }
+    }
+}
+
+/// Routes a modal submission to the card it was opened from
+pub async fn handle_modal(ctx: &Context, submission: ModalSubmitInteraction) -> CommandResult {
+    let config = ctx.get_guild_config(submission.guild_id).await?;
+    let hide_adult = matches!(&config, Some(config) if config.hides_adult());
+    match submission.data.custom_id.parse::<ComponentId>() {
+        Ok(ComponentId::Card(CardAction::RateModal, state)) => {
+            card::handle_rating(ctx, &submission, state, hide_adult).await
+        }
+        _ => {
+            submission.reply_error(ctx, OUTDATED_MESSAGE).await?;
+            Ok(())
+        }
+    }
+}
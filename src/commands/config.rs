@@ -0,0 +1,276 @@
+use mongodm::ToRepository;
+use serenity::{
+    framework::standard::{macros::command, Args, CommandResult},
+    model::{
+        channel::Message,
+        id::{ChannelId, GuildId, RoleId, UserId},
+        Permissions,
+    },
+    prelude::Context,
+    utils::parse_channel,
+};
+
+use crate::{
+    db::guild_config::{
+        AdultContent, GuildConfig, GuildConfigCollectionExt, GuildConfigUpdate, TOGGLEABLE_COMMANDS,
+    },
+    embeds::make_success_embed,
+    error::{BotError, BotResult},
+    extentions::ClientContextExt,
+    utils::GuildPrefixContainer,
+};
+
+const MAX_PREFIX_LENGTH: usize = 10;
+const CHANNEL_MESSAGE: &str = "Mention a channel of this server, like `#anime`...";
+
+#[command("config")]
+#[description("Show or change how I behave in this server")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+#[usage(
+    "config [prefix <prefix|reset>|channel <#channel|off>|board <#channel|off>|\
+    locale <language tag|reset>|adult <allow|hide>|timeout <minutes|off>|enable <command>|disable <command>]"
+)]
+#[example("config prefix !")]
+#[example("config disable manga")]
+#[example("config board #suggestions")]
+pub async fn config(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+    // `required_permissions` lets everyone through when the guild is not cached
+    if !can_manage_guild(ctx, guild_id, msg.author.id).await? {
+        return Err(BotError::Validation(
+            "You need the Manage Server permission to change my config".to_owned(),
+        )
+        .into());
+    }
+    let config_repo = ctx.get_db().await?.repository::<GuildConfig>();
+    args.trimmed();
+
+    // validate arguments
+    let key = args.single::<String>().ok().map(|key| key.to_lowercase());
+    let value = args.rest().trim();
+    let reset = matches!(value.to_lowercase().as_str(), "reset" | "off" | "none");
+    let update = match key.as_deref() {
+        None => None,
+        Some("prefix") if reset => Some(GuildConfigUpdate::Prefix(None)),
+        Some("prefix") => {
+            if value.is_empty()
+                || value.len() > MAX_PREFIX_LENGTH
+                || value.contains(char::is_whitespace)
+            {
+                msg.reply(
+                    &ctx.http,
+                    format!(
+                        "A prefix is 1 to {} characters without spaces, like `!`...",
+                        MAX_PREFIX_LENGTH
+                    ),
+                )
+                .await?;
+                return Ok(());
+            }
+            Some(GuildConfigUpdate::Prefix(Some(value.to_owned())))
+        }
+        Some("channel") if reset => Some(GuildConfigUpdate::AnnouncementChannel(None)),
+        Some("channel") => match guild_channel(ctx, guild_id, value).await {
+            Some(channel_id) => Some(GuildConfigUpdate::AnnouncementChannel(Some(channel_id))),
+            None => {
+                msg.reply(&ctx.http, CHANNEL_MESSAGE).await?;
+                return Ok(());
+            }
+        },
+        Some("board") if reset => Some(GuildConfigUpdate::SuggestionChannel(None)),
+        Some("board") => match guild_channel(ctx, guild_id, value).await {
+            Some(channel_id) => Some(GuildConfigUpdate::SuggestionChannel(Some(channel_id))),
+            None => {
+                msg.reply(&ctx.http, CHANNEL_MESSAGE).await?;
+                return Ok(());
+            }
+        },
+        Some("locale") if reset => Some(GuildConfigUpdate::Locale(None)),
+        Some("locale") => {
+            if !is_language_tag(value) {
+                msg.reply(
+                    &ctx.http,
+                    "A locale is a language tag like `en`, `de` or `pt-BR`...",
+                )
+                .await?;
+                return Ok(());
+            }
+            Some(GuildConfigUpdate::Locale(Some(value.to_owned())))
+        }
+        Some("adult") => match value.parse::<AdultContent>() {
+            Ok(adult_content) => Some(GuildConfigUpdate::AdultContent(adult_content)),
+            Err(_) => {
+                msg.reply(&ctx.http, "Adult media is either `allow` or `hide`...")
+                    .await?;
+                return Ok(());
+            }
+        },
+        Some("timeout") if reset => Some(GuildConfigUpdate::CardTimeout(None)),
+        Some("timeout") => match value.parse::<u32>() {
+            Ok(minutes) if minutes > 0 => Some(GuildConfigUpdate::CardTimeout(Some(minutes))),
+            _ => {
+                msg.reply(
+                    &ctx.http,
+                    "A timeout is a number of minutes, or `off` to keep cards working forever...",
+                )
+                .await?;
+                return Ok(());
+            }
+        },
+        Some(toggle @ ("enable" | "disable")) => {
+            let name = value.to_lowercase();
+            if !TOGGLEABLE_COMMANDS.contains(&name.as_str()) {
+                msg.reply(
+                    &ctx.http,
+                    format!(
+                        "I can only {} {}...",
+                        toggle,
+                        TOGGLEABLE_COMMANDS
+                            .iter()
+                            .map(|name| format!("`{}`", name))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                )
+                .await?;
+                return Ok(());
+            }
+            Some(GuildConfigUpdate::Command {
+                name,
+                enabled: toggle == "enable",
+            })
+        }
+        Some(key) => {
+            msg.reply(&ctx.http, format!("I don't know the `{}` setting...", key))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let config = match &update {
+        Some(update) => config_repo.update_config(guild_id, update).await?,
+        None => config_repo.find_config(guild_id).await?,
+    };
+    if let Some(GuildConfigUpdate::Prefix(prefix)) = update {
+        let mut data = ctx.data.write().await;
+        if let Some(prefixes) = data.get_mut::<GuildPrefixContainer>() {
+            prefixes.insert(guild_id, prefix);
+        }
+    }
+
+    let embed = make_success_embed(|e| {
+        e.title("⚙️ Server config");
+        match &config {
+            Some(config) => e
+                .field(
+                    "Prefix",
+                    config
+                        .prefix
+                        .as_ref()
+                        .map_or("Default".to_owned(), |prefix| format!("`{}`", prefix)),
+                    true,
+                )
+                .field(
+                    "Announcements",
+                    config
+                        .announcement_channel
+                        .map_or("By DM".to_owned(), |channel_id| {
+                            format!("<#{}>", channel_id)
+                        }),
+                    true,
+                )
+                .field(
+                    "Suggestion board",
+                    config
+                        .suggestion_channel
+                        .map_or("Off".to_owned(), |channel_id| format!("<#{}>", channel_id)),
+                    true,
+                )
+                .field(
+                    "Locale",
+                    config.locale.as_deref().unwrap_or("Default"),
+                    true,
+                )
+                .field("Adult media", config.adult_content, true)
+                .field(
+                    "Card timeout",
+                    config
+                        .card_timeout
+                        .map_or("Never".to_owned(), |minutes| format!("{} min", minutes)),
+                    true,
+                )
+                .field(
+                    "Disabled commands",
+                    if config.disabled_commands.is_empty() {
+                        "None".to_owned()
+                    } else {
+                        config
+                            .disabled_commands
+                            .iter()
+                            .map(|name| format!("`{}`", name))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    },
+                    true,
+                ),
+            None => e.description(
+                "Everything is on the defaults, change them with `config <setting> <value>`",
+            ),
+        }
+    })
+    .await;
+    msg.channel_id
+        .send_message(&ctx.http, |m| m.set_embed(embed))
+        .await?;
+
+    Ok(())
+}
+
+/// The channel `value` names if it is in `guild_id`, posting elsewhere would leak who watches
+/// what
+async fn guild_channel(ctx: &Context, guild_id: GuildId, value: &str) -> Option<ChannelId> {
+    let channel_id = parse_channel(value)
+        .or_else(|| value.parse().ok())
+        .map(ChannelId)?;
+    channel_id
+        .to_channel(ctx)
+        .await
+        .ok()
+        .and_then(|channel| channel.guild())
+        .filter(|channel| channel.guild_id == guild_id)
+        .map(|channel| channel.id)
+}
+
+/// Loosely checks for a BCP 47 tag like `en`, `pt-BR` or `zh-Hant`
+fn is_language_tag(value: &str) -> bool {
+    let mut subtags = value.split('-');
+    let language = subtags.next().unwrap_or_default();
+    (2..=3).contains(&language.len())
+        && language.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags.all(|subtag| {
+            (2..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
+/// Whether `user_id` may manage `guild_id`, asked from Discord so a failed lookup refuses
+async fn can_manage_guild(ctx: &Context, guild_id: GuildId, user_id: UserId) -> BotResult<bool> {
+    let guild = guild_id.to_partial_guild(&ctx.http).await?;
+    if guild.owner_id == user_id {
+        return Ok(true);
+    }
+    let member = ctx.http.get_member(guild_id.0, user_id.0).await?;
+    // the @everyone role shares the guild's id
+    let permissions = member
+        .roles
+        .iter()
+        .chain(std::iter::once(&RoleId(guild_id.0)))
+        .filter_map(|role_id| guild.roles.get(role_id))
+        .fold(Permissions::empty(), |permissions, role| {
+            permissions | role.permissions
+        });
+    Ok(permissions.administrator() || permissions.manage_guild())
+}
@@ -1,26 +1,182 @@
+use anyhow::Result;
 use serenity::{
-    framework::standard::{macros::command, Args, CommandResult},
-    model::prelude::*,
+    builder::CreateEmbed,
+    framework::standard::{macros::command, Args, Command, CommandGroup, CommandResult, OnlyIn},
+    model::{
+        id::{GuildId, UserId},
+        prelude::*,
+    },
     prelude::*,
 };
 
-// TODO: correct help message
-const HELP_MESSAGE: &str = "
-Hello there, Human!
-You have summoned me. Let's see about getting you what you need.
-❓ Need technical help?
-➡️ Post in the <#946196407285604382> channel and other humans will assist you.
-❓ Looking for the Code of Conduct?
-➡️ Here it is: <https://opensource.facebook.com/code-of-conduct>
-❓ Something wrong?
-➡️ You can flag an admin with @admin
-I hope that resolves your issue!
-— HelpBot 🤖
-";
-
-#[command]
-pub async fn help(ctx: &Context, msg: &Message, _: Args) -> CommandResult {
-    msg.channel_id.say(&ctx.http, HELP_MESSAGE).await?;
+use crate::{
+    db::guild_config::GuildConfig,
+    embeds::make_blank_embed,
+    error::BotError,
+    extentions::ClientContextExt,
+    utils::{OwnersContainer, PrefixContainer},
+    GROUPS,
+};
+
+/// Who asks for help and where, decides the prefix shown and which commands are listed
+pub struct HelpScope {
+    prefix: String,
+    config: Option<GuildConfig>,
+    is_owner: bool,
+}
+
+impl HelpScope {
+    pub async fn new(ctx: &Context, user_id: UserId, guild_id: Option<GuildId>) -> Result<Self> {
+        let config = ctx.get_guild_config(guild_id).await?;
+        let data = ctx.data.read().await;
+        let prefix = config
+            .as_ref()
+            .and_then(|config| config.prefix.clone())
+            .or_else(|| data.get::<PrefixContainer>().cloned())
+            .unwrap_or_default();
+        let is_owner = matches!(
+            data.get::<OwnersContainer>(),
+            Some(owners) if owners.contains(&user_id)
+        );
+        Ok(HelpScope {
+            prefix,
+            config,
+            is_owner,
+        })
+    }
+
+    /// Hidden commands, owner commands for everyone else and commands the guild turned off are
+    /// left out
+    fn shows(&self, command: &Command) -> bool {
+        let options = command.options;
+        options.help_available
+            && (!options.owners_only || self.is_owner)
+            && !matches!(&self.config, Some(config) if !config.is_enabled(options.names[0]))
+    }
+
+    fn find(&self, name: &str) -> Option<&'static Command> {
+        let name = name.trim_start_matches(self.prefix.as_str()).to_lowercase();
+        GROUPS
+            .iter()
+            .filter(|group| group.options.help_available)
+            .flat_map(|group| group.options.commands.iter().copied())
+            .filter(|command| self.shows(command))
+            .find(|command| command.options.names.contains(&name.as_str()))
+    }
+
+    /// Every command the user can use, by group
+    pub async fn overview_embed(&self) -> CreateEmbed {
+        make_blank_embed(|e| {
+            e.title("📖 Commands").description(format!(
+                "Use `{}help <command>` to see how a command works",
+                self.prefix
+            ));
+            for group in GROUPS.iter().filter(|group| group.options.help_available) {
+                let commands = group
+                    .options
+                    .commands
+                    .iter()
+                    .filter(|command| self.shows(command))
+                    .map(|command| {
+                        format!(
+                            "`{}{}` {}",
+                            self.prefix,
+                            command.options.names[0],
+                            command.options.desc.unwrap_or_default()
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                if !commands.is_empty() {
+                    e.field(group_title(group), commands.join("\n"), false);
+                }
+            }
+            e
+        })
+        .await
+    }
+
+    /// How to use the command called `name`, `None` if there is no such command for the user
+    pub async fn command_embed(&self, name: &str) -> Option<CreateEmbed> {
+        let options = self.find(name)?.options;
+        let embed = make_blank_embed(|e| {
+            e.title(format!("📖 {}{}", self.prefix, options.names[0]));
+            if let Some(desc) = options.desc {
+                e.description(desc);
+            }
+            if let Some(usage) = options.usage {
+                e.field("Usage", format!("`{}{}`", self.prefix, usage), false);
+            }
+            if !options.examples.is_empty() {
+                let examples = options
+                    .examples
+                    .iter()
+                    .map(|example| format!("`{}{}`", self.prefix, example))
+                    .collect::<Vec<_>>();
+                e.field("Examples", examples.join("\n"), false);
+            }
+            if options.names.len() > 1 {
+                let aliases = options.names[1..]
+                    .iter()
+                    .map(|alias| format!("`{}{}`", self.prefix, alias))
+                    .collect::<Vec<_>>();
+                e.field("Aliases", aliases.join(", "), false);
+            }
+            let mut limits = vec![];
+            if options.only_in == OnlyIn::Guild {
+                limits.push("Only works in servers".to_owned());
+            }
+            if options.only_in == OnlyIn::Dm {
+                limits.push("Only works in DMs".to_owned());
+            }
+            if !options.required_permissions.is_empty() {
+                limits.push(format!(
+                    "Needs the {} permission",
+                    options
+                        .required_permissions
+                        .get_permission_names()
+                        .join(", ")
+                ));
+            }
+            if !limits.is_empty() {
+                e.field("Limits", limits.join("\n"), false);
+            }
+            e
+        })
+        .await;
+        Some(embed)
+    }
+
+    pub fn unknown_command(&self, name: &str) -> BotError {
+        BotError::Validation(format!(
+            "I don't know a `{}` command, `{}help` lists them all",
+            name, self.prefix
+        ))
+    }
+}
+
+fn group_title(group: &CommandGroup) -> String {
+    match group.options.description {
+        Some(description) => format!("{} · {}", group.name, description),
+        None => group.name.to_owned(),
+    }
+}
+
+#[command("help")]
+#[description("List the commands, or show how one works")]
+#[usage("help [command]")]
+#[example("help anime")]
+pub async fn help(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let scope = HelpScope::new(ctx, msg.author.id, msg.guild_id).await?;
+    let embed = match args.trimmed().current() {
+        None => scope.overview_embed().await,
+        Some(name) => match scope.command_embed(name).await {
+            Some(embed) => embed,
+            None => return Err(scope.unknown_command(name).into()),
+        },
+    };
+    msg.channel_id
+        .send_message(&ctx.http, |m| m.set_embed(embed))
+        .await?;
 
     Ok(())
 }
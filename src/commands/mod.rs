@@ -1,2 +1,6 @@
 pub mod anime;
+pub mod components;
+pub mod config;
 pub mod help;
+pub mod slash;
+pub mod watchlist;
@@ -0,0 +1,428 @@
+use std::env;
+
+use serenity::{
+    builder::{CreateApplicationCommands, CreateComponents},
+    framework::standard::CommandResult,
+    json::Value,
+    model::{
+        id::GuildId,
+        interactions::{
+            application_command::{
+                ApplicationCommand, ApplicationCommandInteraction,
+                ApplicationCommandInteractionDataOption,
+                ApplicationCommandInteractionDataOptionValue as OptionValue,
+                ApplicationCommandOptionType,
+            },
+            autocomplete::AutocompleteInteraction,
+            InteractionResponseType,
+        },
+    },
+    prelude::Context,
+};
+use tracing::info;
+
+use crate::{
+    commands::{anime::card::MediaCard, help::HelpScope, watchlist},
+    db::watchlist::{WatchListFilter, WatchListSort},
+    embeds::make_error_embed,
+    error::BotError,
+    extentions::ClientContextExt,
+    media::MediaType,
+    metadata::MediaSearch,
+    paginator::{MediaPaginator, SuggestionPaginator, WatchListPaginator},
+    query::MediaQuery,
+};
+
+/// Discord caps choice names and values at 100 characters
+const MAX_CHOICE_LENGTH: usize = 100;
+const AUTOCOMPLETE_PER_PAGE: i64 = 10;
+
+/// Registers the slash commands for the `DISCORD_GUILD_ID` test guild if set, globally otherwise
+pub async fn register(ctx: &Context) -> CommandResult {
+    match env::var("DISCORD_GUILD_ID") {
+        Ok(guild_id) => {
+            let guild_id = GuildId(guild_id.parse()?);
+            guild_id
+                .set_application_commands(&ctx.http, create_commands)
+                .await?;
+            info!("Registered slash commands for guild {}", guild_id);
+        }
+        Err(_) => {
+            ApplicationCommand::set_global_application_commands(&ctx.http, create_commands).await?;
+            info!("Registered global slash commands");
+        }
+    }
+
+    Ok(())
+}
+
+fn create_commands(commands: &mut CreateApplicationCommands) -> &mut CreateApplicationCommands {
+    commands
+        .create_application_command(|c| {
+            c.name("anime")
+                .description("Look up an anime")
+                .create_option(|o| {
+                    o.name("title")
+                        .description("Anime name, AniList id or url, or mal:<id>, filters like year:2023 genre:fantasy")
+                        .kind(ApplicationCommandOptionType::String)
+                        .required(true)
+                        .set_autocomplete(true)
+                })
+        })
+        .create_application_command(|c| {
+            c.name("manga")
+                .description("Look up a manga or light novel")
+                .create_option(|o| {
+                    o.name("title")
+                        .description("Manga or novel name, AniList id or url, or mal:<id>, filters like genre:romance")
+                        .kind(ApplicationCommandOptionType::String)
+                        .required(true)
+                        .set_autocomplete(true)
+                })
+        })
+        .create_application_command(|c| {
+            c.name("help")
+                .description("List the commands, or show how one works")
+                .create_option(|o| {
+                    o.name("command")
+                        .description("The command to explain")
+                        .kind(ApplicationCommandOptionType::String)
+                })
+        })
+        .create_application_command(|c| {
+            c.name("list")
+                .description("Show a watch list")
+                .create_option(|o| {
+                    o.name("user")
+                        .description("Whose list to show, yours by default")
+                        .kind(ApplicationCommandOptionType::User)
+                })
+                .create_option(|o| {
+                    o.name("filter")
+                        .description("Which entries to show")
+                        .kind(ApplicationCommandOptionType::String)
+                        .add_string_choice("All", "all")
+                        .add_string_choice("Planning", "planning")
+                        .add_string_choice("Watching / Reading", "consuming")
+                        .add_string_choice("Finished", "finished")
+                        .add_string_choice("Paused", "paused")
+                        .add_string_choice("Dropped", "dropped")
+                        .add_string_choice("Rewatching / Rereading", "repeating")
+                        .add_string_choice("Suggested", "suggested")
+                })
+                .create_option(|o| {
+                    o.name("sort")
+                        .description("How to order the entries")
+                        .kind(ApplicationCommandOptionType::String)
+                        .add_string_choice("Recently updated", "updated")
+                        .add_string_choice("Recently added", "created")
+                        .add_string_choice("Personal rating", "rating")
+                })
+        })
+        .create_application_command(|c| {
+            c.name("progress")
+                .description("Set your episode or chapter progress")
+                .create_option(|o| {
+                    o.name("title")
+                        .description("Anime or manga name")
+                        .kind(ApplicationCommandOptionType::String)
+                        .required(true)
+                        .set_autocomplete(true)
+                })
+                .create_option(|o| {
+                    o.name("progress")
+                        .description("Episode or chapter you are on")
+                        .kind(ApplicationCommandOptionType::Integer)
+                        .required(true)
+                        .min_int_value(0)
+                })
+                .create_option(|o| {
+                    o.name("type")
+                        .description("Anime by default")
+                        .kind(ApplicationCommandOptionType::String)
+                        .add_string_choice("Anime", "anime")
+                        .add_string_choice("Manga", "manga")
+                })
+                .create_option(|o| {
+                    o.name("volume")
+                        .description("Volume you are on, manga only")
+                        .kind(ApplicationCommandOptionType::Integer)
+                        .min_int_value(0)
+                })
+        })
+        .create_application_command(|c| {
+            c.name("suggestions")
+                .description("Rank what the members of this server suggest")
+        })
+}
+
+fn find_option<'a>(
+    options: &'a [ApplicationCommandInteractionDataOption],
+    name: &str,
+) -> Option<&'a OptionValue> {
+    options
+        .iter()
+        .find(|option| option.name == name)
+        .and_then(|option| option.resolved.as_ref())
+}
+
+fn string_option<'a>(
+    options: &'a [ApplicationCommandInteractionDataOption],
+    name: &str,
+) -> Option<&'a str> {
+    match find_option(options, name) {
+        Some(OptionValue::String(value)) => Some(value),
+        _ => None,
+    }
+}
+
+fn integer_option(options: &[ApplicationCommandInteractionDataOption], name: &str) -> Option<i64> {
+    match find_option(options, name) {
+        Some(OptionValue::Integer(value)) => Some(*value),
+        _ => None,
+    }
+}
+
+fn media_type_option(options: &[ApplicationCommandInteractionDataOption]) -> MediaType {
+    let media_type = options
+        .iter()
+        .find(|option| option.name == "type")
+        .and_then(|option| option.value.as_ref())
+        .and_then(Value::as_str);
+    match media_type {
+        Some("manga") => MediaType::Manga,
+        _ => MediaType::Anime,
+    }
+}
+
+/// Runs the invoked slash command
+pub async fn handle_command(
+    ctx: &Context,
+    command: ApplicationCommandInteraction,
+) -> CommandResult {
+    info!(
+        "Got slash command '{}' by user '{}'",
+        command.data.name,
+        command.user.tag()
+    );
+    let options = &command.data.options;
+
+    // commands the guild turned off are refused
+    let config = ctx.get_guild_config(command.guild_id).await?;
+    if matches!(&config, Some(config) if !config.is_enabled(&command.data.name)) {
+        let embed = make_error_embed(|e| e.description("This command is turned off here")).await;
+        command
+            .create_interaction_response(&ctx.http, |resp| {
+                resp.kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|data| data.ephemeral(true).set_embed(embed))
+            })
+            .await?;
+        return Ok(());
+    }
+
+    match command.data.name.as_str() {
+        "anime" | "manga" => {
+            let media_type = match command.data.name.as_str() {
+                "manga" => MediaType::Manga,
+                _ => MediaType::Anime,
+            };
+            let search = string_option(options, "title").unwrap_or_default();
+
+            command.defer(&ctx.http).await?;
+            let mut query = MediaQuery::parse(search, media_type).map_err(BotError::from)?;
+            if matches!(&config, Some(config) if config.hides_adult()) {
+                query.hide_adult();
+            }
+            let card = match MediaCard::new(ctx, command.user.id, search, query).await? {
+                Some(card) => card,
+                None => {
+                    command
+                        .edit_original_interaction_response(&ctx.http, |r| {
+                            r.content(MediaCard::not_found_message(&media_type))
+                        })
+                        .await?;
+                    return Ok(());
+                }
+            };
+
+            // send media card
+            command
+                .edit_original_interaction_response(&ctx.http, |r| {
+                    r.set_embed(card.embed()).components(|c| {
+                        *c = card.components();
+                        c
+                    })
+                })
+                .await?;
+
+            Ok(())
+        }
+        "help" => {
+            let scope = HelpScope::new(ctx, command.user.id, command.guild_id).await?;
+            let embed = match string_option(options, "command") {
+                None => scope.overview_embed().await,
+                Some(name) => match scope.command_embed(name).await {
+                    Some(embed) => embed,
+                    None => return Err(scope.unknown_command(name).into()),
+                },
+            };
+            command
+                .create_interaction_response(&ctx.http, |resp| {
+                    resp.kind(InteractionResponseType::ChannelMessageWithSource)
+                        .interaction_response_data(|data| data.set_embed(embed))
+                })
+                .await?;
+
+            Ok(())
+        }
+        "list" => {
+            let user = match find_option(options, "user") {
+                Some(OptionValue::User(user, _)) => user.clone(),
+                _ => command.user.clone(),
+            };
+            let filter = string_option(options, "filter")
+                .and_then(|filter| filter.parse::<WatchListFilter>().ok())
+                .unwrap_or(WatchListFilter::All);
+            let sort = string_option(options, "sort")
+                .and_then(|sort| sort.parse::<WatchListSort>().ok())
+                .unwrap_or(WatchListSort::UpdatedAt);
+
+            command.defer(&ctx.http).await?;
+
+            // query watch list page 1
+            let watch_info_repo = ctx.get_watch_list().await;
+            let watch_list_paginator = WatchListPaginator::new(
+                ctx.get_metadata().await,
+                watch_info_repo,
+                user,
+                filter,
+                sort,
+                0,
+            )
+            .await?;
+
+            // send watch list
+            command
+                .edit_original_interaction_response(&ctx.http, |r| {
+                    r.set_embed(watch_list_paginator.embed()).components(|c| {
+                        *c = CreateComponents::default();
+                        c.add_action_row(watch_list_paginator.action_row())
+                    })
+                })
+                .await?;
+
+            Ok(())
+        }
+        "progress" => {
+            let media_name = string_option(options, "title").unwrap_or_default();
+            let progress = integer_option(options, "progress").unwrap_or_default();
+            let volumes = integer_option(options, "volume");
+
+            command.defer(&ctx.http).await?;
+            let embed = watchlist::progress::update_progress(
+                ctx,
+                command.user.id,
+                media_type_option(options),
+                progress,
+                volumes,
+                media_name,
+            )
+            .await?;
+            command
+                .edit_original_interaction_response(&ctx.http, |r| r.set_embed(embed))
+                .await?;
+
+            Ok(())
+        }
+        "suggestions" => {
+            let guild_id = command.guild_id.ok_or_else(|| {
+                BotError::Validation("Suggestions only work in servers".to_owned())
+            })?;
+
+            command.defer(&ctx.http).await?;
+
+            // rank suggestions page 1
+            let watch_list = ctx.get_watch_list().await;
+            let suggestions = ctx
+                .get_suggestion_cache()
+                .await
+                .suggestions(ctx, watch_list.as_ref(), guild_id)
+                .await?;
+            let guild_name = guild_id
+                .name(ctx)
+                .unwrap_or_else(|| "this server".to_owned());
+            let suggestion_paginator =
+                SuggestionPaginator::new(ctx.get_metadata().await, guild_name, suggestions, 0)
+                    .await?;
+
+            // send suggestions
+            command
+                .edit_original_interaction_response(&ctx.http, |r| {
+                    r.set_embed(suggestion_paginator.embed()).components(|c| {
+                        *c = CreateComponents::default();
+                        c.add_action_row(suggestion_paginator.action_row())
+                    })
+                })
+                .await?;
+
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Suggests titles from AniList for the focused `title` option
+pub async fn handle_autocomplete(
+    ctx: &Context,
+    autocomplete: AutocompleteInteraction,
+) -> CommandResult {
+    let options = &autocomplete.data.options;
+    let search = options
+        .iter()
+        .find(|option| option.focused && option.name == "title")
+        .and_then(|option| option.value.as_ref())
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+
+    // nothing worth searching for yet
+    if search.trim().is_empty() {
+        return Ok(autocomplete
+            .create_autocomplete_response(&ctx.http, |r| r)
+            .await?);
+    }
+
+    let media_type = match autocomplete.data.name.as_str() {
+        "manga" => MediaType::Manga,
+        "progress" => media_type_option(options),
+        _ => MediaType::Anime,
+    };
+    let config = ctx.get_guild_config(autocomplete.guild_id).await?;
+    let media_paginator = MediaPaginator::new(
+        ctx.get_metadata().await,
+        MediaSearch {
+            search: Some(search.to_owned()),
+            media_type,
+            per_page: AUTOCOMPLETE_PER_PAGE,
+            is_adult: config.filter(|config| config.hides_adult()).map(|_| false),
+            ..MediaSearch::default()
+        },
+    )
+    .await?;
+
+    autocomplete
+        .create_autocomplete_response(&ctx.http, |r| {
+            for media in media_paginator.results() {
+                let title = media
+                    .title
+                    .chars()
+                    .take(MAX_CHOICE_LENGTH)
+                    .collect::<String>();
+                // the id looks up the exact media, titles would be searched again
+                r.add_string_choice(&title, media.id.to_string());
+            }
+            r
+        })
+        .await?;
+
+    Ok(())
+}
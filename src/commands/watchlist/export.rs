@@ -0,0 +1,95 @@
+use std::borrow::Cow;
+
+use serenity::{
+    framework::standard::{macros::command, Args, CommandResult},
+    model::channel::{AttachmentType, Message},
+    prelude::Context,
+};
+
+use crate::{
+    error::BotError,
+    export::{export as export_watch_list, ExportFormat},
+    extentions::ClientContextExt,
+};
+
+#[command("export")]
+#[description(
+    "DM you your whole watch list as a file, `json` keeps everything, `csv` is for spreadsheets \
+    and `xml` imports into MyAnimeList or AniList"
+)]
+#[usage("export [json|csv|xml]")]
+#[example("export csv")]
+#[example("export xml")]
+pub async fn export(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let format = match args.trimmed().current() {
+        None => ExportFormat::Json,
+        Some(format) => format.parse::<ExportFormat>().map_err(|_| {
+            BotError::Validation(format!(
+                "I can export `json`, `csv` or `xml`, I don't know `{}`",
+                format
+            ))
+        })?,
+    };
+    let entries = ctx
+        .get_watch_list()
+        .await
+        .find_all(msg.author.id)
+        .await
+        .map_err(BotError::Storage)?;
+    if entries.is_empty() {
+        return Err(BotError::Validation(
+            "Your watch list is empty, there is nothing to export yet".to_owned(),
+        )
+        .into());
+    }
+
+    let count = entries.len();
+    let export = export_watch_list(
+        ctx.get_metadata().await.as_ref(),
+        entries,
+        format,
+        "mokuroku-watch-list",
+    )
+    .await?;
+    if export.files.is_empty() {
+        return Err(BotError::Validation(
+            "Nothing on your watch list is on MyAnimeList, try `json` or `csv`".to_owned(),
+        )
+        .into());
+    }
+
+    let mut content = format!("📦 Your watch list, {} entries", count);
+    if export.left_out > 0 {
+        content.push_str(&format!(
+            "\n{} entries are not on MyAnimeList and were left out",
+            export.left_out
+        ));
+    }
+    let files = export
+        .files
+        .iter()
+        .map(|file| AttachmentType::Bytes {
+            data: Cow::Borrowed(&file.data),
+            filename: file.filename.clone(),
+        })
+        .collect::<Vec<_>>();
+    let sent = match msg.author.create_dm_channel(ctx).await {
+        Ok(channel) => channel
+            .send_files(&ctx.http, files, |m| m.content(content))
+            .await
+            .is_ok(),
+        Err(_) => false,
+    };
+    if !sent {
+        return Err(BotError::Validation(
+            "I could not DM you, allow direct messages from server members and try again"
+                .to_owned(),
+        )
+        .into());
+    }
+    if msg.guild_id.is_some() {
+        msg.reply(&ctx.http, "📬 Sent it to your DMs").await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,156 @@
+use mongodm::prelude::BsonDateTime;
+use serenity::{
+    builder::CreateEmbed,
+    framework::standard::{macros::command, Args, CommandResult},
+    model::channel::Message,
+    prelude::Context,
+};
+
+use crate::{
+    embeds::make_success_embed,
+    error::BotError,
+    extentions::ClientContextExt,
+    mal::{parse_export, plan_import, resolve_ids, ImportChange, ImportPlan},
+};
+
+/// MAL exports of even the longest lists stay well under this
+const MAX_EXPORT_SIZE: u64 = 8 * 1024 * 1024;
+/// Changes and unresolved titles listed in the summary, the rest are only counted
+const SHOWN_ENTRIES: usize = 10;
+const TITLE_LENGTH: usize = 50;
+
+#[command("import")]
+#[description(
+    "Import your watch list from a MyAnimeList export attached to the message, `dry` only shows \
+    what would change"
+)]
+#[usage("import mal [dry]")]
+#[example("import mal dry")]
+#[example("import mal")]
+pub async fn import(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    args.trimmed();
+    let source = args.single::<String>().unwrap_or_default().to_lowercase();
+    if source != "mal" {
+        return Err(BotError::Validation(
+            "I can import MyAnimeList exports, attach yours to `import mal`".to_owned(),
+        )
+        .into());
+    }
+    let dry_run = match args.rest().trim().to_lowercase().as_str() {
+        "" => false,
+        "dry" | "dry-run" | "--dry-run" => true,
+        other => {
+            return Err(BotError::Validation(format!(
+                "I don't know what `{}` means, only `dry` goes after `import mal`",
+                other
+            ))
+            .into())
+        }
+    };
+    let attachment = msg.attachments.first().ok_or_else(|| {
+        BotError::Validation(
+            "Attach your export from https://myanimelist.net/panel.php?go=export to the message"
+                .to_owned(),
+        )
+    })?;
+    if attachment.size > MAX_EXPORT_SIZE {
+        return Err(BotError::Validation(format!(
+            "That file is too big for an export, they are under {} MB",
+            MAX_EXPORT_SIZE / 1024 / 1024
+        ))
+        .into());
+    }
+
+    let file = attachment.download().await?;
+    let export = parse_export(&file).map_err(BotError::from)?;
+    let ids = resolve_ids(ctx.get_anilist().await.as_ref(), &export.entries).await?;
+    let watch_list = ctx.get_watch_list().await;
+    let existing = watch_list
+        .find_all(msg.author.id)
+        .await
+        .map_err(BotError::Storage)?;
+    let plan = plan_import(export, &ids, existing, msg.author.id, BsonDateTime::now());
+
+    let embed = import_embed(&plan, dry_run).await;
+    if !dry_run {
+        watch_list
+            .save_entries(plan.into_entries())
+            .await
+            .map_err(BotError::Storage)?;
+    }
+    msg.channel_id
+        .send_message(&ctx.http, |m| m.reference_message(msg).set_embed(embed))
+        .await?;
+
+    Ok(())
+}
+
+async fn import_embed(plan: &ImportPlan, dry_run: bool) -> CreateEmbed {
+    make_success_embed(|e| {
+        if dry_run {
+            e.title("📥 MyAnimeList import, dry run").description(
+                "Nothing is saved yet, import again without `dry` to save these changes",
+            );
+        } else {
+            e.title("📥 MyAnimeList import");
+        }
+        e.field("Imported", plan.imported(), true)
+            .field("Updated", plan.updated(), true)
+            .field("Skipped", plan.skipped, true)
+            .field("Unresolved", plan.unresolved.len(), true);
+        if !plan.changes.is_empty() {
+            e.field(
+                "Changes",
+                listing(plan.changes.iter().map(change_line), plan.changes.len()),
+                false,
+            );
+        }
+        if !plan.unresolved.is_empty() {
+            e.field(
+                "Not on AniList",
+                listing(
+                    plan.unresolved
+                        .iter()
+                        .map(|entry| format!("{} `mal:{}`", shorten(&entry.title), entry.mal_id)),
+                    plan.unresolved.len(),
+                ),
+                false,
+            );
+        }
+        e
+    })
+    .await
+}
+
+/// The first `SHOWN_ENTRIES` lines, and how many more there are
+fn listing(lines: impl Iterator<Item = String>, total: usize) -> String {
+    let mut listing = lines.take(SHOWN_ENTRIES).collect::<Vec<_>>();
+    if total > SHOWN_ENTRIES {
+        listing.push(format!("…and {} more", total - SHOWN_ENTRIES));
+    }
+    listing.join("\n")
+}
+
+fn change_line(change: &ImportChange) -> String {
+    let after = &change.after;
+    let status = after.watch_status.as_label(&after.media_type);
+    match &change.before {
+        None => format!("➕ {} · {}", shorten(&change.title), status),
+        Some(before) if before.watch_status != after.watch_status => format!(
+            "✏️ {} · {} → {}",
+            shorten(&change.title),
+            before.watch_status.as_label(&before.media_type),
+            status
+        ),
+        Some(_) => format!("✏️ {} · {}", shorten(&change.title), status),
+    }
+}
+
+fn shorten(title: &str) -> String {
+    if title.chars().count() <= TITLE_LENGTH {
+        return title.to_owned();
+    }
+    let mut shortened = title.chars().take(TITLE_LENGTH - 1).collect::<String>();
+    shortened.push('…');
+    shortened
+}
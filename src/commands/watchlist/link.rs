@@ -0,0 +1,141 @@
+use mongodm::ToRepository;
+use serenity::{
+    framework::standard::{macros::command, Args, CommandResult},
+    model::channel::Message,
+    prelude::Context,
+};
+use tracing::warn;
+
+use crate::{
+    anilist::AniListError,
+    db::anilist_link::{AniListLink, AniListLinkCollectionExt},
+    embeds::make_success_embed,
+    error::BotError,
+    extentions::ClientContextExt,
+    graphql::{viewer, Viewer},
+};
+
+#[command("link")]
+#[description(
+    "Link your AniList account, your watch list is kept in sync with your AniList lists after. \
+    Send the token in a DM, `off` unlinks"
+)]
+#[usage("link [token|off]")]
+#[example("link")]
+#[example("link off")]
+pub async fn link(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let links = ctx.get_db().await?.repository::<AniListLink>();
+    let anilist = ctx.get_anilist().await;
+    let token = match args.trimmed().current() {
+        None => {
+            let authorize_url = anilist.authorize_url().ok_or_else(|| {
+                BotError::Validation("Linking AniList accounts is off on this bot".to_owned())
+            })?;
+            let link = links.find_link(msg.author.id).await?;
+            let embed = make_success_embed(|e| {
+                e.title("🔗 AniList");
+                match &link {
+                    Some(link) => e.description(format!(
+                        "Linked to **{}**, changes go both ways. Last pulled {}\n\
+                        Unlink with `link off`",
+                        link.anilist_name,
+                        link.pulled_at.map_or_else(
+                            || "never".to_owned(),
+                            |at| format!("<t:{}:R>", at.timestamp_millis() / 1000)
+                        )
+                    )),
+                    None => e.description(format!(
+                        "Not linked. [Authorize Mokuroku on AniList]({}), then DM me \
+                        `link <token>` with the token it shows you",
+                        authorize_url
+                    )),
+                }
+            })
+            .await;
+            msg.channel_id
+                .send_message(&ctx.http, |m| m.reference_message(msg).set_embed(embed))
+                .await?;
+            return Ok(());
+        }
+        Some(arg) if arg.eq_ignore_ascii_case("off") => {
+            let description = if links.delete_link(msg.author.id).await? {
+                "Unlinked, your watch list is not synced with AniList anymore"
+            } else {
+                "Your AniList account is not linked"
+            };
+            let embed =
+                make_success_embed(|e| e.title("🔗 AniList").description(description)).await;
+            msg.channel_id
+                .send_message(&ctx.http, |m| m.reference_message(msg).set_embed(embed))
+                .await?;
+            return Ok(());
+        }
+        Some(token) => token.to_owned(),
+    };
+
+    // everyone in the channel saw the token, it has to go
+    if msg.guild_id.is_some() {
+        if let Err(why) = msg.delete(&ctx.http).await {
+            warn!(
+                "Could not delete a message with an AniList token: {:?}",
+                why
+            );
+        }
+        return Err(BotError::Validation(
+            "Never post your token in a server! Revoke it at https://anilist.co/settings/apps \
+            and DM me a new one"
+                .to_owned(),
+        )
+        .into());
+    }
+
+    let viewer = anilist
+        .query_as::<Viewer>(viewer::Variables {}, &token)
+        .await
+        .and_then(|response| match response.errors {
+            Some(errors) => Err(AniListError::from_graphql(&errors)),
+            None => response
+                .data
+                .and_then(|data| data.viewer)
+                .ok_or(AniListError::NotFound),
+        });
+    let viewer = match viewer {
+        Ok(viewer) => viewer,
+        Err(AniListError::Unauthorized) => {
+            return Err(BotError::Validation(
+                "AniList did not accept that token, get a new one with `link`".to_owned(),
+            )
+            .into())
+        }
+        Err(why) => return Err(BotError::from(why).into()),
+    };
+
+    let link = links
+        .save_link(msg.author.id, viewer.id, &viewer.name, &token)
+        .await?;
+
+    // the first pull can take a while for long lists
+    let sync = ctx.get_anilist_sync().await?;
+    let pulled_link = link.clone();
+    tokio::spawn(async move {
+        if let Err(why) = sync.pull(&pulled_link).await {
+            warn!(
+                "Could not sync the AniList list of {}: {:?}",
+                pulled_link.discord_user_id, why
+            );
+        }
+    });
+
+    let embed = make_success_embed(|e| {
+        e.title("🔗 AniList").description(format!(
+            "Linked to **{}**! Your lists are being synced, changes go both ways from now on",
+            link.anilist_name
+        ))
+    })
+    .await;
+    msg.channel_id
+        .send_message(&ctx.http, |m| m.set_embed(embed))
+        .await?;
+
+    Ok(())
+}
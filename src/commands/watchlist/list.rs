@@ -0,0 +1,113 @@
+use serenity::{
+    framework::standard::{macros::command, Args, CommandResult},
+    model::{
+        channel::Message,
+        id::UserId,
+        interactions::{message_component::MessageComponentInteraction, InteractionResponseType},
+    },
+    prelude::Context,
+    utils::parse_username,
+};
+
+use crate::{
+    custom_id::{ListAction, ListState},
+    db::watchlist::{WatchListFilter, WatchListSort},
+    extentions::{ClientContextExt, MessageComponentInteractionExt},
+    paginator::WatchListPaginator,
+};
+
+#[command("list")]
+#[description("Show your watch list or someone else's, filtered by status")]
+#[usage(
+    "list [@user] [all|planning|watching|finished|paused|dropped|rewatching|suggested] \
+    [updated|created|rating]"
+)]
+#[example("list watching")]
+#[example("list @friend finished rating")]
+pub async fn list(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let (mut user_id, mut filter, mut sort) = (
+        msg.author.id,
+        WatchListFilter::All,
+        WatchListSort::UpdatedAt,
+    );
+
+    // validate arguments
+    for arg in args.trimmed().iter::<String>() {
+        let arg = arg?;
+        if let Some(id) = parse_username(&arg) {
+            user_id = UserId(id);
+        } else if let Ok(f) = arg.parse::<WatchListFilter>() {
+            filter = f;
+        } else if let Ok(s) = arg.parse::<WatchListSort>() {
+            sort = s;
+        } else {
+            msg.reply(&ctx.http, format!("I don't know what `{}` means...", arg))
+                .await?;
+            return Ok(());
+        }
+    }
+    let user = user_id.to_user(&ctx).await?;
+
+    // query watch list page 1
+    let watch_info_repo = ctx.get_watch_list().await;
+    let watch_list_paginator = WatchListPaginator::new(
+        ctx.get_metadata().await,
+        watch_info_repo,
+        user,
+        filter,
+        sort,
+        0,
+    )
+    .await?;
+
+    // send watch list, its buttons are handled by the interaction dispatcher
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.set_embed(watch_list_paginator.embed())
+                .components(|c| c.add_action_row(watch_list_paginator.action_row()))
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Handles the navigation buttons on a posted watch list
+pub async fn handle_component(
+    ctx: &Context,
+    interaction: &MessageComponentInteraction,
+    action: ListAction,
+    state: ListState,
+) -> CommandResult {
+    let page = match action {
+        ListAction::PrevPage => state.page.saturating_sub(1),
+        ListAction::NextPage => state.page + 1,
+    };
+    let user = state.user.to_user(ctx).await?;
+    let watch_info_repo = ctx.get_watch_list().await;
+    let watch_list_paginator = WatchListPaginator::new(
+        ctx.get_metadata().await,
+        watch_info_repo,
+        user,
+        state.filter,
+        state.sort,
+        page,
+    )
+    .await?;
+
+    // Update message on page change
+    if watch_list_paginator.state() != state {
+        interaction
+            .create_interaction_response(&ctx, |resp| {
+                resp.kind(InteractionResponseType::UpdateMessage)
+                    .interaction_response_data(|data| {
+                        data.set_embed(watch_list_paginator.embed())
+                            .components(|c| c.add_action_row(watch_list_paginator.action_row()))
+                    })
+            })
+            .await?;
+    } else {
+        interaction.ack(ctx).await?;
+    }
+
+    Ok(())
+}
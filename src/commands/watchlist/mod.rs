@@ -0,0 +1,7 @@
+pub mod export;
+pub mod import;
+pub mod link;
+pub mod list;
+pub mod notify;
+pub mod progress;
+pub mod suggestions;
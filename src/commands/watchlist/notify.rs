@@ -0,0 +1,92 @@
+use mongodm::ToRepository;
+use serenity::{
+    framework::standard::{macros::command, Args, CommandResult},
+    model::channel::Message,
+    prelude::Context,
+};
+
+use crate::{
+    db::notifications::{NotificationSettings, NotificationSettingsCollectionExt},
+    embeds::make_success_embed,
+    extentions::ClientContextExt,
+};
+
+#[command("notify")]
+#[description("Get told when a new episode of an anime you are watching airs")]
+#[usage("notify [on|off|quiet <start hour> <end hour>|quiet off]")]
+#[example("notify on")]
+#[example("notify quiet 23 7")]
+pub async fn notify(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let settings_repo = ctx.get_db().await?.repository::<NotificationSettings>();
+    args.trimmed();
+
+    // validate arguments
+    let settings = match args.current().map(str::to_lowercase).as_deref() {
+        None => settings_repo.find_settings(msg.author.id).await?,
+        Some("on") => {
+            settings_repo
+                .set_enabled(msg.author.id, true, msg.guild_id)
+                .await?
+        }
+        Some("off") => {
+            settings_repo
+                .set_enabled(msg.author.id, false, msg.guild_id)
+                .await?
+        }
+        Some("quiet") => {
+            args.advance();
+            if args.current().map(str::to_lowercase).as_deref() == Some("off") {
+                settings_repo.set_quiet_hours(msg.author.id, None).await?
+            } else {
+                match (args.single::<u8>(), args.single::<u8>()) {
+                    (Ok(start), Ok(end)) if start < 24 && end < 24 => {
+                        settings_repo
+                            .set_quiet_hours(msg.author.id, Some((start, end)))
+                            .await?
+                    }
+                    _ => {
+                        msg.reply(
+                            &ctx.http,
+                            "Quiet hours are two UTC hours from 0 to 23, like `quiet 23 7`...",
+                        )
+                        .await?;
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        Some(arg) => {
+            msg.reply(&ctx.http, format!("I don't know what `{}` means...", arg))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let enabled = matches!(&settings, Some(settings) if settings.enabled);
+    let quiet_hours = settings
+        .as_ref()
+        .and_then(|settings| settings.quiet_start.zip(settings.quiet_end));
+    let embed = make_success_embed(|e| {
+        e.title("🔔 Airing notifications").description(format!(
+            "{}\n{}",
+            if enabled {
+                "You get a message when an episode of an anime you are watching airs"
+            } else {
+                "You don't get airing messages, turn them on with `notify on`"
+            },
+            match quiet_hours {
+                Some((start, end)) => format!(
+                    "Quiet hours are {:02}:00 to {:02}:00 UTC, messages wait until they end",
+                    start, end
+                ),
+                None => "No quiet hours, set them with `notify quiet <start> <end>`".to_owned(),
+            }
+        ))
+    })
+    .await;
+    msg.channel_id
+        .send_message(&ctx.http, |m| m.set_embed(embed))
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,125 @@
+use anyhow::anyhow;
+use serenity::{
+    builder::CreateEmbed,
+    framework::standard::{macros::command, Args, CommandResult},
+    model::{channel::Message, id::UserId},
+    prelude::Context,
+};
+
+use crate::{
+    db::watchlist::WatchStatus,
+    embeds::{make_error_embed, make_success_embed},
+    error::{BotError, BotResult},
+    extentions::ClientContextExt,
+    media::MediaType,
+    metadata::{MediaLookup, MediaSearch},
+    paginator::MediaPaginator,
+};
+
+#[command("progress")]
+#[description("Set the episode or chapter you are on")]
+#[usage("progress [anime|manga] <episode|chapter> [v<volume>] <name>")]
+#[example("progress 12 frieren")]
+#[example("progress manga 120 v14 berserk")]
+#[min_args(2)]
+pub async fn progress(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    args.trimmed().quoted();
+
+    // validate arguments
+    let media_type = match args.current().map(str::to_lowercase).as_deref() {
+        Some("manga") => Some(MediaType::Manga),
+        Some("anime") => Some(MediaType::Anime),
+        _ => None,
+    };
+    if media_type.is_some() {
+        args.advance();
+    }
+    let progress = match args.single::<i64>() {
+        Ok(progress) if progress >= 0 => progress,
+        _ => {
+            msg.reply(&ctx.http, "The progress must be a number of 0 or more...")
+                .await?;
+            return Ok(());
+        }
+    };
+    let volumes = args
+        .current()
+        .and_then(|arg| arg.strip_prefix('v'))
+        .and_then(|volumes| volumes.parse::<i64>().ok());
+    if volumes.is_some() {
+        args.advance();
+    }
+    let media_name = args.rest();
+    if media_name.is_empty() {
+        msg.reply(&ctx.http, "Which one are you on?").await?;
+        return Ok(());
+    }
+
+    let embed = update_progress(
+        ctx,
+        msg.author.id,
+        media_type.unwrap_or(MediaType::Anime),
+        progress,
+        volumes,
+        media_name,
+    )
+    .await?;
+    msg.channel_id
+        .send_message(&ctx.http, |m| m.set_embed(embed))
+        .await?;
+
+    Ok(())
+}
+
+/// Sets the progress on the first search result for `media_name`, returns the reply embed
+pub async fn update_progress(
+    ctx: &Context,
+    user_id: UserId,
+    media_type: MediaType,
+    progress: i64,
+    volumes: Option<i64>,
+    media_name: &str,
+) -> BotResult<CreateEmbed> {
+    // autocompleted titles come in as AniList ids, otherwise the first search result is the
+    // media to update
+    let metadata = ctx.get_metadata().await;
+    let media = match MediaLookup::parse(media_name, media_type) {
+        Some(lookup) => metadata.media(&lookup).await?,
+        None => MediaPaginator::new(
+            metadata,
+            MediaSearch {
+                search: Some(media_name.to_owned()),
+                media_type,
+                ..MediaSearch::default()
+            },
+        )
+        .await?
+        .current_page(),
+    };
+    let media = match media {
+        Some(media) => media,
+        None => return Ok(make_error_embed(|e| e.description("Cannot find anything...")).await),
+    };
+
+    let info = ctx
+        .get_watch_list()
+        .await
+        .set_progress(&media, user_id, progress, volumes)
+        .await
+        .map_err(BotError::Storage)?
+        .ok_or_else(|| BotError::Storage(anyhow!("Progress was not saved")))?;
+    let reply = match info.watch_status {
+        WatchStatus::Finished => format!(
+            "You {} _it_ {}",
+            info.watch_status.as_verb(&info.media_type),
+            info.watch_status.as_emoji(&info.media_type)
+        ),
+        _ => format!(
+            "You are on {} **{}** {}",
+            info.progress_unit(),
+            info.progress_text(media.total_progress()),
+            info.watch_status.as_emoji(&info.media_type)
+        ),
+    };
+    Ok(make_success_embed(|e| e.title(&media.title).description(reply)).await)
+}
@@ -0,0 +1,90 @@
+use serenity::{
+    builder::CreateComponents,
+    framework::standard::{macros::command, CommandResult},
+    model::{channel::Message, interactions::message_component::MessageComponentInteraction},
+    prelude::Context,
+};
+
+use crate::{
+    custom_id::ListAction,
+    extentions::{ClientContextExt, MessageComponentInteractionExt},
+    paginator::SuggestionPaginator,
+};
+
+#[command("suggestions")]
+#[description("Rank what the members of this server suggest, and how many finished it")]
+#[only_in(guilds)]
+#[usage("suggestions")]
+#[example("suggestions")]
+pub async fn suggestions(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+    let watch_list = ctx.get_watch_list().await;
+    let suggestions = ctx
+        .get_suggestion_cache()
+        .await
+        .suggestions(ctx, watch_list.as_ref(), guild_id)
+        .await?;
+    let guild_name = guild_id
+        .name(ctx)
+        .unwrap_or_else(|| "this server".to_owned());
+    let suggestion_paginator =
+        SuggestionPaginator::new(ctx.get_metadata().await, guild_name, suggestions, 0).await?;
+
+    // send suggestions, its buttons are handled by the interaction dispatcher
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.set_embed(suggestion_paginator.embed())
+                .components(|c| c.add_action_row(suggestion_paginator.action_row()))
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Handles the navigation buttons on posted suggestions
+pub async fn handle_component(
+    ctx: &Context,
+    interaction: &MessageComponentInteraction,
+    action: ListAction,
+    page: u64,
+) -> CommandResult {
+    let guild_id = match interaction.guild_id {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+    let next_page = match action {
+        ListAction::PrevPage => page.saturating_sub(1),
+        ListAction::NextPage => page + 1,
+    };
+    // ranking may list the guild's members, Discord only waits 3 seconds for an answer
+    interaction.ack(ctx).await?;
+    let watch_list = ctx.get_watch_list().await;
+    let suggestions = ctx
+        .get_suggestion_cache()
+        .await
+        .suggestions(ctx, watch_list.as_ref(), guild_id)
+        .await?;
+    let guild_name = guild_id
+        .name(ctx)
+        .unwrap_or_else(|| "this server".to_owned());
+    let suggestion_paginator =
+        SuggestionPaginator::new(ctx.get_metadata().await, guild_name, suggestions, next_page)
+            .await?;
+
+    // Update message on page change
+    if suggestion_paginator.page() != page {
+        interaction
+            .edit_original_interaction_response(&ctx.http, |r| {
+                r.set_embed(suggestion_paginator.embed()).components(|c| {
+                    *c = CreateComponents::default();
+                    c.add_action_row(suggestion_paginator.action_row())
+                })
+            })
+            .await?;
+    }
+
+    Ok(())
+}
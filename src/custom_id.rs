@@ -0,0 +1,205 @@
+use std::{fmt::Display, str::FromStr};
+
+use serenity::model::id::UserId;
+
+use crate::{
+    db::watchlist::{WatchListFilter, WatchListSort},
+    media::MediaType,
+};
+
+/// Bump when the layout of a state changes, older components then get a polite error
+const CARD_VERSION: &str = "c1";
+const LIST_VERSION: &str = "l1";
+const SUGGESTIONS_VERSION: &str = "s1";
+
+/// What a component on a media card does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CardAction {
+    PrevPage,
+    NextPage,
+    JumpTo,
+    Results,
+    Suggest,
+    ProgressInc,
+    ProgressDec,
+    Rate,
+    RateModal,
+    Status,
+}
+
+impl CardAction {
+    const ALL: [CardAction; 10] = [
+        CardAction::PrevPage,
+        CardAction::NextPage,
+        CardAction::JumpTo,
+        CardAction::Results,
+        CardAction::Suggest,
+        CardAction::ProgressInc,
+        CardAction::ProgressDec,
+        CardAction::Rate,
+        CardAction::RateModal,
+        CardAction::Status,
+    ];
+
+    fn as_code(&self) -> &'static str {
+        match self {
+            Self::PrevPage => "prev",
+            Self::NextPage => "next",
+            Self::JumpTo => "jump",
+            Self::Results => "results",
+            Self::Suggest => "suggest",
+            Self::ProgressInc => "inc",
+            Self::ProgressDec => "dec",
+            Self::Rate => "rate",
+            Self::RateModal => "rated",
+            Self::Status => "status",
+        }
+    }
+
+    /// Flipping through a card is left to whoever looked it up
+    pub fn is_navigation(&self) -> bool {
+        matches!(
+            self,
+            Self::PrevPage | Self::NextPage | Self::JumpTo | Self::Results
+        )
+    }
+}
+
+/// Everything needed to rebuild a media card, the search itself is kept in the card's footer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CardState {
+    pub owner: UserId,
+    pub media_id: i64,
+    pub media_type: MediaType,
+    pub page: i64,
+    pub index: usize,
+    pub show_results: bool,
+}
+
+/// What a component on a watch list or the suggestions does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListAction {
+    PrevPage,
+    NextPage,
+}
+
+/// Everything needed to rebuild a watch list page
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListState {
+    pub user: UserId,
+    pub filter: WatchListFilter,
+    pub sort: WatchListSort,
+    pub page: u64,
+}
+
+/// A component custom id, e.g. `c1:next:<owner>:<media id>:a:<page>:<index>:0`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ComponentId {
+    Card(CardAction, CardState),
+    List(ListAction, ListState),
+    /// The guild's suggestions, on a page
+    Suggestions(ListAction, u64),
+}
+
+impl Display for ComponentId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Card(action, state) => write!(
+                f,
+                "{}:{}:{}:{}:{}:{}:{}:{}",
+                CARD_VERSION,
+                action.as_code(),
+                state.owner,
+                state.media_id,
+                match state.media_type {
+                    MediaType::Manga => "m",
+                    _ => "a",
+                },
+                state.page,
+                state.index,
+                state.show_results as u8
+            ),
+            Self::List(action, state) => write!(
+                f,
+                "{}:{}:{}:{}:{}:{}",
+                LIST_VERSION,
+                match action {
+                    ListAction::PrevPage => "prev",
+                    ListAction::NextPage => "next",
+                },
+                state.user,
+                state.filter.as_key(),
+                state.sort.as_key(),
+                state.page
+            ),
+            Self::Suggestions(action, page) => write!(
+                f,
+                "{}:{}:{}",
+                SUGGESTIONS_VERSION,
+                match action {
+                    ListAction::PrevPage => "prev",
+                    ListAction::NextPage => "next",
+                },
+                page
+            ),
+        }
+    }
+}
+
+impl FromStr for ComponentId {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s.split(':').collect::<Vec<_>>();
+        match parts.as_slice() {
+            [CARD_VERSION, action, owner, media_id, media_type, page, index, show_results] => {
+                let action = CardAction::ALL
+                    .iter()
+                    .find(|known| known.as_code() == *action)
+                    .copied()
+                    .ok_or(())?;
+                let media_type = match *media_type {
+                    "m" => MediaType::Manga,
+                    "a" => MediaType::Anime,
+                    _ => return Err(()),
+                };
+                Ok(Self::Card(
+                    action,
+                    CardState {
+                        owner: UserId(owner.parse().map_err(|_| ())?),
+                        media_id: media_id.parse().map_err(|_| ())?,
+                        media_type,
+                        page: page.parse().map_err(|_| ())?,
+                        index: index.parse().map_err(|_| ())?,
+                        show_results: *show_results == "1",
+                    },
+                ))
+            }
+            [LIST_VERSION, action, user, filter, sort, page] => {
+                let action = match *action {
+                    "prev" => ListAction::PrevPage,
+                    "next" => ListAction::NextPage,
+                    _ => return Err(()),
+                };
+                Ok(Self::List(
+                    action,
+                    ListState {
+                        user: UserId(user.parse().map_err(|_| ())?),
+                        filter: filter.parse()?,
+                        sort: sort.parse()?,
+                        page: page.parse().map_err(|_| ())?,
+                    },
+                ))
+            }
+            [SUGGESTIONS_VERSION, action, page] => {
+                let action = match *action {
+                    "prev" => ListAction::PrevPage,
+                    "next" => ListAction::NextPage,
+                    _ => return Err(()),
+                };
+                Ok(Self::Suggestions(action, page.parse().map_err(|_| ())?))
+            }
+            _ => Err(()),
+        }
+    }
+}
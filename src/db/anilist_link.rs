@@ -0,0 +1,125 @@
+use anyhow::{Context, Result};
+use mongodm::{
+    doc,
+    prelude::{
+        to_bson, BsonDateTime, MongoCollection, MongoFindOneAndUpdateOptions, MongoReturnDocument,
+    },
+    CollectionConfig, Index, IndexOption, Indexes, Model,
+};
+use serde::{Deserialize, Serialize};
+use serenity::{async_trait, futures::TryStreamExt, model::id::UserId};
+
+pub struct AniListLinkCollConf;
+
+impl CollectionConfig for AniListLinkCollConf {
+    fn collection_name() -> &'static str {
+        "anilist-links"
+    }
+
+    fn indexes() -> Indexes {
+        Indexes::new().with(Index::new("discord_user_id").with_option(IndexOption::Unique))
+    }
+}
+
+/// An AniList account a user linked with `link`, their watch list is kept in sync with it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AniListLink {
+    pub discord_user_id: UserId,
+    pub anilist_user_id: i64,
+    pub anilist_name: String,
+    /// The implicit grant access token, AniList makes them last a year
+    pub token: String,
+    pub linked_at: BsonDateTime,
+    /// When the AniList list was last pulled, never if unset
+    pub pulled_at: Option<BsonDateTime>,
+}
+
+impl Model for AniListLink {
+    type CollConf = AniListLinkCollConf;
+}
+
+#[async_trait]
+pub trait AniListLinkCollectionExt {
+    async fn find_link(&self, user_id: UserId) -> Result<Option<AniListLink>>;
+    async fn find_links(&self) -> Result<Vec<AniListLink>>;
+    async fn save_link(
+        &self,
+        user_id: UserId,
+        anilist_user_id: i64,
+        anilist_name: &str,
+        token: &str,
+    ) -> Result<AniListLink>;
+    /// Whether there was a link to delete
+    async fn delete_link(&self, user_id: UserId) -> Result<bool>;
+    async fn set_pulled_at(&self, user_id: UserId, pulled_at: BsonDateTime) -> Result<()>;
+}
+
+#[async_trait]
+impl AniListLinkCollectionExt for MongoCollection<AniListLink> {
+    async fn find_link(&self, user_id: UserId) -> Result<Option<AniListLink>> {
+        self.find_one(
+            doc! {"discord_user_id": to_bson(user_id.as_u64()).unwrap()},
+            None,
+        )
+        .await
+        .context("Failed to find AniList link")
+    }
+
+    async fn find_links(&self) -> Result<Vec<AniListLink>> {
+        self.find(doc! {}, None)
+            .await
+            .context("Failed to find AniList links")?
+            .try_collect()
+            .await
+            .context("Failed to read AniList links")
+    }
+
+    async fn save_link(
+        &self,
+        user_id: UserId,
+        anilist_user_id: i64,
+        anilist_name: &str,
+        token: &str,
+    ) -> Result<AniListLink> {
+        let options = MongoFindOneAndUpdateOptions::builder()
+            .upsert(true)
+            .return_document(MongoReturnDocument::After)
+            .build();
+        self.find_one_and_update(
+            doc! {"discord_user_id": to_bson(user_id.as_u64()).unwrap()},
+            doc! {"$set": {
+                "anilist_user_id": anilist_user_id,
+                "anilist_name": anilist_name,
+                "token": token,
+                "linked_at": BsonDateTime::now(),
+                "pulled_at": null,
+            }},
+            Some(options),
+        )
+        .await
+        .context("Failed to save AniList link")?
+        .context("Saved AniList link is missing")
+    }
+
+    async fn delete_link(&self, user_id: UserId) -> Result<bool> {
+        let result = self
+            .delete_one(
+                doc! {"discord_user_id": to_bson(user_id.as_u64()).unwrap()},
+                None,
+            )
+            .await
+            .context("Failed to delete AniList link")?;
+        Ok(result.deleted_count > 0)
+    }
+
+    async fn set_pulled_at(&self, user_id: UserId, pulled_at: BsonDateTime) -> Result<()> {
+        self.update_one(
+            doc! {"discord_user_id": to_bson(user_id.as_u64()).unwrap()},
+            doc! {"$set": {"pulled_at": pulled_at}},
+            None,
+        )
+        .await
+        .context("Failed to save AniList pull time")?;
+        Ok(())
+    }
+}
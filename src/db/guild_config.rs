@@ -0,0 +1,243 @@
+use std::{fmt::Display, str::FromStr};
+
+use anyhow::{Context, Result};
+use mongodm::{
+    doc,
+    prelude::{
+        to_bson, Bson, BsonDateTime, BsonDocument, MongoCollection, MongoFindOneAndUpdateOptions,
+        MongoReturnDocument,
+    },
+    CollectionConfig, Index, IndexOption, Indexes, Model,
+};
+use serde::{Deserialize, Serialize};
+use serenity::{
+    async_trait,
+    futures::TryStreamExt,
+    model::id::{ChannelId, GuildId, MessageId},
+};
+
+/// Commands a guild can turn off, `config` itself always stays on
+pub const TOGGLEABLE_COMMANDS: &[&str] = &[
+    "anime",
+    "manga",
+    "list",
+    "progress",
+    "notify",
+    "suggestions",
+    "import",
+    "export",
+    "link",
+    "help",
+];
+
+pub struct GuildConfigCollConf;
+
+impl CollectionConfig for GuildConfigCollConf {
+    fn collection_name() -> &'static str {
+        "guild-configs"
+    }
+
+    fn indexes() -> Indexes {
+        Indexes::new().with(Index::new("discord_guild_id").with_option(IndexOption::Unique))
+    }
+}
+
+/// Whether lookups in a guild may show adult media
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AdultContent {
+    Allow,
+    Hide,
+}
+
+// `#[default]` on variants needs a newer compiler than the one this builds with
+#[allow(clippy::derivable_impls)]
+impl Default for AdultContent {
+    fn default() -> Self {
+        Self::Allow
+    }
+}
+
+impl FromStr for AdultContent {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "allow" | "show" | "yes" | "on" => Ok(Self::Allow),
+            "hide" | "no" | "off" => Ok(Self::Hide),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Display for AdultContent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Allow => write!(f, "Allowed"),
+            Self::Hide => write!(f, "Hidden"),
+        }
+    }
+}
+
+/// Settings a guild's moderators change with `config`, a guild without a document uses the
+/// defaults
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct GuildConfig {
+    pub discord_guild_id: GuildId,
+    /// Used next to the `BOT_PREFIX` one, which keeps working so a bad prefix can be undone
+    pub prefix: Option<String>,
+    /// Where airing announcements for users who opted in from this guild go, DMs if unset
+    pub announcement_channel: Option<ChannelId>,
+    /// Language tag like `en` or `pt-BR`, kept for when replies are translated
+    pub locale: Option<String>,
+    #[serde(default)]
+    pub adult_content: AdultContent,
+    /// Cards and lists stop reacting to their components after this many minutes
+    pub card_timeout: Option<u32>,
+    #[serde(default)]
+    pub disabled_commands: Vec<String>,
+    /// Where the pinned suggestion board is kept up to date, no board if unset
+    pub suggestion_channel: Option<ChannelId>,
+    /// The board message in `suggestion_channel`, posted again when it is gone
+    pub suggestion_message: Option<MessageId>,
+    pub updated_at: BsonDateTime,
+}
+
+impl Model for GuildConfig {
+    type CollConf = GuildConfigCollConf;
+}
+
+impl GuildConfig {
+    pub fn is_enabled(&self, command_name: &str) -> bool {
+        !self
+            .disabled_commands
+            .iter()
+            .any(|disabled| disabled == command_name)
+    }
+
+    pub fn hides_adult(&self) -> bool {
+        self.adult_content == AdultContent::Hide
+    }
+
+    /// Whether a component on a message posted at `posted_at` (unix seconds) timed out at `now`
+    pub fn is_timed_out(&self, posted_at: i64, now: i64) -> bool {
+        matches!(self.card_timeout, Some(minutes) if now - posted_at > i64::from(minutes) * 60)
+    }
+}
+
+/// One change made with `config`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GuildConfigUpdate {
+    Prefix(Option<String>),
+    AnnouncementChannel(Option<ChannelId>),
+    Locale(Option<String>),
+    AdultContent(AdultContent),
+    CardTimeout(Option<u32>),
+    Command { name: String, enabled: bool },
+    SuggestionChannel(Option<ChannelId>),
+}
+
+impl GuildConfigUpdate {
+    fn as_document(&self) -> BsonDocument {
+        let now = BsonDateTime::now();
+        match self {
+            Self::Prefix(prefix) => doc! { "$set": { "prefix": prefix, "updated_at": now } },
+            // ids are stored as numbers like in the watch list
+            Self::AnnouncementChannel(channel_id) => doc! { "$set": {
+                "announcement_channel": channel_id.map_or(Bson::Null, |id| to_bson(id.as_u64()).unwrap()),
+                "updated_at": now,
+            } },
+            Self::Locale(locale) => doc! { "$set": { "locale": locale, "updated_at": now } },
+            Self::AdultContent(adult_content) => doc! { "$set": {
+                "adult_content": to_bson(adult_content).unwrap(),
+                "updated_at": now,
+            } },
+            Self::CardTimeout(minutes) => doc! { "$set": {
+                "card_timeout": minutes.map(i64::from),
+                "updated_at": now,
+            } },
+            // a board in a new channel is a new message
+            Self::SuggestionChannel(channel_id) => doc! { "$set": {
+                "suggestion_channel": channel_id.map_or(Bson::Null, |id| to_bson(id.as_u64()).unwrap()),
+                "suggestion_message": Bson::Null,
+                "updated_at": now,
+            } },
+            Self::Command {
+                name,
+                enabled: true,
+            } => doc! {
+                "$pull": { "disabled_commands": name },
+                "$set": { "updated_at": now },
+            },
+            Self::Command {
+                name,
+                enabled: false,
+            } => doc! {
+                "$addToSet": { "disabled_commands": name },
+                "$set": { "updated_at": now },
+            },
+        }
+    }
+}
+
+#[async_trait]
+pub trait GuildConfigCollectionExt {
+    async fn find_config(&self, guild_id: GuildId) -> Result<Option<GuildConfig>>;
+    async fn update_config(
+        &self,
+        guild_id: GuildId,
+        update: &GuildConfigUpdate,
+    ) -> Result<Option<GuildConfig>>;
+    async fn find_suggestion_boards(&self) -> Result<Vec<GuildConfig>>;
+    async fn set_suggestion_message(&self, guild_id: GuildId, message_id: MessageId) -> Result<()>;
+}
+
+#[async_trait]
+impl GuildConfigCollectionExt for MongoCollection<GuildConfig> {
+    async fn find_config(&self, guild_id: GuildId) -> Result<Option<GuildConfig>> {
+        self.find_one(
+            doc! {"discord_guild_id": to_bson(guild_id.as_u64()).unwrap()},
+            None,
+        )
+        .await
+        .context("Failed to find guild config")
+    }
+
+    async fn update_config(
+        &self,
+        guild_id: GuildId,
+        update: &GuildConfigUpdate,
+    ) -> Result<Option<GuildConfig>> {
+        let options = MongoFindOneAndUpdateOptions::builder()
+            .upsert(true)
+            .return_document(MongoReturnDocument::After)
+            .build();
+        self.find_one_and_update(
+            doc! {"discord_guild_id": to_bson(guild_id.as_u64()).unwrap()},
+            update.as_document(),
+            Some(options),
+        )
+        .await
+        .context("Failed to update guild config")
+    }
+
+    async fn find_suggestion_boards(&self) -> Result<Vec<GuildConfig>> {
+        self.find(doc! {"suggestion_channel": { "$ne": Bson::Null }}, None)
+            .await
+            .context("Failed to find suggestion boards")?
+            .try_collect()
+            .await
+            .context("Failed to read suggestion boards")
+    }
+
+    async fn set_suggestion_message(&self, guild_id: GuildId, message_id: MessageId) -> Result<()> {
+        self.update_one(
+            doc! {"discord_guild_id": to_bson(guild_id.as_u64()).unwrap()},
+            doc! {"$set": {"suggestion_message": to_bson(message_id.as_u64()).unwrap()}},
+            None,
+        )
+        .await
+        .context("Failed to save suggestion board message")?;
+        Ok(())
+    }
+}
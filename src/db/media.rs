@@ -0,0 +1,163 @@
+use anyhow::{Context, Result};
+use mongodm::{
+    doc,
+    prelude::{BsonDateTime, MongoCollection, MongoFindOptions, MongoReplaceOptions},
+    CollectionConfig, Index, IndexOption, Indexes, Model,
+};
+use serde::{Deserialize, Serialize};
+use serenity::{async_trait, futures::TryStreamExt};
+
+use crate::media::{Media, MediaStatus};
+
+const HOUR_MILLIS: i64 = 60 * 60 * 1000;
+const DAY_MILLIS: i64 = 24 * HOUR_MILLIS;
+/// Media nobody listed or looked up for this long are dropped instead of refreshed
+const UNREAD_MILLIS: i64 = 14 * DAY_MILLIS;
+
+pub struct CachedMediaCollConf;
+
+impl CollectionConfig for CachedMediaCollConf {
+    fn collection_name() -> &'static str {
+        "media"
+    }
+
+    fn indexes() -> Indexes {
+        Indexes::new()
+            .with(Index::new("anilist_media_id").with_option(IndexOption::Unique))
+            .with(Index::new("refresh_after"))
+    }
+}
+
+/// A media as the metadata providers last described it, so watch lists can be shown without
+/// asking them
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CachedMedia {
+    pub anilist_media_id: i64,
+    pub media: Media,
+    pub fetched_at: BsonDateTime,
+    /// When the refresh job fetches the media again
+    pub refresh_after: BsonDateTime,
+    /// When a lookup was last answered with the media
+    pub read_at: BsonDateTime,
+}
+
+impl Model for CachedMedia {
+    type CollConf = CachedMediaCollConf;
+}
+
+impl CachedMedia {
+    pub fn new(media: Media, fetched_at: BsonDateTime) -> Self {
+        let refresh_after = Self::refresh_after(&media, fetched_at);
+        CachedMedia {
+            anilist_media_id: media.id,
+            media,
+            fetched_at,
+            refresh_after,
+            read_at: fetched_at,
+        }
+    }
+
+    /// Media on a watch list are kept fresh, the others only while they are looked up
+    pub fn worth_refreshing(&self, listed: bool, now: BsonDateTime) -> bool {
+        listed || self.read_at.timestamp_millis() + UNREAD_MILLIS > now.timestamp_millis()
+    }
+
+    /// Airing anime are refreshed once their next episode aired, finished media hardly change
+    pub fn refresh_after(media: &Media, fetched_at: BsonDateTime) -> BsonDateTime {
+        let now = fetched_at.timestamp_millis();
+        let refresh_after = match media.status {
+            Some(MediaStatus::Releasing) => match media.next_airing {
+                // the episode count is bumped a little after the episode airs
+                Some(next) => (next.airing_at * 1000 + HOUR_MILLIS)
+                    .max(now + HOUR_MILLIS)
+                    .min(now + DAY_MILLIS),
+                None => now + DAY_MILLIS,
+            },
+            Some(MediaStatus::NotYetReleased) => now + DAY_MILLIS,
+            Some(MediaStatus::Hiatus) | None => now + 7 * DAY_MILLIS,
+            Some(MediaStatus::Finished) | Some(MediaStatus::Cancelled) => now + 30 * DAY_MILLIS,
+        };
+        BsonDateTime::from_millis(refresh_after)
+    }
+}
+
+#[async_trait]
+pub trait CachedMediaCollectionExt {
+    /// The cached media of `media_ids`, stale or not
+    async fn find_cached_media(&self, media_ids: &[i64]) -> Result<Vec<CachedMedia>>;
+    /// The `limit` media that are due for a refresh the longest
+    async fn find_stale_media(&self, now: BsonDateTime, limit: i64) -> Result<Vec<CachedMedia>>;
+    async fn save_media(&self, media: Vec<CachedMedia>) -> Result<()>;
+    async fn mark_read(&self, media_ids: &[i64], read_at: BsonDateTime) -> Result<()>;
+    async fn delete_media(&self, media_ids: &[i64]) -> Result<()>;
+    /// Keeps media the providers no longer know from being refreshed until `refresh_after`
+    async fn postpone_refresh(&self, media_ids: &[i64], refresh_after: BsonDateTime) -> Result<()>;
+}
+
+#[async_trait]
+impl CachedMediaCollectionExt for MongoCollection<CachedMedia> {
+    async fn find_cached_media(&self, media_ids: &[i64]) -> Result<Vec<CachedMedia>> {
+        self.find(doc! { "anilist_media_id": { "$in": media_ids } }, None)
+            .await
+            .context("Failed to find cached media")?
+            .try_collect()
+            .await
+            .context("Failed to read cached media")
+    }
+
+    async fn find_stale_media(&self, now: BsonDateTime, limit: i64) -> Result<Vec<CachedMedia>> {
+        let options = MongoFindOptions::builder()
+            .sort(doc! { "refresh_after": 1 })
+            .limit(limit)
+            .build();
+        self.find(doc! { "refresh_after": { "$lte": now } }, Some(options))
+            .await
+            .context("Failed to find stale media")?
+            .try_collect()
+            .await
+            .context("Failed to read stale media")
+    }
+
+    async fn save_media(&self, media: Vec<CachedMedia>) -> Result<()> {
+        let options = MongoReplaceOptions::builder().upsert(true).build();
+        for cached in media {
+            self.replace_one(
+                doc! { "anilist_media_id": cached.anilist_media_id },
+                cached,
+                Some(options.clone()),
+            )
+            .await
+            .context("Failed to save cached media")?;
+        }
+        Ok(())
+    }
+
+    async fn mark_read(&self, media_ids: &[i64], read_at: BsonDateTime) -> Result<()> {
+        self.update_many(
+            doc! { "anilist_media_id": { "$in": media_ids } },
+            doc! { "$set": { "read_at": read_at } },
+            None,
+        )
+        .await
+        .context("Failed to mark cached media read")?;
+        Ok(())
+    }
+
+    async fn delete_media(&self, media_ids: &[i64]) -> Result<()> {
+        self.delete_many(doc! { "anilist_media_id": { "$in": media_ids } }, None)
+            .await
+            .context("Failed to delete cached media")?;
+        Ok(())
+    }
+
+    async fn postpone_refresh(&self, media_ids: &[i64], refresh_after: BsonDateTime) -> Result<()> {
+        self.update_many(
+            doc! { "anilist_media_id": { "$in": media_ids } },
+            doc! { "$set": { "refresh_after": refresh_after } },
+            None,
+        )
+        .await
+        .context("Failed to postpone media refresh")?;
+        Ok(())
+    }
+}
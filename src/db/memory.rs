@@ -0,0 +1,284 @@
+use std::{cmp::Reverse, sync::Mutex};
+
+use anyhow::{anyhow, ensure, Result};
+use mongodm::prelude::BsonDateTime;
+use serenity::{async_trait, model::id::UserId};
+
+use crate::{
+    db::watchlist::{
+        EntryUpdate, WatchInfo, WatchListFilter, WatchListSort, WatchListStore, WatchStatus,
+        MAX_RATING,
+    },
+    media::{Media, MediaType},
+};
+
+/// Keeps watch lists in memory with the same semantics as the MongoDB update pipelines, for tests
+/// and running without a database. Entries are kept in insertion order, standing in for `_id`.
+#[derive(Default)]
+pub struct MemoryWatchListStore {
+    entries: Mutex<Vec<WatchInfo>>,
+}
+
+impl MemoryWatchListStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies `update` to the entry of `user_id` for `media`, creating it first if needed
+    fn upsert(
+        &self,
+        media: &Media,
+        user_id: UserId,
+        update: EntryUpdate,
+    ) -> Result<Option<WatchInfo>> {
+        let mut entries = self
+            .entries
+            .lock()
+            .map_err(|_| anyhow!("Watch list store is poisoned"))?;
+        let now = BsonDateTime::now();
+        let position = entries
+            .iter()
+            .position(|info| info.anilist_media_id == media.id && info.discord_user_id == user_id);
+        let position = match position {
+            Some(position) => position,
+            None => {
+                entries.push(WatchInfo::new(media.id, user_id, media.media_type, now));
+                entries.len() - 1
+            }
+        };
+
+        let info = &mut entries[position];
+        info.apply(&update, media.total_progress(), now);
+        Ok(Some(info.clone()))
+    }
+}
+
+#[async_trait]
+impl WatchListStore for MemoryWatchListStore {
+    async fn toggle_suggestion(&self, media: &Media, user_id: UserId) -> Result<Option<WatchInfo>> {
+        self.upsert(media, user_id, EntryUpdate::ToggleSuggestion)
+    }
+
+    async fn toggle_finish(&self, media: &Media, user_id: UserId) -> Result<Option<WatchInfo>> {
+        self.upsert(
+            media,
+            user_id,
+            EntryUpdate::ToggleStatus(WatchStatus::Finished),
+        )
+    }
+
+    async fn toggle_consuming(&self, media: &Media, user_id: UserId) -> Result<Option<WatchInfo>> {
+        self.upsert(
+            media,
+            user_id,
+            EntryUpdate::ToggleStatus(WatchStatus::Consuming),
+        )
+    }
+
+    async fn set_status(
+        &self,
+        media: &Media,
+        user_id: UserId,
+        status: WatchStatus,
+    ) -> Result<Option<WatchInfo>> {
+        self.upsert(media, user_id, EntryUpdate::SetStatus(status))
+    }
+
+    async fn set_rating(
+        &self,
+        media: &Media,
+        user_id: UserId,
+        rating: u8,
+    ) -> Result<Option<WatchInfo>> {
+        ensure!(
+            (1..=MAX_RATING).contains(&rating),
+            "Rating {} is out of range",
+            rating
+        );
+        self.upsert(media, user_id, EntryUpdate::SetRating(rating))
+    }
+
+    async fn set_progress(
+        &self,
+        media: &Media,
+        user_id: UserId,
+        progress: i64,
+        volumes: Option<i64>,
+    ) -> Result<Option<WatchInfo>> {
+        self.upsert(media, user_id, EntryUpdate::SetProgress(progress, volumes))
+    }
+
+    async fn add_progress(
+        &self,
+        media: &Media,
+        user_id: UserId,
+        amount: i64,
+    ) -> Result<Option<WatchInfo>> {
+        self.upsert(media, user_id, EntryUpdate::AddProgress(amount))
+    }
+
+    async fn find_watch_info(&self, media_id: i64, user_id: UserId) -> Result<Option<WatchInfo>> {
+        let entries = self
+            .entries
+            .lock()
+            .map_err(|_| anyhow!("Watch list store is poisoned"))?;
+        Ok(entries
+            .iter()
+            .find(|info| info.anilist_media_id == media_id && info.discord_user_id == user_id)
+            .cloned())
+    }
+
+    async fn count_watch_list(&self, user_id: UserId, filter: WatchListFilter) -> Result<u64> {
+        let entries = self
+            .entries
+            .lock()
+            .map_err(|_| anyhow!("Watch list store is poisoned"))?;
+        Ok(entries
+            .iter()
+            .filter(|info| info.discord_user_id == user_id && filter.matches(info))
+            .count() as u64)
+    }
+
+    async fn find_watch_list(
+        &self,
+        user_id: UserId,
+        filter: WatchListFilter,
+        sort: WatchListSort,
+        skip: u64,
+        limit: i64,
+    ) -> Result<Vec<WatchInfo>> {
+        let entries = self
+            .entries
+            .lock()
+            .map_err(|_| anyhow!("Watch list store is poisoned"))?;
+        // newest first, ties go to the entry added last like sorting on `_id`
+        let mut watch_list = entries
+            .iter()
+            .enumerate()
+            .filter(|(_, info)| info.discord_user_id == user_id && filter.matches(info))
+            .collect::<Vec<_>>();
+        watch_list.sort_by_key(|(position, info)| {
+            let (first, second) = match sort {
+                WatchListSort::UpdatedAt => (info.updated_at.timestamp_millis(), 0),
+                WatchListSort::CreatedAt => (info.created_at.timestamp_millis(), 0),
+                WatchListSort::Rating => (
+                    info.rating.map_or(-1, i64::from),
+                    info.updated_at.timestamp_millis(),
+                ),
+            };
+            Reverse((first, second, *position))
+        });
+        Ok(watch_list
+            .into_iter()
+            .map(|(_, info)| info.clone())
+            .skip(skip as usize)
+            // like MongoDB, no limit when it is 0
+            .take(match limit {
+                0 => usize::MAX,
+                limit => limit.unsigned_abs() as usize,
+            })
+            .collect())
+    }
+
+    async fn find_consuming(
+        &self,
+        media_type: MediaType,
+        user_ids: &[UserId],
+    ) -> Result<Vec<WatchInfo>> {
+        let entries = self
+            .entries
+            .lock()
+            .map_err(|_| anyhow!("Watch list store is poisoned"))?;
+        Ok(entries
+            .iter()
+            .filter(|info| {
+                info.media_type == media_type
+                    && info.watch_status == WatchStatus::Consuming
+                    && user_ids.contains(&info.discord_user_id)
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn find_suggested(&self) -> Result<Vec<WatchInfo>> {
+        let entries = self
+            .entries
+            .lock()
+            .map_err(|_| anyhow!("Watch list store is poisoned"))?;
+        Ok(entries
+            .iter()
+            .filter(|info| info.suggests)
+            .cloned()
+            .collect())
+    }
+
+    async fn find_finished(&self, media_ids: &[i64]) -> Result<Vec<WatchInfo>> {
+        let entries = self
+            .entries
+            .lock()
+            .map_err(|_| anyhow!("Watch list store is poisoned"))?;
+        Ok(entries
+            .iter()
+            .filter(|info| {
+                info.watch_status == WatchStatus::Finished
+                    && media_ids.contains(&info.anilist_media_id)
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn find_listed_media(&self, media_ids: &[i64]) -> Result<Vec<i64>> {
+        let entries = self
+            .entries
+            .lock()
+            .map_err(|_| anyhow!("Watch list store is poisoned"))?;
+        let mut listed = entries
+            .iter()
+            .filter(|info| {
+                (info.watch_status != WatchStatus::NotSeen || info.suggests)
+                    && media_ids.contains(&info.anilist_media_id)
+            })
+            .map(|info| info.anilist_media_id)
+            .collect::<Vec<_>>();
+        listed.sort_unstable();
+        listed.dedup();
+        Ok(listed)
+    }
+
+    async fn find_all(&self, user_id: UserId) -> Result<Vec<WatchInfo>> {
+        let entries = self
+            .entries
+            .lock()
+            .map_err(|_| anyhow!("Watch list store is poisoned"))?;
+        Ok(entries
+            .iter()
+            .filter(|info| info.discord_user_id == user_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn find_every_entry(&self) -> Result<Vec<WatchInfo>> {
+        let entries = self
+            .entries
+            .lock()
+            .map_err(|_| anyhow!("Watch list store is poisoned"))?;
+        Ok(entries.clone())
+    }
+
+    async fn save_entries(&self, new_entries: Vec<WatchInfo>) -> Result<()> {
+        let mut entries = self
+            .entries
+            .lock()
+            .map_err(|_| anyhow!("Watch list store is poisoned"))?;
+        for new_info in new_entries {
+            match entries.iter_mut().find(|info| {
+                info.anilist_media_id == new_info.anilist_media_id
+                    && info.discord_user_id == new_info.discord_user_id
+            }) {
+                Some(info) => *info = new_info,
+                None => entries.push(new_info),
+            }
+        }
+        Ok(())
+    }
+}
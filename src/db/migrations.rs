@@ -0,0 +1,107 @@
+use anyhow::{Context, Result};
+use mongodm::{
+    doc,
+    prelude::{BsonDateTime, MongoDatabase},
+    CollectionConfig, Index, IndexOption, Indexes, Model, ToRepository,
+};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::db::{media::CachedMedia, watchlist::WatchInfo};
+
+/// Every migration ever written, in the order they have to be applied
+const MIGRATIONS: &[&str] = &["0001_watch_info_statuses", "0002_cached_media_read_at"];
+
+pub struct MigrationCollConf;
+
+impl CollectionConfig for MigrationCollConf {
+    fn collection_name() -> &'static str {
+        "migrations"
+    }
+
+    fn indexes() -> Indexes {
+        Indexes::new().with(Index::new("name").with_option(IndexOption::Unique))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Migration {
+    pub name: String,
+    pub applied_at: BsonDateTime,
+}
+
+impl Model for Migration {
+    type CollConf = MigrationCollConf;
+}
+
+/// Applies every migration that has not been applied to the database yet
+pub async fn migrate(db: &MongoDatabase) -> Result<()> {
+    let migration_repo = db.repository::<Migration>();
+    for name in MIGRATIONS {
+        if migration_repo
+            .find_one(doc! { "name": name }, None)
+            .await
+            .context("Failed to find migration")?
+            .is_some()
+        {
+            continue;
+        }
+
+        info!("Applying migration {}", name);
+        apply(db, name).await?;
+        migration_repo
+            .insert_one(
+                Migration {
+                    name: name.to_string(),
+                    applied_at: BsonDateTime::now(),
+                },
+                None,
+            )
+            .await
+            .with_context(|| format!("Failed to record migration {}", name))?;
+    }
+    Ok(())
+}
+
+async fn apply(db: &MongoDatabase, name: &str) -> Result<()> {
+    match name {
+        // Fill the fields added for progress tracking and rewatches, and keep entries whose
+        // status was toggled off from restoring an unknown status
+        "0001_watch_info_statuses" => {
+            let watch_info_repo = db.repository::<WatchInfo>();
+            for field in ["progress", "progress_volumes", "repeat"] {
+                watch_info_repo
+                    .update_many(
+                        doc! { field: { "$exists": false } },
+                        doc! { "$set": { field: 0_i64 } },
+                        None,
+                    )
+                    .await
+                    .with_context(|| format!("Failed to fill {}", field))?;
+            }
+            watch_info_repo
+                .update_many(
+                    doc! { "last_watch_status": { "$exists": false } },
+                    doc! { "$set": { "last_watch_status": "NOT_SEEN" } },
+                    None,
+                )
+                .await
+                .context("Failed to fill last_watch_status")?;
+            Ok(())
+        }
+        // Media cached before reads were tracked count as read now, so they get a full
+        // window to be looked up before they are dropped
+        "0002_cached_media_read_at" => {
+            db.repository::<CachedMedia>()
+                .update_many(
+                    doc! { "read_at": { "$exists": false } },
+                    doc! { "$set": { "read_at": BsonDateTime::now() } },
+                    None,
+                )
+                .await
+                .context("Failed to fill read_at")?;
+            Ok(())
+        }
+        _ => unreachable!("Unknown migration {}", name),
+    }
+}
@@ -1 +1,9 @@
+pub mod anilist_link;
+pub mod guild_config;
+pub mod media;
+pub mod memory;
+pub mod migrations;
+pub mod notifications;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
 pub mod watchlist;
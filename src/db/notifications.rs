@@ -0,0 +1,259 @@
+use anyhow::{ensure, Context, Result};
+use mongodm::{
+    doc,
+    prelude::{
+        to_bson, BsonDateTime, MongoCollection, MongoFindOneAndUpdateOptions, MongoReturnDocument,
+        MongoUpdateOptions,
+    },
+    CollectionConfig, Index, IndexOption, Indexes, Model,
+};
+use serde::{Deserialize, Serialize};
+use serenity::{
+    async_trait,
+    futures::TryStreamExt,
+    model::id::{GuildId, UserId},
+};
+
+const HOUR_MILLIS: i64 = 60 * 60 * 1000;
+
+pub struct NotificationSettingsCollConf;
+
+impl CollectionConfig for NotificationSettingsCollConf {
+    fn collection_name() -> &'static str {
+        "notification-settings"
+    }
+
+    fn indexes() -> Indexes {
+        Indexes::new().with(Index::new("discord_user_id").with_option(IndexOption::Unique))
+    }
+}
+
+/// Whether a user gets airing announcements, users have to opt in
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct NotificationSettings {
+    pub discord_user_id: UserId,
+    pub enabled: bool,
+    /// Where announcements were turned on, they go to that guild's announcement channel
+    pub discord_guild_id: Option<GuildId>,
+    /// Announcements made from `quiet_start` until `quiet_end` (UTC hours) wait for `quiet_end`
+    pub quiet_start: Option<u8>,
+    pub quiet_end: Option<u8>,
+    pub updated_at: BsonDateTime,
+}
+
+impl Model for NotificationSettings {
+    type CollConf = NotificationSettingsCollConf;
+}
+
+impl NotificationSettings {
+    /// When an announcement made at `now` may be delivered
+    pub fn deliver_after(&self, now: BsonDateTime) -> BsonDateTime {
+        let (start, end) = match (self.quiet_start, self.quiet_end) {
+            (Some(start), Some(end)) if start != end => (i64::from(start), i64::from(end)),
+            _ => return now,
+        };
+
+        let now = now.timestamp_millis();
+        let hour = now / HOUR_MILLIS % 24;
+        // quiet hours may wrap around midnight, like 23 to 7
+        let quiet = if start < end {
+            start <= hour && hour < end
+        } else {
+            start <= hour || hour < end
+        };
+        if !quiet {
+            return BsonDateTime::from_millis(now);
+        }
+
+        let hours_left = (end - hour + 24) % 24;
+        BsonDateTime::from_millis(now - now % HOUR_MILLIS + hours_left * HOUR_MILLIS)
+    }
+}
+
+pub struct AnnouncementCollConf;
+
+impl CollectionConfig for AnnouncementCollConf {
+    fn collection_name() -> &'static str {
+        "announcements"
+    }
+
+    fn indexes() -> Indexes {
+        Indexes::new()
+            .with(
+                Index::new("airing_id")
+                    .with_key("discord_user_id")
+                    .with_option(IndexOption::Unique),
+            )
+            .with(Index::new("delivered_at").with_key("deliver_after"))
+    }
+}
+
+/// An aired episode to announce to one user, kept after delivery so it is never announced twice
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Announcement {
+    /// AniList's `AiringSchedule` id, one per episode
+    pub airing_id: i64,
+    pub anilist_media_id: i64,
+    pub episode: i64,
+    pub discord_user_id: UserId,
+    pub deliver_after: BsonDateTime,
+    pub delivered_at: Option<BsonDateTime>,
+    pub created_at: BsonDateTime,
+}
+
+impl Model for Announcement {
+    type CollConf = AnnouncementCollConf;
+}
+
+#[async_trait]
+pub trait NotificationSettingsCollectionExt {
+    async fn find_settings(&self, user_id: UserId) -> Result<Option<NotificationSettings>>;
+    async fn find_enabled(&self) -> Result<Vec<NotificationSettings>>;
+    async fn set_enabled(
+        &self,
+        user_id: UserId,
+        enabled: bool,
+        guild_id: Option<GuildId>,
+    ) -> Result<Option<NotificationSettings>>;
+    async fn set_quiet_hours(
+        &self,
+        user_id: UserId,
+        quiet_hours: Option<(u8, u8)>,
+    ) -> Result<Option<NotificationSettings>>;
+}
+
+#[async_trait]
+impl NotificationSettingsCollectionExt for MongoCollection<NotificationSettings> {
+    async fn find_settings(&self, user_id: UserId) -> Result<Option<NotificationSettings>> {
+        self.find_one(
+            doc! {"discord_user_id": to_bson(user_id.as_u64()).unwrap()},
+            None,
+        )
+        .await
+        .context("Failed to find notification settings")
+    }
+
+    async fn find_enabled(&self) -> Result<Vec<NotificationSettings>> {
+        self.find(doc! {"enabled": true}, None)
+            .await
+            .context("Failed to find notification settings")?
+            .try_collect()
+            .await
+            .context("Failed to read notification settings")
+    }
+
+    async fn set_enabled(
+        &self,
+        user_id: UserId,
+        enabled: bool,
+        guild_id: Option<GuildId>,
+    ) -> Result<Option<NotificationSettings>> {
+        let options = MongoFindOneAndUpdateOptions::builder()
+            .upsert(true)
+            .return_document(MongoReturnDocument::After)
+            .build();
+        self.find_one_and_update(
+            doc! {"discord_user_id": to_bson(user_id.as_u64()).unwrap()},
+            doc! {
+                "$set": {
+                    "enabled": enabled,
+                    "discord_guild_id": guild_id.map(|id| to_bson(id.as_u64()).unwrap()),
+                    "updated_at": BsonDateTime::now(),
+                },
+                "$setOnInsert": { "quiet_start": null, "quiet_end": null },
+            },
+            Some(options),
+        )
+        .await
+        .context("Failed to update notification settings")
+    }
+
+    async fn set_quiet_hours(
+        &self,
+        user_id: UserId,
+        quiet_hours: Option<(u8, u8)>,
+    ) -> Result<Option<NotificationSettings>> {
+        if let Some((start, end)) = quiet_hours {
+            ensure!(start < 24 && end < 24, "Quiet hours must be from 0 to 23");
+        }
+
+        let options = MongoFindOneAndUpdateOptions::builder()
+            .upsert(true)
+            .return_document(MongoReturnDocument::After)
+            .build();
+        self.find_one_and_update(
+            doc! {"discord_user_id": to_bson(user_id.as_u64()).unwrap()},
+            doc! {
+                "$set": {
+                    "quiet_start": quiet_hours.map(|(start, _)| i32::from(start)),
+                    "quiet_end": quiet_hours.map(|(_, end)| i32::from(end)),
+                    "updated_at": BsonDateTime::now(),
+                },
+                "$setOnInsert": { "enabled": false },
+            },
+            Some(options),
+        )
+        .await
+        .context("Failed to update notification settings")
+    }
+}
+
+#[async_trait]
+pub trait AnnouncementCollectionExt {
+    async fn queue_announcement(&self, announcement: &Announcement) -> Result<bool>;
+    async fn find_due_announcements(&self) -> Result<Vec<Announcement>>;
+    async fn mark_delivered(&self, announcement: &Announcement) -> Result<()>;
+}
+
+#[async_trait]
+impl AnnouncementCollectionExt for MongoCollection<Announcement> {
+    /// Queues `announcement` unless it was queued before, `true` if it is new
+    async fn queue_announcement(&self, announcement: &Announcement) -> Result<bool> {
+        let options = MongoUpdateOptions::builder().upsert(true).build();
+        let result = self
+            .update_one(
+                doc! {
+                    "airing_id": announcement.airing_id,
+                    "discord_user_id": to_bson(announcement.discord_user_id.as_u64()).unwrap(),
+                },
+                // the ids come from the filter, stored as numbers like in the watch list
+                doc! { "$setOnInsert": {
+                    "anilist_media_id": announcement.anilist_media_id,
+                    "episode": announcement.episode,
+                    "deliver_after": announcement.deliver_after,
+                    "delivered_at": announcement.delivered_at,
+                    "created_at": announcement.created_at,
+                } },
+                Some(options),
+            )
+            .await
+            .context("Failed to queue announcement")?;
+        Ok(result.upserted_id.is_some())
+    }
+
+    async fn find_due_announcements(&self) -> Result<Vec<Announcement>> {
+        self.find(
+            doc! {"delivered_at": null, "deliver_after": { "$lte": BsonDateTime::now() }},
+            None,
+        )
+        .await
+        .context("Failed to find due announcements")?
+        .try_collect()
+        .await
+        .context("Failed to read due announcements")
+    }
+
+    async fn mark_delivered(&self, announcement: &Announcement) -> Result<()> {
+        self.update_one(
+            doc! {
+                "airing_id": announcement.airing_id,
+                "discord_user_id": to_bson(announcement.discord_user_id.as_u64()).unwrap(),
+            },
+            doc! { "$set": { "delivered_at": BsonDateTime::now() } },
+            None,
+        )
+        .await
+        .context("Failed to mark announcement as delivered")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,487 @@
+use std::{
+    convert::TryFrom,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, ensure, Context, Result};
+use mongodm::prelude::BsonDateTime;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serenity::{async_trait, model::id::UserId};
+
+use crate::{
+    db::watchlist::{
+        EntryUpdate, WatchInfo, WatchListFilter, WatchListSort, WatchListStore, WatchStatus,
+        MAX_RATING,
+    },
+    media::{Media, MediaType},
+};
+
+/// Schema changes in order, `PRAGMA user_version` counts the ones already applied
+const MIGRATIONS: &[&str] = &[
+    // the unique key mirrors the `WatchInfoCollConf` index
+    "CREATE TABLE watch_list (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        anilist_media_id INTEGER NOT NULL,
+        discord_user_id INTEGER NOT NULL,
+        media_type TEXT NOT NULL,
+        watch_status TEXT NOT NULL,
+        last_watch_status TEXT NOT NULL,
+        suggests INTEGER NOT NULL DEFAULT 0,
+        rating INTEGER,
+        progress INTEGER NOT NULL DEFAULT 0,
+        progress_volumes INTEGER NOT NULL DEFAULT 0,
+        repeat INTEGER NOT NULL DEFAULT 0,
+        updated_at INTEGER NOT NULL,
+        created_at INTEGER NOT NULL,
+        UNIQUE (anilist_media_id, discord_user_id)
+    );
+    CREATE INDEX watch_list_user ON watch_list (discord_user_id, watch_status);",
+];
+
+const COLUMNS: &str = "anilist_media_id, discord_user_id, media_type, watch_status, \
+    last_watch_status, suggests, rating, progress, progress_volumes, repeat, updated_at, created_at";
+
+/// Keeps watch lists in an SQLite file, for hosts without MongoDB. rusqlite blocks, so every
+/// query runs on tokio's blocking pool.
+#[derive(Clone)]
+pub struct SqliteWatchListStore {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl SqliteWatchListStore {
+    /// Opens `path`, `:memory:` for a throwaway database, and brings its schema up to date
+    pub fn open(path: &str) -> Result<Self> {
+        let mut connection = Connection::open(path)
+            .with_context(|| format!("Failed to open SQLite database {}", path))?;
+        migrate(&mut connection)?;
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    async fn run<T, F>(&self, query: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+    {
+        let connection = self.connection.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut connection = connection
+                .lock()
+                .map_err(|_| anyhow!("SQLite connection is poisoned"))?;
+            query(&mut connection)
+        })
+        .await
+        .context("SQLite query panicked")?
+    }
+
+    /// Applies `update` to the entry of `user_id` for `media`, creating it first if needed
+    async fn upsert(
+        &self,
+        media: &Media,
+        user_id: UserId,
+        update: EntryUpdate,
+    ) -> Result<Option<WatchInfo>> {
+        let media_id = media.id;
+        let media_type = media.media_type;
+        let total = media.total_progress();
+        self.run(move |connection| {
+            let transaction = connection.transaction()?;
+            let now = BsonDateTime::now();
+            let mut info = find_entry(&transaction, media_id, user_id)?
+                .unwrap_or_else(|| WatchInfo::new(media_id, user_id, media_type, now));
+            info.apply(&update, total, now);
+            save_entry(&transaction, &info)?;
+            transaction.commit()?;
+            Ok(Some(info))
+        })
+        .await
+        .context("Failed to update watch list")
+    }
+
+    /// Copies `entries` in, replacing the entries they share a media and user with
+    pub async fn import(&self, entries: Vec<WatchInfo>) -> Result<usize> {
+        self.run(move |connection| {
+            let transaction = connection.transaction()?;
+            for info in &entries {
+                save_entry(&transaction, info)?;
+            }
+            transaction.commit()?;
+            Ok(entries.len())
+        })
+        .await
+        .context("Failed to import watch list")
+    }
+}
+
+fn migrate(connection: &mut Connection) -> Result<()> {
+    let applied: usize = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+        let transaction = connection.transaction()?;
+        transaction
+            .execute_batch(migration)
+            .with_context(|| format!("Failed to apply SQLite migration {}", version + 1))?;
+        transaction.pragma_update(None, "user_version", version + 1)?;
+        transaction.commit()?;
+    }
+    Ok(())
+}
+
+/// Discord ids fit in an `i64`, SQLite has no unsigned integers
+fn user_id_to_sql(user_id: UserId) -> i64 {
+    user_id.0 as i64
+}
+
+fn media_type_to_sql(media_type: &MediaType) -> &'static str {
+    match media_type {
+        MediaType::Manga => "MANGA",
+        _ => "ANIME",
+    }
+}
+
+fn entry_from_row(row: &Row) -> rusqlite::Result<WatchInfo> {
+    Ok(WatchInfo {
+        anilist_media_id: row.get(0)?,
+        discord_user_id: UserId(row.get::<_, i64>(1)? as u64),
+        media_type: match row.get::<_, String>(2)?.as_str() {
+            "MANGA" => MediaType::Manga,
+            _ => MediaType::Anime,
+        },
+        watch_status: WatchStatus::from(row.get::<_, String>(3)?),
+        last_watch_status: WatchStatus::from(row.get::<_, String>(4)?),
+        suggests: row.get(5)?,
+        rating: row.get(6)?,
+        progress: row.get(7)?,
+        progress_volumes: row.get(8)?,
+        repeat: row.get(9)?,
+        updated_at: BsonDateTime::from_millis(row.get(10)?),
+        created_at: BsonDateTime::from_millis(row.get(11)?),
+    })
+}
+
+fn find_entry(
+    connection: &Connection,
+    media_id: i64,
+    user_id: UserId,
+) -> rusqlite::Result<Option<WatchInfo>> {
+    connection
+        .query_row(
+            &format!(
+                "SELECT {} FROM watch_list WHERE anilist_media_id = ?1 AND discord_user_id = ?2",
+                COLUMNS
+            ),
+            params![media_id, user_id_to_sql(user_id)],
+            entry_from_row,
+        )
+        .optional()
+}
+
+fn save_entry(connection: &Connection, info: &WatchInfo) -> rusqlite::Result<()> {
+    connection.execute(
+        &format!(
+            "INSERT INTO watch_list ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+            ON CONFLICT (anilist_media_id, discord_user_id) DO UPDATE SET
+                media_type = excluded.media_type,
+                watch_status = excluded.watch_status,
+                last_watch_status = excluded.last_watch_status,
+                suggests = excluded.suggests,
+                rating = excluded.rating,
+                progress = excluded.progress,
+                progress_volumes = excluded.progress_volumes,
+                repeat = excluded.repeat,
+                updated_at = excluded.updated_at,
+                created_at = excluded.created_at",
+            COLUMNS
+        ),
+        params![
+            info.anilist_media_id,
+            user_id_to_sql(info.discord_user_id),
+            media_type_to_sql(&info.media_type),
+            info.watch_status.as_str(),
+            info.last_watch_status.as_str(),
+            info.suggests,
+            info.rating,
+            info.progress,
+            info.progress_volumes,
+            info.repeat,
+            info.updated_at.timestamp_millis(),
+            info.created_at.timestamp_millis(),
+        ],
+    )?;
+    Ok(())
+}
+
+/// The `WHERE` clause of `WatchListFilter::as_document`, the user is `?1`. Statuses are our own
+/// constants, so they are safe to inline.
+fn filter_to_sql(filter: WatchListFilter) -> String {
+    match filter {
+        WatchListFilter::All => {
+            "discord_user_id = ?1 AND (watch_status != 'NOT_SEEN' OR suggests)".to_owned()
+        }
+        WatchListFilter::Status(status) => format!(
+            "discord_user_id = ?1 AND watch_status = '{}'",
+            status.as_str()
+        ),
+        WatchListFilter::Suggested => "discord_user_id = ?1 AND suggests".to_owned(),
+    }
+}
+
+/// The `ORDER BY` of `WatchListSort::as_document`, `id` stands in for `_id`
+fn sort_to_sql(sort: WatchListSort) -> &'static str {
+    match sort {
+        WatchListSort::UpdatedAt => "updated_at DESC, id DESC",
+        WatchListSort::CreatedAt => "created_at DESC, id DESC",
+        WatchListSort::Rating => "rating DESC, updated_at DESC, id DESC",
+    }
+}
+
+#[async_trait]
+impl WatchListStore for SqliteWatchListStore {
+    async fn toggle_suggestion(&self, media: &Media, user_id: UserId) -> Result<Option<WatchInfo>> {
+        self.upsert(media, user_id, EntryUpdate::ToggleSuggestion)
+            .await
+    }
+
+    async fn toggle_finish(&self, media: &Media, user_id: UserId) -> Result<Option<WatchInfo>> {
+        self.upsert(
+            media,
+            user_id,
+            EntryUpdate::ToggleStatus(WatchStatus::Finished),
+        )
+        .await
+    }
+
+    async fn toggle_consuming(&self, media: &Media, user_id: UserId) -> Result<Option<WatchInfo>> {
+        self.upsert(
+            media,
+            user_id,
+            EntryUpdate::ToggleStatus(WatchStatus::Consuming),
+        )
+        .await
+    }
+
+    async fn set_status(
+        &self,
+        media: &Media,
+        user_id: UserId,
+        status: WatchStatus,
+    ) -> Result<Option<WatchInfo>> {
+        self.upsert(media, user_id, EntryUpdate::SetStatus(status))
+            .await
+    }
+
+    async fn set_rating(
+        &self,
+        media: &Media,
+        user_id: UserId,
+        rating: u8,
+    ) -> Result<Option<WatchInfo>> {
+        ensure!(
+            (1..=MAX_RATING).contains(&rating),
+            "Rating {} is out of range",
+            rating
+        );
+        self.upsert(media, user_id, EntryUpdate::SetRating(rating))
+            .await
+    }
+
+    async fn set_progress(
+        &self,
+        media: &Media,
+        user_id: UserId,
+        progress: i64,
+        volumes: Option<i64>,
+    ) -> Result<Option<WatchInfo>> {
+        self.upsert(media, user_id, EntryUpdate::SetProgress(progress, volumes))
+            .await
+    }
+
+    async fn add_progress(
+        &self,
+        media: &Media,
+        user_id: UserId,
+        amount: i64,
+    ) -> Result<Option<WatchInfo>> {
+        self.upsert(media, user_id, EntryUpdate::AddProgress(amount))
+            .await
+    }
+
+    async fn find_watch_info(&self, media_id: i64, user_id: UserId) -> Result<Option<WatchInfo>> {
+        self.run(move |connection| Ok(find_entry(connection, media_id, user_id)?))
+            .await
+            .context("Failed to find watch info")
+    }
+
+    async fn count_watch_list(&self, user_id: UserId, filter: WatchListFilter) -> Result<u64> {
+        self.run(move |connection| {
+            let count: i64 = connection.query_row(
+                &format!(
+                    "SELECT COUNT(*) FROM watch_list WHERE {}",
+                    filter_to_sql(filter)
+                ),
+                params![user_id_to_sql(user_id)],
+                |row| row.get(0),
+            )?;
+            Ok(u64::try_from(count)?)
+        })
+        .await
+        .context("Failed to count watch list")
+    }
+
+    async fn find_watch_list(
+        &self,
+        user_id: UserId,
+        filter: WatchListFilter,
+        sort: WatchListSort,
+        skip: u64,
+        limit: i64,
+    ) -> Result<Vec<WatchInfo>> {
+        self.run(move |connection| {
+            let mut statement = connection.prepare(&format!(
+                "SELECT {} FROM watch_list WHERE {} ORDER BY {} LIMIT ?2 OFFSET ?3",
+                COLUMNS,
+                filter_to_sql(filter),
+                sort_to_sql(sort)
+            ))?;
+            // like MongoDB, no limit when it is 0
+            let limit = match limit {
+                0 => -1,
+                limit => limit.abs(),
+            };
+            let watch_list = statement
+                .query_map(
+                    params![user_id_to_sql(user_id), limit, skip as i64],
+                    entry_from_row,
+                )?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(watch_list)
+        })
+        .await
+        .context("Failed to find watch list")
+    }
+
+    async fn find_consuming(
+        &self,
+        media_type: MediaType,
+        user_ids: &[UserId],
+    ) -> Result<Vec<WatchInfo>> {
+        if user_ids.is_empty() {
+            return Ok(vec![]);
+        }
+        // ids are numbers, so they are safe to inline
+        let user_ids = user_ids
+            .iter()
+            .map(|user_id| user_id_to_sql(*user_id).to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.run(move |connection| {
+            let mut statement = connection.prepare(&format!(
+                "SELECT {} FROM watch_list WHERE media_type = ?1 AND watch_status = 'CONSUMING' \
+                AND discord_user_id IN ({})",
+                COLUMNS, user_ids
+            ))?;
+            let watching = statement
+                .query_map(params![media_type_to_sql(&media_type)], entry_from_row)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(watching)
+        })
+        .await
+        .context("Failed to find consumed media")
+    }
+
+    async fn find_suggested(&self) -> Result<Vec<WatchInfo>> {
+        self.run(|connection| {
+            let mut statement = connection.prepare(&format!(
+                "SELECT {} FROM watch_list WHERE suggests",
+                COLUMNS
+            ))?;
+            let suggested = statement
+                .query_map([], entry_from_row)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(suggested)
+        })
+        .await
+        .context("Failed to find suggested media")
+    }
+
+    async fn find_finished(&self, media_ids: &[i64]) -> Result<Vec<WatchInfo>> {
+        if media_ids.is_empty() {
+            return Ok(vec![]);
+        }
+        // ids are numbers, so they are safe to inline
+        let media_ids = media_ids
+            .iter()
+            .map(i64::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.run(move |connection| {
+            let mut statement = connection.prepare(&format!(
+                "SELECT {} FROM watch_list WHERE watch_status = 'FINISHED' \
+                AND anilist_media_id IN ({})",
+                COLUMNS, media_ids
+            ))?;
+            let finished = statement
+                .query_map([], entry_from_row)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(finished)
+        })
+        .await
+        .context("Failed to find finished media")
+    }
+
+    async fn find_listed_media(&self, media_ids: &[i64]) -> Result<Vec<i64>> {
+        if media_ids.is_empty() {
+            return Ok(vec![]);
+        }
+        let media_ids = media_ids
+            .iter()
+            .map(i64::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.run(move |connection| {
+            let mut statement = connection.prepare(&format!(
+                "SELECT DISTINCT anilist_media_id FROM watch_list \
+                WHERE (watch_status != 'NOT_SEEN' OR suggests) AND anilist_media_id IN ({})",
+                media_ids
+            ))?;
+            let listed = statement
+                .query_map([], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(listed)
+        })
+        .await
+        .context("Failed to find listed media")
+    }
+
+    async fn find_all(&self, user_id: UserId) -> Result<Vec<WatchInfo>> {
+        self.run(move |connection| {
+            let mut statement = connection.prepare(&format!(
+                "SELECT {} FROM watch_list WHERE discord_user_id = ?1 ORDER BY id",
+                COLUMNS
+            ))?;
+            let entries = statement
+                .query_map(params![user_id_to_sql(user_id)], entry_from_row)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(entries)
+        })
+        .await
+        .context("Failed to find watch list")
+    }
+
+    async fn find_every_entry(&self) -> Result<Vec<WatchInfo>> {
+        self.run(|connection| {
+            let mut statement =
+                connection.prepare(&format!("SELECT {} FROM watch_list ORDER BY id", COLUMNS))?;
+            let entries = statement
+                .query_map([], entry_from_row)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(entries)
+        })
+        .await
+        .context("Failed to find watch lists")
+    }
+
+    async fn save_entries(&self, entries: Vec<WatchInfo>) -> Result<()> {
+        self.import(entries).await.map(|_| ())
+    }
+}
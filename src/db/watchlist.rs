@@ -1,19 +1,22 @@
-use std::fmt::Display;
+use std::{fmt::Display, str::FromStr};
 
-use anyhow::{Context, Result};
+use anyhow::{ensure, Context, Result};
 use mongodm::{
     doc,
     operator::{Equal, MergeObjects, Not, ReplaceWith, Set, Unset},
     prelude::{
-        to_bson, Bson, BsonDateTime, MongoCollection, MongoFindOneAndUpdateOptions,
-        MongoReturnDocument,
+        to_bson, Bson, BsonDateTime, BsonDocument, MongoCollection, MongoFindOneAndUpdateOptions,
+        MongoFindOptions, MongoReplaceOptions, MongoReturnDocument,
     },
     CollectionConfig, Index, IndexOption, Indexes, Model,
 };
 use serde::{Deserialize, Serialize};
-use serenity::{async_trait, model::id::UserId};
+use serenity::{async_trait, futures::TryStreamExt, model::id::UserId};
 
-use crate::graphql::lookup_media_page::{LookupMediaPagePageMedia as Media, MediaType};
+use crate::media::{Media, MediaType};
+
+/// Personal ratings are scored out of 10, like AniList's `POINT_10` score format
+pub const MAX_RATING: u8 = 10;
 
 pub struct WatchInfoCollConf;
 
@@ -31,15 +34,20 @@ impl CollectionConfig for WatchInfoCollConf {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+/// Mirrors AniList's `MediaListStatus`, plus `NotSeen` for entries that only hold a suggestion
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum WatchStatus {
     NotSeen,
+    Planning,
     Consuming,
     Finished,
+    Paused,
+    Dropped,
+    Repeating,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct WatchInfo {
     pub anilist_media_id: i64,
     pub discord_user_id: UserId,
@@ -48,6 +56,12 @@ pub struct WatchInfo {
     pub last_watch_status: WatchStatus,
     pub suggests: bool,
     pub rating: Option<u8>,
+    #[serde(default)]
+    pub progress: i64,
+    #[serde(default)]
+    pub progress_volumes: i64,
+    #[serde(default)]
+    pub repeat: i64,
     pub updated_at: BsonDateTime,
     pub created_at: BsonDateTime,
 }
@@ -56,13 +70,131 @@ impl Model for WatchInfo {
     type CollConf = WatchInfoCollConf;
 }
 
+impl WatchInfo {
+    /// Progress like `7/12`, or `7/?` when the total is not known yet
+    pub fn progress_text(&self, total: Option<i64>) -> String {
+        format!(
+            "{}/{}",
+            self.progress,
+            total.map_or_else(|| "?".to_owned(), |total| total.to_string())
+        )
+    }
+
+    pub fn progress_unit(&self) -> &str {
+        match self.media_type {
+            MediaType::Manga => "chapter",
+            _ => "episode",
+        }
+    }
+
+    /// An entry nothing was done with yet, what the update pipelines merge missing fields from
+    pub fn new(
+        anilist_media_id: i64,
+        discord_user_id: UserId,
+        media_type: MediaType,
+        now: BsonDateTime,
+    ) -> Self {
+        WatchInfo {
+            anilist_media_id,
+            discord_user_id,
+            media_type,
+            watch_status: WatchStatus::NotSeen,
+            last_watch_status: WatchStatus::NotSeen,
+            suggests: false,
+            rating: None,
+            progress: 0,
+            progress_volumes: 0,
+            repeat: 0,
+            updated_at: now,
+            created_at: now,
+        }
+    }
+
+    /// Applies `update` the way the MongoDB update pipelines do, for backends without them.
+    /// `total` is the media's episode or chapter count.
+    pub fn apply(&mut self, update: &EntryUpdate, total: Option<i64>, now: BsonDateTime) {
+        match *update {
+            EntryUpdate::ToggleSuggestion => self.suggests = !self.suggests,
+            EntryUpdate::ToggleStatus(status) => {
+                let last_watch_status = self.last_watch_status;
+                self.last_watch_status = self.watch_status;
+                self.watch_status = if self.watch_status == status {
+                    last_watch_status
+                } else {
+                    status
+                };
+            }
+            EntryUpdate::SetStatus(status) => {
+                // every time a rewatch starts, count it and start over
+                if status == WatchStatus::Repeating && self.watch_status != WatchStatus::Repeating {
+                    self.repeat += 1;
+                    self.progress = 0;
+                    self.progress_volumes = 0;
+                }
+                self.change_status(status);
+            }
+            EntryUpdate::SetRating(rating) => self.rating = Some(rating),
+            EntryUpdate::SetProgress(progress, volumes) => {
+                self.apply_progress(total, progress, volumes)
+            }
+            EntryUpdate::AddProgress(amount) => {
+                self.apply_progress(total, self.progress + amount, None)
+            }
+        }
+        self.updated_at = now;
+    }
+
+    /// Moves the entry to `status`, remembering the status it leaves
+    pub fn change_status(&mut self, status: WatchStatus) {
+        if self.watch_status != status {
+            self.last_watch_status = self.watch_status;
+            self.watch_status = status;
+        }
+    }
+
+    /// Mirrors `progress_pipeline`
+    fn apply_progress(&mut self, total: Option<i64>, progress: i64, volumes: Option<i64>) {
+        let total = total.filter(|total| *total > 0);
+        self.progress = progress.max(0);
+        if let Some(volumes) = volumes {
+            self.progress_volumes = volumes.max(0);
+        }
+        if let Some(total) = total {
+            self.progress = self.progress.min(total);
+        }
+
+        let status = if matches!(total, Some(total) if self.progress >= total) {
+            WatchStatus::Finished
+        } else if self.progress > 0
+            && matches!(
+                self.watch_status,
+                WatchStatus::NotSeen | WatchStatus::Planning
+            )
+        {
+            WatchStatus::Consuming
+        } else {
+            self.watch_status
+        };
+        self.change_status(status);
+    }
+}
+
+/// A change to one watch list entry, see `WatchInfo::apply`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryUpdate {
+    ToggleSuggestion,
+    /// Toggles between the status and the one the entry had before
+    ToggleStatus(WatchStatus),
+    SetStatus(WatchStatus),
+    SetRating(u8),
+    /// Progress and, for manga, volumes
+    SetProgress(i64, Option<i64>),
+    AddProgress(i64),
+}
+
 impl From<WatchStatus> for Bson {
     fn from(val: WatchStatus) -> Self {
-        match val {
-            WatchStatus::Finished => Bson::String("FINISHED".to_owned()),
-            WatchStatus::Consuming => Bson::String("CONSUMING".to_owned()),
-            WatchStatus::NotSeen => Bson::String("NOT_SEEN".to_owned()),
-        }
+        Bson::String(val.as_str().to_owned())
     }
 }
 
@@ -71,6 +203,10 @@ impl From<String> for WatchStatus {
         match val.as_str() {
             "FINISHED" => WatchStatus::Finished,
             "CONSUMING" => WatchStatus::Consuming,
+            "PLANNING" => WatchStatus::Planning,
+            "PAUSED" => WatchStatus::Paused,
+            "DROPPED" => WatchStatus::Dropped,
+            "REPEATING" => WatchStatus::Repeating,
             _ => WatchStatus::NotSeen, // includes "NOT_SEEN"
         }
     }
@@ -78,41 +214,334 @@ impl From<String> for WatchStatus {
 
 impl Display for WatchStatus {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}",
+            self.as_label(&MediaType::Anime),
+            self.as_emoji(&MediaType::Anime)
+        )
+    }
+}
+
+impl WatchStatus {
+    /// The stored name, `From<String>` parses it back
+    pub fn as_str(&self) -> &'static str {
         match self {
-            WatchStatus::Finished => write!(f, "Finished {}", self.as_emoji()),
-            WatchStatus::Consuming => write!(f, "Watching {}", self.as_emoji()),
-            WatchStatus::NotSeen => write!(f, "Not seen {}", self.as_emoji()),
+            WatchStatus::Finished => "FINISHED",
+            WatchStatus::Consuming => "CONSUMING",
+            WatchStatus::Planning => "PLANNING",
+            WatchStatus::Paused => "PAUSED",
+            WatchStatus::Dropped => "DROPPED",
+            WatchStatus::Repeating => "REPEATING",
+            WatchStatus::NotSeen => "NOT_SEEN",
         }
     }
+
+    /// Every status, in the order they are offered to users
+    pub const ALL: [WatchStatus; 7] = [
+        WatchStatus::Planning,
+        WatchStatus::Consuming,
+        WatchStatus::Finished,
+        WatchStatus::Paused,
+        WatchStatus::Dropped,
+        WatchStatus::Repeating,
+        WatchStatus::NotSeen,
+    ];
+
+    pub fn as_label(&self, media_type: &MediaType) -> &str {
+        match (self, media_type) {
+            (WatchStatus::Finished, _) => "Finished",
+            (WatchStatus::Consuming, MediaType::Manga) => "Reading",
+            (WatchStatus::Consuming, _) => "Watching",
+            (WatchStatus::Planning, _) => "Planning",
+            (WatchStatus::Paused, _) => "Paused",
+            (WatchStatus::Dropped, _) => "Dropped",
+            (WatchStatus::Repeating, MediaType::Manga) => "Rereading",
+            (WatchStatus::Repeating, _) => "Rewatching",
+            (WatchStatus::NotSeen, _) => "Not seen",
+        }
+    }
+
+    pub fn as_emoji(&self, media_type: &MediaType) -> &str {
+        match (self, media_type) {
+            (WatchStatus::Finished, _) => "🏁",
+            (WatchStatus::Consuming, MediaType::Manga) => "📖",
+            (WatchStatus::Consuming, _) => "👀",
+            (WatchStatus::Planning, _) => "📝",
+            (WatchStatus::Paused, _) => "⏸️",
+            (WatchStatus::Dropped, _) => "🗑️",
+            (WatchStatus::Repeating, _) => "🔁",
+            (WatchStatus::NotSeen, _) => "❓",
+        }
+    }
+
+    pub fn as_verb(&self, media_type: &MediaType) -> &str {
+        match (self, media_type) {
+            (WatchStatus::Finished, _) => "finished",
+            (WatchStatus::Consuming, MediaType::Manga) => "are reading",
+            (WatchStatus::Consuming, _) => "are watching",
+            (WatchStatus::Planning, MediaType::Manga) => "are planning to read",
+            (WatchStatus::Planning, _) => "are planning to watch",
+            (WatchStatus::Paused, _) => "paused",
+            (WatchStatus::Dropped, _) => "dropped",
+            (WatchStatus::Repeating, MediaType::Manga) => "are rereading",
+            (WatchStatus::Repeating, _) => "are rewatching",
+            (WatchStatus::NotSeen, _) => "forgot",
+        }
+    }
+
+    /// Whether progress is being made on the media
+    pub fn is_active(&self) -> bool {
+        matches!(self, WatchStatus::Consuming | WatchStatus::Repeating)
+    }
 }
 
-impl WatchStatus {
-    pub fn as_emoji(&self) -> &str {
+/// Which entries of a watch list to show
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchListFilter {
+    All,
+    Status(WatchStatus),
+    Suggested,
+}
+
+impl FromStr for WatchListFilter {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "all" => Ok(Self::All),
+            "watching" | "reading" | "consuming" => Ok(Self::Status(WatchStatus::Consuming)),
+            "finished" => Ok(Self::Status(WatchStatus::Finished)),
+            "planning" => Ok(Self::Status(WatchStatus::Planning)),
+            "paused" => Ok(Self::Status(WatchStatus::Paused)),
+            "dropped" => Ok(Self::Status(WatchStatus::Dropped)),
+            "rewatching" | "rereading" | "repeating" => Ok(Self::Status(WatchStatus::Repeating)),
+            "suggested" | "suggests" => Ok(Self::Suggested),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Display for WatchListFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::All => write!(f, "All 📜"),
+            Self::Status(status) => write!(f, "{}", status),
+            Self::Suggested => write!(f, "Suggested 🌟"),
+        }
+    }
+}
+
+impl WatchListFilter {
+    /// The name `from_str` parses back
+    pub fn as_key(&self) -> &'static str {
         match self {
-            WatchStatus::Finished => "🏁",
-            WatchStatus::Consuming => "👀",
-            WatchStatus::NotSeen => "❓",
+            Self::All | Self::Status(WatchStatus::NotSeen) => "all",
+            Self::Status(WatchStatus::Planning) => "planning",
+            Self::Status(WatchStatus::Consuming) => "consuming",
+            Self::Status(WatchStatus::Finished) => "finished",
+            Self::Status(WatchStatus::Paused) => "paused",
+            Self::Status(WatchStatus::Dropped) => "dropped",
+            Self::Status(WatchStatus::Repeating) => "repeating",
+            Self::Suggested => "suggested",
         }
     }
 
-    pub fn as_verb(&self) -> &str {
+    /// Whether `info` is listed, the same as the query of `as_document`
+    pub fn matches(&self, info: &WatchInfo) -> bool {
         match self {
-            WatchStatus::Finished => "finished",
-            WatchStatus::Consuming => "are watching",
-            WatchStatus::NotSeen => "forgot",
+            Self::All => info.watch_status != WatchStatus::NotSeen || info.suggests,
+            Self::Status(status) => info.watch_status == *status,
+            Self::Suggested => info.suggests,
         }
     }
+
+    fn as_document(&self, user_id: UserId) -> BsonDocument {
+        let mut filter = doc! { "discord_user_id": to_bson(user_id.as_u64()).unwrap() };
+        match self {
+            // entries toggled back to NOT_SEEN without a suggestion are empty
+            Self::All => filter.insert(
+                "$or",
+                vec![
+                    doc! { "watch_status": { "$ne": WatchStatus::NotSeen } },
+                    doc! { "suggests": true },
+                ],
+            ),
+            Self::Status(status) => filter.insert("watch_status", *status),
+            Self::Suggested => filter.insert("suggests", true),
+        };
+        filter
+    }
 }
 
+/// Order of the entries of a watch list, newest/highest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchListSort {
+    UpdatedAt,
+    CreatedAt,
+    Rating,
+}
+
+impl FromStr for WatchListSort {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "updated" | "recent" => Ok(Self::UpdatedAt),
+            "created" | "added" => Ok(Self::CreatedAt),
+            "rating" | "rated" => Ok(Self::Rating),
+            _ => Err(()),
+        }
+    }
+}
+
+impl WatchListSort {
+    /// The name `from_str` parses back
+    pub fn as_key(&self) -> &'static str {
+        match self {
+            Self::UpdatedAt => "updated",
+            Self::CreatedAt => "created",
+            Self::Rating => "rating",
+        }
+    }
+
+    fn as_document(&self) -> BsonDocument {
+        match self {
+            Self::UpdatedAt => doc! { "updated_at": -1, "_id": -1 },
+            Self::CreatedAt => doc! { "created_at": -1, "_id": -1 },
+            Self::Rating => doc! { "rating": -1, "updated_at": -1, "_id": -1 },
+        }
+    }
+}
+
+/// Update pipeline that applies a new progress, finishing the entry once it reaches the total and
+/// starting it once there is any progress
+fn progress_pipeline(media: &Media, progress: Bson, volumes: Option<i64>) -> Vec<BsonDocument> {
+    let total = media.total_progress().filter(|total| *total > 0);
+
+    let mut progress_set =
+        doc! { "progress": { "$max": [0_i64, progress] }, "updated_at": "$$NOW" };
+    if let Some(volumes) = volumes {
+        progress_set.insert("progress_volumes", volumes.max(0));
+    }
+    let mut pipeline = vec![doc! { Set: progress_set }];
+    if let Some(total) = total {
+        pipeline.push(doc! { Set: { "progress": { "$min": ["$progress", total] } } });
+    }
+
+    let finished = match total {
+        Some(total) => Bson::Document(doc! { "$gte": ["$progress", total] }),
+        None => Bson::Boolean(false),
+    };
+    pipeline.extend(vec![
+        doc! { Set: {
+            "temp": { "$switch": {
+                "branches": [
+                    { "case": finished, "then": WatchStatus::Finished },
+                    {
+                        "case": { "$and": [
+                            { "$gt": ["$progress", 0_i64] },
+                            { "$in": [
+                                { "$ifNull": ["$watch_status", WatchStatus::NotSeen] },
+                                [WatchStatus::NotSeen, WatchStatus::Planning],
+                            ] },
+                        ] },
+                        "then": WatchStatus::Consuming,
+                    },
+                ],
+                "default": { "$ifNull": ["$watch_status", WatchStatus::NotSeen] },
+            } },
+        } },
+        doc! { Set: {
+            "last_watch_status": {
+                "$cond": [
+                    { Equal: ["$temp", "$watch_status"] },
+                    "$last_watch_status",
+                    "$watch_status"
+                ]
+            },
+            "watch_status": "$temp",
+        } },
+        doc! { ReplaceWith: { MergeObjects: [
+            {
+                "last_watch_status": WatchStatus::NotSeen,
+                "created_at": "$$NOW",
+                "progress_volumes": 0_i64,
+                "repeat": 0_i64,
+                "media_type": media.media_type,
+                "rating": Bson::Null,
+                "suggests": false,
+            },
+            "$$ROOT"
+        ] } },
+        doc! { Unset: "temp" },
+    ]);
+    pipeline
+}
+
+/// Every watch list operation, implemented by each storage backend. Operations on an entry that
+/// does not exist yet create it, like an upsert.
 #[async_trait]
-pub trait WatchListCollectionExt {
+pub trait WatchListStore: Send + Sync {
     async fn toggle_suggestion(&self, media: &Media, user_id: UserId) -> Result<Option<WatchInfo>>;
     async fn toggle_finish(&self, media: &Media, user_id: UserId) -> Result<Option<WatchInfo>>;
     async fn toggle_consuming(&self, media: &Media, user_id: UserId) -> Result<Option<WatchInfo>>;
+    async fn set_status(
+        &self,
+        media: &Media,
+        user_id: UserId,
+        status: WatchStatus,
+    ) -> Result<Option<WatchInfo>>;
+    async fn set_rating(
+        &self,
+        media: &Media,
+        user_id: UserId,
+        rating: u8,
+    ) -> Result<Option<WatchInfo>>;
+    async fn set_progress(
+        &self,
+        media: &Media,
+        user_id: UserId,
+        progress: i64,
+        volumes: Option<i64>,
+    ) -> Result<Option<WatchInfo>>;
+    async fn add_progress(
+        &self,
+        media: &Media,
+        user_id: UserId,
+        amount: i64,
+    ) -> Result<Option<WatchInfo>>;
+    async fn find_watch_info(&self, media_id: i64, user_id: UserId) -> Result<Option<WatchInfo>>;
+    async fn count_watch_list(&self, user_id: UserId, filter: WatchListFilter) -> Result<u64>;
+    async fn find_watch_list(
+        &self,
+        user_id: UserId,
+        filter: WatchListFilter,
+        sort: WatchListSort,
+        skip: u64,
+        limit: i64,
+    ) -> Result<Vec<WatchInfo>>;
+    async fn find_consuming(
+        &self,
+        media_type: MediaType,
+        user_ids: &[UserId],
+    ) -> Result<Vec<WatchInfo>>;
+    /// Every suggested entry, of any user
+    async fn find_suggested(&self) -> Result<Vec<WatchInfo>>;
+    /// The finished entries for `media_ids`, of any user
+    async fn find_finished(&self, media_ids: &[i64]) -> Result<Vec<WatchInfo>>;
+    /// Which of `media_ids` are on anyone's watch list, empty entries left out
+    async fn find_listed_media(&self, media_ids: &[i64]) -> Result<Vec<i64>>;
+    /// Every entry of `user_id`, empty ones included
+    async fn find_all(&self, user_id: UserId) -> Result<Vec<WatchInfo>>;
+    /// Every entry of every user, for backups
+    async fn find_every_entry(&self) -> Result<Vec<WatchInfo>>;
+    /// Writes `entries` as they are, replacing the entries they share a media and user with
+    async fn save_entries(&self, entries: Vec<WatchInfo>) -> Result<()>;
 }
 
 #[async_trait]
-impl WatchListCollectionExt for MongoCollection<WatchInfo> {
+impl WatchListStore for MongoCollection<WatchInfo> {
     async fn toggle_finish(&self, media: &Media, user_id: UserId) -> Result<Option<WatchInfo>> {
         let options = MongoFindOneAndUpdateOptions::builder()
             .upsert(true)
@@ -139,7 +568,10 @@ impl WatchListCollectionExt for MongoCollection<WatchInfo> {
                     {
                         "last_watch_status": WatchStatus::NotSeen,
                         "created_at": "$$NOW",
-                        "media_type": MediaType::Anime,
+                        "progress": 0_i64,
+                        "progress_volumes": 0_i64,
+                        "repeat": 0_i64,
+                        "media_type": media.media_type,
                         "rating": Bson::Null,
                         "suggests": false,
                     },
@@ -177,7 +609,10 @@ impl WatchListCollectionExt for MongoCollection<WatchInfo> {
                     {
                         "last_watch_status": WatchStatus::NotSeen,
                         "created_at": "$$NOW",
-                        "media_type": MediaType::Anime,
+                        "progress": 0_i64,
+                        "progress_volumes": 0_i64,
+                        "repeat": 0_i64,
+                        "media_type": media.media_type,
                         "rating": Bson::Null,
                         "suggests": false,
                     },
@@ -201,7 +636,10 @@ impl WatchListCollectionExt for MongoCollection<WatchInfo> {
                 doc! { ReplaceWith: { MergeObjects:[
                     {
                         "created_at": "$$NOW",
-                        "media_type": MediaType::Anime,
+                        "progress": 0_i64,
+                        "progress_volumes": 0_i64,
+                        "repeat": 0_i64,
+                        "media_type": media.media_type,
                         "rating": Bson::Null,
                         "watch_status": WatchStatus::NotSeen,
                         "last_watch_status": WatchStatus::NotSeen,
@@ -212,4 +650,296 @@ impl WatchListCollectionExt for MongoCollection<WatchInfo> {
             Some(options),
         ).await.context("Failed to toggle suggestion")
     }
+
+    async fn set_status(
+        &self,
+        media: &Media,
+        user_id: UserId,
+        status: WatchStatus,
+    ) -> Result<Option<WatchInfo>> {
+        let options = MongoFindOneAndUpdateOptions::builder()
+            .upsert(true)
+            .return_document(MongoReturnDocument::After)
+            .build();
+        let mut status_set = doc! {
+            "last_watch_status": {
+                "$cond": [
+                    { Equal: ["$watch_status", status] },
+                    "$last_watch_status",
+                    "$watch_status"
+                ]
+            },
+            "watch_status": status,
+            "updated_at": "$$NOW",
+        };
+        // every time a rewatch starts, count it and start over
+        if status == WatchStatus::Repeating {
+            let rewatching = doc! { Equal: ["$watch_status", WatchStatus::Repeating] };
+            status_set.insert(
+                "repeat",
+                doc! { "$cond": [
+                    rewatching.clone(),
+                    { "$ifNull": ["$repeat", 0_i64] },
+                    { "$add": [{ "$ifNull": ["$repeat", 0_i64] }, 1_i64] }
+                ] },
+            );
+            status_set.insert(
+                "progress",
+                doc! { "$cond": [rewatching.clone(), { "$ifNull": ["$progress", 0_i64] }, 0_i64] },
+            );
+            status_set.insert(
+                "progress_volumes",
+                doc! { "$cond": [rewatching, { "$ifNull": ["$progress_volumes", 0_i64] }, 0_i64] },
+            );
+        }
+        self.find_one_and_update(
+            doc! {"anilist_media_id": media.id, "discord_user_id": to_bson(user_id.as_u64()).unwrap()},
+            vec![
+                doc! { Set: status_set },
+                doc! { ReplaceWith: { MergeObjects:[
+                    {
+                        "last_watch_status": WatchStatus::NotSeen,
+                        "created_at": "$$NOW",
+                        "progress": 0_i64,
+                        "progress_volumes": 0_i64,
+                        "repeat": 0_i64,
+                        "media_type": media.media_type,
+                        "rating": Bson::Null,
+                        "suggests": false,
+                    },
+                    "$$ROOT"
+                ] } },
+            ],
+            Some(options),
+        ).await.context("Failed to set status")
+    }
+
+    async fn set_rating(
+        &self,
+        media: &Media,
+        user_id: UserId,
+        rating: u8,
+    ) -> Result<Option<WatchInfo>> {
+        ensure!(
+            (1..=MAX_RATING).contains(&rating),
+            "Rating {} is out of range",
+            rating
+        );
+        let options = MongoFindOneAndUpdateOptions::builder()
+            .upsert(true)
+            .return_document(MongoReturnDocument::After)
+            .build();
+        self.find_one_and_update(
+            doc! {"anilist_media_id": media.id, "discord_user_id": to_bson(user_id.as_u64()).unwrap()},
+            vec![
+                doc! { Set: { "rating": i32::from(rating), "updated_at": "$$NOW" } },
+                doc! { ReplaceWith: { MergeObjects:[
+                    {
+                        "created_at": "$$NOW",
+                        "progress": 0_i64,
+                        "progress_volumes": 0_i64,
+                        "repeat": 0_i64,
+                        "media_type": media.media_type,
+                        "suggests": false,
+                        "watch_status": WatchStatus::NotSeen,
+                        "last_watch_status": WatchStatus::NotSeen,
+                    },
+                    "$$ROOT"
+                ] } },
+            ],
+            Some(options),
+        ).await.context("Failed to set rating")
+    }
+
+    async fn set_progress(
+        &self,
+        media: &Media,
+        user_id: UserId,
+        progress: i64,
+        volumes: Option<i64>,
+    ) -> Result<Option<WatchInfo>> {
+        let options = MongoFindOneAndUpdateOptions::builder()
+            .upsert(true)
+            .return_document(MongoReturnDocument::After)
+            .build();
+        self.find_one_and_update(
+            doc! {"anilist_media_id": media.id, "discord_user_id": to_bson(user_id.as_u64()).unwrap()},
+            progress_pipeline(media, Bson::Int64(progress), volumes),
+            Some(options),
+        )
+        .await
+        .context("Failed to set progress")
+    }
+
+    async fn add_progress(
+        &self,
+        media: &Media,
+        user_id: UserId,
+        amount: i64,
+    ) -> Result<Option<WatchInfo>> {
+        let options = MongoFindOneAndUpdateOptions::builder()
+            .upsert(true)
+            .return_document(MongoReturnDocument::After)
+            .build();
+        self.find_one_and_update(
+            doc! {"anilist_media_id": media.id, "discord_user_id": to_bson(user_id.as_u64()).unwrap()},
+            progress_pipeline(
+                media,
+                Bson::Document(doc! { "$add": [{ "$ifNull": ["$progress", 0_i64] }, amount] }),
+                None,
+            ),
+            Some(options),
+        )
+        .await
+        .context("Failed to add progress")
+    }
+
+    async fn find_watch_info(&self, media_id: i64, user_id: UserId) -> Result<Option<WatchInfo>> {
+        self.find_one(
+            doc! {"anilist_media_id": media_id, "discord_user_id": to_bson(user_id.as_u64()).unwrap()},
+            None,
+        )
+        .await
+        .context("Failed to find watch info")
+    }
+
+    async fn count_watch_list(&self, user_id: UserId, filter: WatchListFilter) -> Result<u64> {
+        self.count_documents(filter.as_document(user_id), None)
+            .await
+            .context("Failed to count watch list")
+    }
+
+    async fn find_watch_list(
+        &self,
+        user_id: UserId,
+        filter: WatchListFilter,
+        sort: WatchListSort,
+        skip: u64,
+        limit: i64,
+    ) -> Result<Vec<WatchInfo>> {
+        let options = MongoFindOptions::builder()
+            .sort(sort.as_document())
+            .skip(skip)
+            .limit(limit)
+            .build();
+        self.find(filter.as_document(user_id), Some(options))
+            .await
+            .context("Failed to find watch list")?
+            .try_collect()
+            .await
+            .context("Failed to read watch list")
+    }
+
+    async fn find_consuming(
+        &self,
+        media_type: MediaType,
+        user_ids: &[UserId],
+    ) -> Result<Vec<WatchInfo>> {
+        let user_ids = user_ids
+            .iter()
+            .map(|user_id| to_bson(user_id.as_u64()).unwrap())
+            .collect::<Vec<_>>();
+        self.find(
+            doc! {
+                "media_type": media_type,
+                "watch_status": WatchStatus::Consuming,
+                "discord_user_id": { "$in": user_ids },
+            },
+            None,
+        )
+        .await
+        .context("Failed to find consumed media")?
+        .try_collect()
+        .await
+        .context("Failed to read consumed media")
+    }
+
+    async fn find_suggested(&self) -> Result<Vec<WatchInfo>> {
+        self.find(doc! { "suggests": true }, None)
+            .await
+            .context("Failed to find suggested media")?
+            .try_collect()
+            .await
+            .context("Failed to read suggested media")
+    }
+
+    async fn find_finished(&self, media_ids: &[i64]) -> Result<Vec<WatchInfo>> {
+        self.find(
+            doc! {
+                "anilist_media_id": { "$in": media_ids },
+                "watch_status": WatchStatus::Finished,
+            },
+            None,
+        )
+        .await
+        .context("Failed to find finished media")?
+        .try_collect()
+        .await
+        .context("Failed to read finished media")
+    }
+
+    async fn find_listed_media(&self, media_ids: &[i64]) -> Result<Vec<i64>> {
+        let listed = self
+            .distinct(
+                "anilist_media_id",
+                doc! {
+                    "anilist_media_id": { "$in": media_ids },
+                    "$or": [
+                        { "watch_status": { "$ne": WatchStatus::NotSeen } },
+                        { "suggests": true },
+                    ],
+                },
+                None,
+            )
+            .await
+            .context("Failed to find listed media")?;
+        Ok(listed.iter().filter_map(Bson::as_i64).collect())
+    }
+
+    async fn find_all(&self, user_id: UserId) -> Result<Vec<WatchInfo>> {
+        self.find(
+            doc! { "discord_user_id": to_bson(user_id.as_u64()).unwrap() },
+            Some(MongoFindOptions::builder().sort(doc! { "_id": 1 }).build()),
+        )
+        .await
+        .context("Failed to find watch list")?
+        .try_collect()
+        .await
+        .context("Failed to read watch list")
+    }
+
+    async fn find_every_entry(&self) -> Result<Vec<WatchInfo>> {
+        self.find(
+            doc! {},
+            Some(MongoFindOptions::builder().sort(doc! { "_id": 1 }).build()),
+        )
+        .await
+        .context("Failed to find watch lists")?
+        .try_collect()
+        .await
+        .context("Failed to read watch lists")
+    }
+
+    async fn save_entries(&self, entries: Vec<WatchInfo>) -> Result<()> {
+        let options = MongoReplaceOptions::builder().upsert(true).build();
+        let documents = self.clone_with_type::<BsonDocument>();
+        for info in entries {
+            let user_id = to_bson(info.discord_user_id.as_u64()).unwrap();
+            // serenity serializes ids as strings, the queries expect numbers
+            let mut document = match to_bson(&info).context("Failed to serialize watch info")? {
+                Bson::Document(document) => document,
+                _ => unreachable!("watch info serializes to a document"),
+            };
+            document.insert("discord_user_id", user_id.clone());
+            documents
+                .replace_one(
+                    doc! {"anilist_media_id": info.anilist_media_id, "discord_user_id": user_id},
+                    document,
+                    Some(options.clone()),
+                )
+                .await
+                .context("Failed to save watch list")?;
+        }
+        Ok(())
+    }
 }
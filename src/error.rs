@@ -0,0 +1,349 @@
+use std::{
+    collections::hash_map::RandomState,
+    error::Error as StdError,
+    fmt,
+    hash::{BuildHasher, Hasher},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serenity::{
+    builder::CreateEmbed,
+    framework::standard::CommandError,
+    http::error::Error as HttpError,
+    model::{
+        id::{ChannelId, GuildId},
+        interactions::{
+            Interaction, InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+        },
+        user::User,
+    },
+    prelude::Context,
+};
+use thiserror::Error;
+use tracing::{error, info};
+
+use crate::{
+    anilist::AniListError, embeds::make_error_embed, mal::MalError, metadata::MetadataError,
+    query::QueryError, utils::OwnerLogContainer,
+};
+
+/// What commands and interactions fail with, every kind is shown to the user as a message they
+/// can act on together with a correlation id to find it in the logs
+#[derive(Error, Debug)]
+pub enum BotError {
+    #[error(transparent)]
+    AniList(#[from] AniListError),
+    #[error(transparent)]
+    Metadata(MetadataError),
+    /// The watch list and guild stores
+    #[error(transparent)]
+    Storage(anyhow::Error),
+    #[error("Discord error")]
+    Discord(#[from] serenity::Error),
+    #[error("{0}")]
+    Validation(String),
+    /// Anything else that went wrong on the bot's side
+    #[error(transparent)]
+    Internal(anyhow::Error),
+}
+
+pub type BotResult<T> = Result<T, BotError>;
+
+impl BotError {
+    fn user_message(&self) -> String {
+        match self {
+            BotError::AniList(why) => anilist_message(why),
+            BotError::Metadata(why) => metadata_message(why),
+            BotError::Storage(_) => STORAGE_MESSAGE.to_owned(),
+            BotError::Discord(_) => DISCORD_MESSAGE.to_owned(),
+            BotError::Validation(message) => message.clone(),
+            BotError::Internal(_) => INTERNAL_MESSAGE.to_owned(),
+        }
+    }
+}
+
+/// AniList failures keep their own messages whichever way they came
+impl From<MetadataError> for BotError {
+    fn from(why: MetadataError) -> Self {
+        match why {
+            MetadataError::AniList(why) => BotError::AniList(why),
+            why => BotError::Metadata(why),
+        }
+    }
+}
+
+impl From<QueryError> for BotError {
+    fn from(why: QueryError) -> Self {
+        BotError::Validation(why.to_string())
+    }
+}
+
+impl From<MalError> for BotError {
+    fn from(why: MalError) -> Self {
+        BotError::Validation(why.to_string())
+    }
+}
+
+/// The JSON error code Discord refused a request with, like 10008 for Unknown Message
+pub fn discord_error_code(why: &serenity::Error) -> Option<isize> {
+    match why {
+        serenity::Error::Http(why) => match &**why {
+            HttpError::UnsuccessfulRequest(response) => Some(response.error.code),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// What failed and who used it, for the logs and the owner digests
+#[derive(Debug, Clone)]
+pub struct ErrorOrigin {
+    pub command: String,
+    pub invoker: String,
+    pub channel_id: Option<ChannelId>,
+    pub guild_id: Option<GuildId>,
+}
+
+impl ErrorOrigin {
+    pub fn new(
+        command: impl Into<String>,
+        invoker: &User,
+        channel_id: Option<ChannelId>,
+        guild_id: Option<GuildId>,
+    ) -> Self {
+        ErrorOrigin {
+            command: command.into(),
+            invoker: invoker.tag(),
+            channel_id,
+            guild_id,
+        }
+    }
+}
+
+impl fmt::Display for ErrorOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} invoked by {}", self.command, self.invoker)?;
+        if let Some(channel_id) = self.channel_id {
+            write!(
+                f,
+                " from {}@{}",
+                channel_id,
+                self.guild_id
+                    .map_or("private".to_owned(), |id| id.0.to_string())
+            )?;
+        }
+        Ok(())
+    }
+}
+
+const EPHEMERAL: InteractionApplicationCommandCallbackDataFlags =
+    InteractionApplicationCommandCallbackDataFlags::EPHEMERAL;
+const STORAGE_MESSAGE: &str = "I could not reach the watch lists, try again in a bit";
+const DISCORD_MESSAGE: &str = "Discord refused that, check that I can send embeds here";
+const INTERNAL_MESSAGE: &str = "Something went wrong on my side, try again in a bit";
+
+fn anilist_message(why: &AniListError) -> String {
+    match why {
+        AniListError::NotFound => "AniList has nothing matching that".to_owned(),
+        AniListError::RateLimited => "AniList is busy, try again in a minute".to_owned(),
+        AniListError::Unauthorized => {
+            "AniList refused your token, link your account again with `link`".to_owned()
+        }
+        AniListError::InvalidQuery(message) => {
+            format!("AniList could not run that search: {}", message)
+        }
+        AniListError::Network(_)
+        | AniListError::Unavailable(_)
+        | AniListError::InvalidResponse(_) => {
+            "I could not reach AniList, try again in a bit".to_owned()
+        }
+    }
+}
+
+fn metadata_message(why: &MetadataError) -> String {
+    match why {
+        MetadataError::AniList(why) => anilist_message(why),
+        MetadataError::RateLimited(provider) => {
+            format!("{} is busy, try again in a minute", provider)
+        }
+        MetadataError::Unsupported(provider, action) => {
+            format!("{} can not {}, try without that filter", provider, action)
+        }
+        MetadataError::Network(provider, _)
+        | MetadataError::Unavailable(provider, _)
+        | MetadataError::InvalidResponse(provider, _) => {
+            format!("I could not reach {}, try again in a bit", provider)
+        }
+    }
+}
+
+/// The message for the first error in the chain the user can do something about, errors nothing
+/// is known about get a neutral one
+fn describe(why: &(dyn StdError + 'static)) -> String {
+    let mut source = Some(why);
+    while let Some(why) = source {
+        if let Some(why) = why.downcast_ref::<BotError>() {
+            return why.user_message();
+        }
+        if let Some(why) = why.downcast_ref::<AniListError>() {
+            return anilist_message(why);
+        }
+        if let Some(why) = why.downcast_ref::<MetadataError>() {
+            return metadata_message(why);
+        }
+        if why.is::<serenity::Error>() {
+            return DISCORD_MESSAGE.to_owned();
+        }
+        source = why.source();
+    }
+    INTERNAL_MESSAGE.to_owned()
+}
+
+/// `why` and everything that caused it, outermost first
+fn error_chain(why: &(dyn StdError + 'static)) -> Vec<String> {
+    let mut chain = vec![];
+    let mut source = Some(why);
+    while let Some(why) = source {
+        chain.push(why.to_string());
+        source = why.source();
+    }
+    chain
+}
+
+/// A short id to find the log line of an error a user reports
+fn correlation_id() -> String {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos(),
+    );
+    format!("{:06x}", hasher.finish() & 0xFF_FFFF)
+}
+
+/// Logs `why` under a new correlation id, queues it for the owners' digest unless the user just
+/// made a mistake, and builds the error embed telling the user about it
+pub async fn report(
+    ctx: &Context,
+    why: &(dyn StdError + Send + Sync + 'static),
+    origin: &ErrorOrigin,
+) -> CreateEmbed {
+    let id = correlation_id();
+    let description = describe(why);
+    if matches!(
+        why.downcast_ref::<BotError>(),
+        Some(BotError::Validation(_))
+    ) {
+        info!("[{}] Refused {}: {}", id, origin, why);
+    } else {
+        error!("[{}] Error in {}: {:?}", id, origin, why);
+        let chain = error_chain(why);
+        if let Some(owner_log) = ctx.data.read().await.get::<OwnerLogContainer>() {
+            owner_log.record(&id, origin, chain);
+        }
+    }
+
+    make_error_embed(|e| {
+        e.description(description)
+            .footer(|f| f.text(format!("Error id {}", id)))
+    })
+    .await
+}
+
+/// Reports a failed interaction to whoever used it, ephemeral where Discord allows it
+pub async fn report_interaction(ctx: &Context, interaction: &Interaction, why: CommandError) {
+    let result = match interaction {
+        Interaction::ApplicationCommand(command) => {
+            let origin = ErrorOrigin::new(
+                format!("/{}", command.data.name),
+                &command.user,
+                Some(command.channel_id),
+                command.guild_id,
+            );
+            let embed = report(ctx, why.as_ref(), &origin).await;
+            // deferred commands already answered, their answer is edited instead
+            match command
+                .create_interaction_response(&ctx.http, |resp| {
+                    resp.kind(InteractionResponseType::ChannelMessageWithSource)
+                        .interaction_response_data(|data| {
+                            data.ephemeral(true).set_embed(embed.clone())
+                        })
+                })
+                .await
+            {
+                Ok(()) => Ok(()),
+                Err(_) => command
+                    .edit_original_interaction_response(&ctx.http, |r| r.set_embed(embed))
+                    .await
+                    .map(|_| ()),
+            }
+        }
+        Interaction::MessageComponent(interaction) => {
+            let origin = ErrorOrigin::new(
+                format!("component {}", interaction.data.custom_id),
+                &interaction.user,
+                Some(interaction.channel_id),
+                interaction.guild_id,
+            );
+            let embed = report(ctx, why.as_ref(), &origin).await;
+            match interaction
+                .create_interaction_response(&ctx.http, |resp| {
+                    resp.kind(InteractionResponseType::ChannelMessageWithSource)
+                        .interaction_response_data(|data| {
+                            data.ephemeral(true).set_embed(embed.clone())
+                        })
+                })
+                .await
+            {
+                Ok(()) => Ok(()),
+                Err(_) => interaction
+                    .create_followup_message(&ctx.http, |m| m.flags(EPHEMERAL).add_embed(embed))
+                    .await
+                    .map(|_| ()),
+            }
+        }
+        Interaction::ModalSubmit(submission) => {
+            let origin = ErrorOrigin::new(
+                format!("modal {}", submission.data.custom_id),
+                &submission.user,
+                Some(submission.channel_id),
+                submission.guild_id,
+            );
+            let embed = report(ctx, why.as_ref(), &origin).await;
+            match submission
+                .create_interaction_response(&ctx.http, |resp| {
+                    resp.kind(InteractionResponseType::ChannelMessageWithSource)
+                        .interaction_response_data(|data| {
+                            data.ephemeral(true).set_embed(embed.clone())
+                        })
+                })
+                .await
+            {
+                Ok(()) => Ok(()),
+                Err(_) => submission
+                    .create_followup_message(&ctx.http, |m| m.flags(EPHEMERAL).add_embed(embed))
+                    .await
+                    .map(|_| ()),
+            }
+        }
+        // autocomplete has nowhere to show an error
+        Interaction::Autocomplete(autocomplete) => {
+            let origin = ErrorOrigin::new(
+                format!("autocomplete of /{}", autocomplete.data.name),
+                &autocomplete.user,
+                Some(autocomplete.channel_id),
+                autocomplete.guild_id,
+            );
+            report(ctx, why.as_ref(), &origin).await;
+            Ok(())
+        }
+        _ => {
+            error!("Error handling interaction: {:?}", why);
+            Ok(())
+        }
+    };
+    if let Err(why) = result {
+        error!("Could not report an interaction error: {:?}", why);
+    }
+}
@@ -0,0 +1,229 @@
+use std::{collections::HashMap, str::FromStr};
+
+use anyhow::Context;
+use mongodm::prelude::BsonDateTime;
+use serde::{Deserialize, Serialize};
+use serenity::model::id::UserId;
+
+use crate::{
+    db::watchlist::{WatchInfo, WatchStatus},
+    error::{BotError, BotResult},
+    mal,
+    media::{Media, MediaType},
+    metadata::MetadataProvider,
+};
+
+/// Bumped whenever `ExportEntry` changes shape, so old exports can still be read
+pub const EXPORT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Every field, the way it is stored
+    Json,
+    /// One row per entry with titles, for spreadsheets
+    Csv,
+    /// MyAnimeList's export format, one file per media type
+    MalXml,
+}
+
+impl FromStr for ExportFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            "xml" | "mal" => Ok(Self::MalXml),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A watch list entry as it is exported to JSON, dates are RFC 3339
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ExportEntry {
+    pub anilist_media_id: i64,
+    pub discord_user_id: UserId,
+    pub media_type: MediaType,
+    pub watch_status: WatchStatus,
+    pub last_watch_status: WatchStatus,
+    pub suggests: bool,
+    pub rating: Option<u8>,
+    pub progress: i64,
+    pub progress_volumes: i64,
+    pub repeat: i64,
+    pub updated_at: String,
+    pub created_at: String,
+}
+
+impl From<&WatchInfo> for ExportEntry {
+    fn from(info: &WatchInfo) -> Self {
+        ExportEntry {
+            anilist_media_id: info.anilist_media_id,
+            discord_user_id: info.discord_user_id,
+            media_type: info.media_type,
+            watch_status: info.watch_status,
+            last_watch_status: info.last_watch_status,
+            suggests: info.suggests,
+            rating: info.rating,
+            progress: info.progress,
+            progress_volumes: info.progress_volumes,
+            repeat: info.repeat,
+            updated_at: info.updated_at.to_rfc3339_string(),
+            created_at: info.created_at.to_rfc3339_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct JsonExport {
+    pub version: u32,
+    pub exported_at: String,
+    pub entries: Vec<ExportEntry>,
+}
+
+/// One entry as a spreadsheet row
+#[derive(Serialize)]
+struct CsvRow<'a> {
+    anilist_id: i64,
+    mal_id: Option<i64>,
+    title: String,
+    media_type: &'a str,
+    status: &'a str,
+    progress: i64,
+    total: Option<i64>,
+    volumes: i64,
+    rating: Option<u8>,
+    repeat: i64,
+    suggests: bool,
+    discord_user_id: u64,
+    updated_at: String,
+    created_at: String,
+}
+
+pub struct ExportFile {
+    pub filename: String,
+    pub data: Vec<u8>,
+}
+
+pub struct Export {
+    pub files: Vec<ExportFile>,
+    /// Entries the format has no room for, MAL XML leaves out media MAL does not have
+    pub left_out: usize,
+}
+
+/// Every entry, versioned and lossless
+pub fn to_json(entries: &[WatchInfo]) -> serde_json::Result<Vec<u8>> {
+    serde_json::to_vec_pretty(&JsonExport {
+        version: EXPORT_VERSION,
+        exported_at: BsonDateTime::now().to_rfc3339_string(),
+        entries: entries.iter().map(ExportEntry::from).collect(),
+    })
+}
+
+/// Every entry with its title, see `with_media`
+pub fn to_csv(entries: &[(WatchInfo, Option<Media>)]) -> anyhow::Result<Vec<u8>> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    for (info, media) in entries {
+        writer
+            .serialize(CsvRow {
+                anilist_id: info.anilist_media_id,
+                mal_id: media.as_ref().and_then(|media| media.id_mal),
+                title: media
+                    .as_ref()
+                    .map_or_else(String::new, |media| media.title.clone()),
+                media_type: match info.media_type {
+                    MediaType::Manga => "manga",
+                    _ => "anime",
+                },
+                status: info.watch_status.as_label(&info.media_type),
+                progress: info.progress,
+                total: media.as_ref().and_then(|media| media.total_progress()),
+                volumes: info.progress_volumes,
+                rating: info.rating,
+                repeat: info.repeat,
+                suggests: info.suggests,
+                discord_user_id: info.discord_user_id.0,
+                updated_at: info.updated_at.to_rfc3339_string(),
+                created_at: info.created_at.to_rfc3339_string(),
+            })
+            .context("Failed to write CSV row")?;
+    }
+    writer.into_inner().context("Failed to write CSV")
+}
+
+/// Pairs `entries` with their media, media that were removed are `None`
+pub async fn with_media(
+    metadata: &dyn MetadataProvider,
+    entries: Vec<WatchInfo>,
+) -> BotResult<Vec<(WatchInfo, Option<Media>)>> {
+    let mut media_ids = entries
+        .iter()
+        .map(|info| info.anilist_media_id)
+        .collect::<Vec<_>>();
+    media_ids.sort_unstable();
+    media_ids.dedup();
+
+    let media_by_id = metadata
+        .media_by_ids(&media_ids)
+        .await?
+        .into_iter()
+        .map(|media| (media.id, media))
+        .collect::<HashMap<_, _>>();
+
+    Ok(entries
+        .into_iter()
+        .map(|info| {
+            let media = media_by_id.get(&info.anilist_media_id).cloned();
+            (info, media)
+        })
+        .collect())
+}
+
+/// `entries` as the files of `format`, named after `name`
+pub async fn export(
+    metadata: &dyn MetadataProvider,
+    entries: Vec<WatchInfo>,
+    format: ExportFormat,
+    name: &str,
+) -> BotResult<Export> {
+    let export = match format {
+        ExportFormat::Json => Export {
+            files: vec![ExportFile {
+                filename: format!("{}.json", name),
+                data: to_json(&entries).map_err(|why| BotError::Internal(why.into()))?,
+            }],
+            left_out: 0,
+        },
+        ExportFormat::Csv => Export {
+            files: vec![ExportFile {
+                filename: format!("{}.csv", name),
+                data: to_csv(&with_media(metadata, entries).await?).map_err(BotError::Internal)?,
+            }],
+            left_out: 0,
+        },
+        ExportFormat::MalXml => {
+            let listed = entries
+                .iter()
+                .filter(|info| info.watch_status != WatchStatus::NotSeen)
+                .count();
+            let entries = with_media(metadata, entries).await?;
+            let mut export = Export {
+                files: vec![],
+                left_out: listed,
+            };
+            for (media_type, suffix) in [(MediaType::Anime, "anime"), (MediaType::Manga, "manga")] {
+                let (xml, written) = mal::write_export(&media_type, &entries);
+                if written > 0 {
+                    export.files.push(ExportFile {
+                        filename: format!("{}-{}.xml", name, suffix),
+                        data: xml.into_bytes(),
+                    });
+                    export.left_out -= written;
+                }
+            }
+            export
+        }
+    };
+    Ok(export)
+}
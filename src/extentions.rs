@@ -1,37 +1,120 @@
-use std::{env, fmt::Display};
+use std::{fmt::Display, sync::Arc};
 
 use crate::{
+    anilist::AniListClient,
+    db::{
+        guild_config::{GuildConfig, GuildConfigCollectionExt},
+        watchlist::WatchListStore,
+    },
     embeds::{make_blank_embed, make_error_embed, make_success_embed},
-    utils::DatabaseContainer,
+    error::{BotError, BotResult},
+    metadata::MetadataProvider,
+    suggestions::SuggestionCache,
+    sync::AniListSync,
+    utils::{
+        AniListContainer, AniListSyncContainer, DatabaseContainer, MetadataContainer,
+        SuggestionCacheContainer, WatchListContainer,
+    },
 };
 
 use anyhow::{Context, Result};
-use mongodm::prelude::MongoDatabase;
+use mongodm::{prelude::MongoDatabase, ToRepository};
 use serenity::{
     async_trait,
     builder::CreateEmbed,
     client,
     model::{
         channel::Message,
-        id::ChannelId,
-        interactions::{message_component::MessageComponentInteraction, InteractionResponseType},
+        id::{ChannelId, GuildId},
+        interactions::{
+            message_component::MessageComponentInteraction, modal::ModalSubmitInteraction,
+            InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+        },
     },
 };
 
+/// What users are told when they use a feature that is kept in MongoDB
+const NEEDS_MONGODB: &str = "That needs MongoDB, which this bot runs without";
+
 #[async_trait]
 pub trait ClientContextExt {
-    async fn get_db(&self) -> MongoDatabase;
+    /// Fails with a message for the user when the bot runs without MongoDB
+    async fn get_db(&self) -> BotResult<MongoDatabase>;
+    async fn get_watch_list(&self) -> Arc<dyn WatchListStore>;
+    async fn get_anilist(&self) -> Arc<AniListClient>;
+    async fn get_metadata(&self) -> Arc<dyn MetadataProvider>;
+    async fn get_suggestion_cache(&self) -> Arc<SuggestionCache>;
+    /// Fails like `get_db`, linked accounts are kept in MongoDB
+    async fn get_anilist_sync(&self) -> BotResult<Arc<AniListSync>>;
+    /// The config of the guild something happened in, `None` in DMs and unconfigured guilds
+    async fn get_guild_config(&self, guild_id: Option<GuildId>) -> Result<Option<GuildConfig>>;
 }
 
 #[async_trait]
 impl ClientContextExt for client::Context {
-    async fn get_db(&self) -> MongoDatabase {
+    async fn get_db(&self) -> BotResult<MongoDatabase> {
         self.data
             .read()
             .await
             .get::<DatabaseContainer>()
+            .cloned()
+            .ok_or_else(|| BotError::Validation(NEEDS_MONGODB.to_owned()))
+    }
+
+    async fn get_watch_list(&self) -> Arc<dyn WatchListStore> {
+        self.data
+            .read()
+            .await
+            .get::<WatchListContainer>()
             .unwrap()
-            .database(env::var("MONGODB_NAME").as_deref().unwrap_or("mokuroku"))
+            .clone()
+    }
+
+    async fn get_anilist(&self) -> Arc<AniListClient> {
+        self.data
+            .read()
+            .await
+            .get::<AniListContainer>()
+            .unwrap()
+            .clone()
+    }
+
+    async fn get_metadata(&self) -> Arc<dyn MetadataProvider> {
+        self.data
+            .read()
+            .await
+            .get::<MetadataContainer>()
+            .unwrap()
+            .clone()
+    }
+
+    async fn get_suggestion_cache(&self) -> Arc<SuggestionCache> {
+        self.data
+            .read()
+            .await
+            .get::<SuggestionCacheContainer>()
+            .unwrap()
+            .clone()
+    }
+
+    async fn get_anilist_sync(&self) -> BotResult<Arc<AniListSync>> {
+        self.data
+            .read()
+            .await
+            .get::<AniListSyncContainer>()
+            .cloned()
+            .ok_or_else(|| BotError::Validation(NEEDS_MONGODB.to_owned()))
+    }
+
+    async fn get_guild_config(&self, guild_id: Option<GuildId>) -> Result<Option<GuildConfig>> {
+        // without MongoDB every guild uses the defaults
+        let db = self.data.read().await.get::<DatabaseContainer>().cloned();
+        match (guild_id, db) {
+            (Some(guild_id), Some(db)) => {
+                db.repository::<GuildConfig>().find_config(guild_id).await
+            }
+            _ => Ok(None),
+        }
     }
 }
 
@@ -77,6 +160,13 @@ pub trait MessageComponentInteractionExt {
     ) -> Result<()>;
 
     async fn ack(&self, ctx: &client::Context) -> Result<()>;
+
+    /// Like `reply_error`, for interactions that were acked already
+    async fn followup_error(
+        &self,
+        ctx: &client::Context,
+        s: impl Display + Send + Sync + 'static,
+    ) -> Result<()>;
 }
 
 #[async_trait]
@@ -133,4 +223,73 @@ impl MessageComponentInteractionExt for MessageComponentInteraction {
         .await
         .context("Failed to send interaction ack")
     }
+
+    async fn followup_error(
+        &self,
+        ctx: &client::Context,
+        s: impl Display + Send + Sync + 'static,
+    ) -> Result<()> {
+        let embed = make_error_embed(|e| e.description(s)).await;
+        self.create_followup_message(&ctx, |m| {
+            m.flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                .add_embed(embed)
+        })
+        .await
+        .context("Failed to send interaction error followup")?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+pub trait ModalSubmitInteractionExt {
+    async fn reply_error(
+        &self,
+        ctx: &client::Context,
+        s: impl Display + Send + Sync + 'static,
+    ) -> Result<()>;
+
+    async fn reply_success(
+        &self,
+        ctx: &client::Context,
+        s: impl Display + Send + Sync + 'static,
+        title: impl Display + Send + Sync + 'static,
+    ) -> Result<()>;
+}
+
+#[async_trait]
+impl ModalSubmitInteractionExt for ModalSubmitInteraction {
+    async fn reply_error(
+        &self,
+        ctx: &client::Context,
+        s: impl Display + Send + Sync + 'static,
+    ) -> Result<()> {
+        let embed = make_error_embed(|e| e.description(s)).await;
+        self.create_interaction_response(&ctx, |resp| {
+            resp.kind(InteractionResponseType::ChannelMessageWithSource);
+            resp.interaction_response_data(|data| {
+                data.ephemeral(true);
+                data.set_embed(embed)
+            })
+        })
+        .await
+        .context("Failed to send modal error reply")
+    }
+
+    async fn reply_success(
+        &self,
+        ctx: &client::Context,
+        s: impl Display + Send + Sync + 'static,
+        title: impl Display + Send + Sync + 'static,
+    ) -> Result<()> {
+        let embed = make_success_embed(|e| e.description(s).title(title)).await;
+        self.create_interaction_response(&ctx, |resp| {
+            resp.kind(InteractionResponseType::ChannelMessageWithSource);
+            resp.interaction_response_data(|data| {
+                data.ephemeral(true);
+                data.set_embed(embed)
+            })
+        })
+        .await
+        .context("Failed to send modal success reply")
+    }
 }
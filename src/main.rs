@@ -1,22 +1,67 @@
+pub mod anilist;
 pub mod commands;
+pub mod custom_id;
 pub mod db;
 pub mod embeds;
+pub mod error;
+pub mod export;
 pub mod extentions;
 pub mod graphql;
+pub mod mal;
+pub mod media;
+pub mod metadata;
+pub mod notifier;
+pub mod owner_log;
 pub mod paginator;
+pub mod query;
 pub mod strings;
+pub mod suggestions;
+pub mod sync;
 pub mod tests;
 pub mod utils;
 
+#[cfg(feature = "sqlite")]
+use crate::db::sqlite::SqliteWatchListStore;
 use crate::{
-    commands::{anime::lookup::*, help::*},
-    db::watchlist::WatchInfoCollConf,
-    utils::{DatabaseContainer, ShardManagerContainer},
+    anilist::AniListClient,
+    commands::{
+        anime::lookup::*,
+        components,
+        config::*,
+        help::*,
+        slash,
+        watchlist::{
+            export::*, import::*, link::*, list::*, notify::*, progress::*, suggestions::*,
+        },
+    },
+    db::{
+        anilist_link::{AniListLink, AniListLinkCollConf},
+        guild_config::GuildConfigCollConf,
+        media::{CachedMedia, CachedMediaCollConf},
+        migrations,
+        migrations::MigrationCollConf,
+        notifications::{AnnouncementCollConf, NotificationSettingsCollConf},
+        watchlist::{WatchInfo, WatchInfoCollConf, WatchListStore},
+    },
+    embeds::make_error_embed,
+    error::ErrorOrigin,
+    extentions::ClientContextExt,
+    metadata::{
+        anilist::AniListProvider, cache::CachedProvider, FallbackProvider, MetadataProvider,
+    },
+    owner_log::{OwnerLog, OwnerLogTarget},
+    suggestions::SuggestionCache,
+    sync::{AniListSync, SyncedWatchListStore},
+    utils::{
+        AniListContainer, AniListSyncContainer, DatabaseContainer, GuildPrefixContainer,
+        MetadataContainer, OwnerLogContainer, OwnersContainer, PrefixContainer,
+        ShardManagerContainer, SuggestionCacheContainer, WatchListContainer,
+    },
 };
 
 use mongodm::{
-    prelude::{MongoClient, MongoClientOptions},
-    sync_indexes,
+    prelude::{MongoClient, MongoClientOptions, MongoDatabase},
+    sync_indexes, ToRepository,
 };
 use serenity::{
     async_trait,
@@ -24,25 +69,65 @@ use serenity::{
     framework::{
         standard::{
             macros::{group, hook},
-            CommandError,
+            CommandError, CommandGroup, DispatchError,
         },
         StandardFramework,
     },
     http::Http,
-    model::{channel::Message, event::ResumedEvent, gateway::Ready},
+    model::{
+        channel::Message, event::ResumedEvent, gateway::Ready, id::ChannelId,
+        interactions::Interaction,
+    },
     prelude::{Context, GatewayIntents},
     utils::validate_token,
     Client,
 };
-use std::{collections::HashSet, env};
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    sync::Arc,
+    time::Duration,
+};
 use tracing::{debug, error, info, instrument};
 
+/// How long interactions wait on the AniList rate limit, Discord wants an answer in 3 seconds
+const INTERACTION_MAX_WAIT: Duration = Duration::from_secs(2);
+
 struct Handler;
 
 #[async_trait]
 impl EventHandler for Handler {
-    async fn ready(&self, _: Context, ready: Ready) {
+    async fn ready(&self, ctx: Context, ready: Ready) {
         info!("Connected as {}!", ready.user.name);
+
+        if let Err(why) = slash::register(&ctx).await {
+            error!("Could not register slash commands: {:?}", why);
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        let handle = async {
+            match interaction.clone() {
+                Interaction::ApplicationCommand(command) => {
+                    slash::handle_command(&ctx, command).await
+                }
+                Interaction::Autocomplete(autocomplete) => {
+                    slash::handle_autocomplete(&ctx, autocomplete).await
+                }
+                Interaction::MessageComponent(interaction) => {
+                    components::handle_component(&ctx, interaction).await
+                }
+                Interaction::ModalSubmit(submission) => {
+                    components::handle_modal(&ctx, submission).await
+                }
+                _ => Ok(()),
+            }
+        };
+        // Discord drops interactions that are not answered in time, so rate limits fail them
+        let result = anilist::with_max_wait(INTERACTION_MAX_WAIT, handle).await;
+        if let Err(why) = result {
+            error::report_interaction(&ctx, &interaction, why).await;
+        }
     }
 
     #[instrument(skip(self, _ctx))]
@@ -52,34 +137,260 @@ impl EventHandler for Handler {
 }
 
 #[group]
-#[commands(help, lookup)]
+#[description("Look up anime and manga")]
+#[commands(lookup, manga)]
+struct Media;
+
+#[group("Watch list")]
+#[description("Keep track of what you watch and read")]
+#[commands(list, progress, notify, suggestions, import, export, link)]
+struct WatchList;
+
+#[group]
+#[description("Help and server settings")]
+#[commands(help, config)]
 struct General;
 
+/// Every command group, in the order `help` lists them
+pub static GROUPS: &[&CommandGroup] = &[&MEDIA_GROUP, &WATCHLIST_GROUP, &GENERAL_GROUP];
+
 #[hook]
 #[instrument]
-async fn after(_: &Context, msg: &Message, cmd_name: &str, error: Result<(), CommandError>) {
+async fn after(ctx: &Context, msg: &Message, cmd_name: &str, error: Result<(), CommandError>) {
     if let Err(why) = error {
-        error!(
-            "Error in {} invoked by {} from {}@{}: {:?}",
-            cmd_name,
-            msg.author.tag(),
-            msg.channel_id,
-            msg.guild_id
-                .map_or("private".to_owned(), |id| id.0.to_string()),
-            why
-        );
+        let origin = ErrorOrigin::new(cmd_name, &msg.author, Some(msg.channel_id), msg.guild_id);
+        let embed = error::report(ctx, why.as_ref(), &origin).await;
+        if let Err(why) = msg
+            .channel_id
+            .send_message(&ctx.http, |m| m.set_embed(embed))
+            .await
+        {
+            error!("Could not send command error: {:?}", why);
+        }
     }
 }
 
 #[hook]
 #[instrument]
-async fn before(_: &Context, msg: &Message, command_name: &str) -> bool {
+async fn before(ctx: &Context, msg: &Message, command_name: &str) -> bool {
     info!(
         "Got command '{}' by user '{}'",
         command_name,
         msg.author.tag()
     );
-    true
+
+    // commands the guild turned off are ignored
+    match ctx.get_guild_config(msg.guild_id).await {
+        Ok(Some(config)) => config.is_enabled(command_name),
+        Ok(None) => true,
+        Err(why) => {
+            error!("Could not read the guild config: {:?}", why);
+            true
+        }
+    }
+}
+
+#[hook]
+async fn dispatch_error(ctx: &Context, msg: &Message, error: DispatchError, _: &str) {
+    let description = match error {
+        DispatchError::LackingPermissions(_) => "You need the Manage Server permission for that",
+        DispatchError::OnlyForGuilds => "That only works in a server",
+        _ => return,
+    };
+    let embed = make_error_embed(|e| e.description(description)).await;
+    if let Err(why) = msg
+        .channel_id
+        .send_message(&ctx.http, |m| m.set_embed(embed))
+        .await
+    {
+        error!("Could not send dispatch error: {:?}", why);
+    }
+}
+
+/// The prefix a guild set with `config`, cached after the first message
+#[hook]
+async fn guild_prefix(ctx: &Context, msg: &Message) -> Option<String> {
+    let guild_id = msg.guild_id?;
+    if let Some(prefix) = ctx
+        .data
+        .read()
+        .await
+        .get::<GuildPrefixContainer>()
+        .and_then(|prefixes| prefixes.get(&guild_id))
+    {
+        return prefix.clone();
+    }
+
+    let prefix = match ctx.get_guild_config(Some(guild_id)).await {
+        Ok(config) => config.and_then(|config| config.prefix),
+        Err(why) => {
+            // not cached, so the next message tries again
+            error!("Could not read the guild prefix: {:?}", why);
+            return None;
+        }
+    };
+    if let Some(prefixes) = ctx.data.write().await.get_mut::<GuildPrefixContainer>() {
+        prefixes.insert(guild_id, prefix.clone());
+    }
+    prefix
+}
+
+/// Connects to the `MONGODB_NAME` database of the cluster at `mongo_uri`
+async fn open_mongo(mongo_uri: &str) -> MongoDatabase {
+    let mongo_database = env::var("MONGODB_NAME")
+        .as_deref()
+        .unwrap_or("mokuroku")
+        .to_owned();
+    let mongo_options = MongoClientOptions::parse(mongo_uri)
+        .await
+        .expect("Couldn't parse the Mongo URI");
+    let mongo =
+        MongoClient::with_options(mongo_options).expect("Couldn't instantiate mongo client");
+    mongo.database(&mongo_database)
+}
+
+/// Syncs the indexes of every collection and migrates existing documents
+async fn prepare_mongo(db: &MongoDatabase) {
+    // sync mongo indexes
+    sync_indexes::<WatchInfoCollConf>(db)
+        .await
+        .expect("Can not sync indexes for Watchinfo collection");
+    sync_indexes::<MigrationCollConf>(db)
+        .await
+        .expect("Can not sync indexes for Migration collection");
+    sync_indexes::<NotificationSettingsCollConf>(db)
+        .await
+        .expect("Can not sync indexes for NotificationSettings collection");
+    sync_indexes::<AnnouncementCollConf>(db)
+        .await
+        .expect("Can not sync indexes for Announcement collection");
+    sync_indexes::<GuildConfigCollConf>(db)
+        .await
+        .expect("Can not sync indexes for GuildConfig collection");
+    sync_indexes::<AniListLinkCollConf>(db)
+        .await
+        .expect("Can not sync indexes for AniListLink collection");
+    sync_indexes::<CachedMediaCollConf>(db)
+        .await
+        .expect("Can not sync indexes for CachedMedia collection");
+
+    // bring existing documents up to date
+    migrations::migrate(db)
+        .await
+        .expect("Can not migrate the database");
+}
+
+/// The watch list backend `WATCH_LIST_URI` points to, MongoDB if it is unset
+fn open_watch_list(db: Option<&MongoDatabase>) -> Arc<dyn WatchListStore> {
+    match env::var("WATCH_LIST_URI") {
+        Ok(uri) if uri.starts_with("sqlite://") => open_sqlite_watch_list(&uri),
+        Ok(uri) if uri != "mongodb" => panic!("Unknown WATCH_LIST_URI {}", uri),
+        _ => Arc::new(
+            db.expect("Expected a MONGODB_URI, or a sqlite:// WATCH_LIST_URI")
+                .repository::<WatchInfo>()
+                .get_underlying(),
+        ),
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn open_sqlite_watch_list(uri: &str) -> Arc<dyn WatchListStore> {
+    let path = uri.trim_start_matches("sqlite://");
+    Arc::new(SqliteWatchListStore::open(path).expect("Can not open the SQLite watch list"))
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn open_sqlite_watch_list(_: &str) -> Arc<dyn WatchListStore> {
+    panic!("SQLite watch lists need a build with `--features sqlite`")
+}
+
+/// Copies the MongoDB `watch-list` collection into the SQLite database of `WATCH_LIST_URI`
+#[cfg(feature = "sqlite")]
+async fn copy_watch_list(db: &MongoDatabase) -> anyhow::Result<usize> {
+    use anyhow::Context as _;
+    use serenity::futures::TryStreamExt;
+
+    let path = env::var("WATCH_LIST_URI")
+        .ok()
+        .and_then(|uri| uri.strip_prefix("sqlite://").map(str::to_owned))
+        .context("WATCH_LIST_URI must be a sqlite:// URI to copy into")?;
+    let entries = db
+        .repository::<WatchInfo>()
+        .find(mongodm::doc! {}, None)
+        .await
+        .context("Failed to find watch list")?
+        .try_collect::<Vec<_>>()
+        .await
+        .context("Failed to read watch list")?;
+    SqliteWatchListStore::open(&path)?.import(entries).await
+}
+
+/// The AniList client, pointed at `ANILIST_API_URL` if it is set. Linking accounts needs the
+/// `ANILIST_CLIENT_ID` of an AniList API client.
+fn open_anilist() -> AniListClient {
+    let mut anilist = AniListClient::new();
+    if let Ok(api_url) = env::var("ANILIST_API_URL") {
+        anilist = anilist.with_api_url(api_url);
+    }
+    if let Ok(client_id) = env::var("ANILIST_CLIENT_ID") {
+        anilist = anilist.with_oauth_client_id(client_id);
+    }
+    anilist
+}
+
+/// The providers `METADATA_PROVIDERS` lists, the first is asked first and the others are
+/// fallen back on in order. Just AniList if it is unset.
+fn open_metadata(anilist: Arc<AniListClient>) -> Arc<dyn MetadataProvider> {
+    let names = env::var("METADATA_PROVIDERS").unwrap_or_else(|_| "anilist".to_owned());
+    let providers = names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| match name.to_lowercase().as_str() {
+            "anilist" => {
+                Arc::new(AniListProvider::new(anilist.clone())) as Arc<dyn MetadataProvider>
+            }
+            "kitsu" => open_kitsu(),
+            "jikan" => open_jikan(),
+            _ => panic!("Unknown metadata provider {}", name),
+        })
+        .collect::<Vec<_>>();
+    Arc::new(FallbackProvider::new(providers))
+}
+
+#[cfg(feature = "kitsu")]
+fn open_kitsu() -> Arc<dyn MetadataProvider> {
+    Arc::new(metadata::kitsu::KitsuProvider::new())
+}
+
+#[cfg(not(feature = "kitsu"))]
+fn open_kitsu() -> Arc<dyn MetadataProvider> {
+    panic!("The Kitsu metadata provider needs a build with `--features kitsu`")
+}
+
+#[cfg(feature = "jikan")]
+fn open_jikan() -> Arc<dyn MetadataProvider> {
+    Arc::new(metadata::jikan::JikanProvider::new())
+}
+
+#[cfg(not(feature = "jikan"))]
+fn open_jikan() -> Arc<dyn MetadataProvider> {
+    panic!("The Jikan metadata provider needs a build with `--features jikan`")
+}
+
+/// Writes every watch list to `path`, as CSV if it ends in `.csv` and as JSON otherwise
+async fn export_watch_lists(db: Option<&MongoDatabase>, path: &str) -> anyhow::Result<usize> {
+    use anyhow::Context as _;
+
+    let entries = open_watch_list(db).find_every_entry().await?;
+    let data = if path.ends_with(".csv") {
+        let metadata = open_metadata(Arc::new(open_anilist()));
+        export::to_csv(&export::with_media(metadata.as_ref(), entries.clone()).await?)?
+    } else {
+        export::to_json(&entries)?
+    };
+    std::fs::write(path, data).with_context(|| format!("Failed to write {}", path))?;
+    Ok(entries.len())
 }
 
 #[tokio::main]
@@ -91,19 +402,45 @@ async fn main() {
     // reads RUST_LOG env
     tracing_subscriber::fmt::init();
 
-    let mongo_uri = env::var("MONGODB_URI").expect("Expected a MONGO_URI in the environment");
-    let mongo_database = env::var("MONGODB_NAME")
-        .as_deref()
-        .unwrap_or("mokuroku")
-        .to_owned();
     let token = env::var("DISCORD_TOKEN").expect("Expected a token in the environment");
     let prefix = env::var("BOT_PREFIX")
         .as_deref()
         .unwrap_or("mr~")
         .to_owned();
 
+    // SQLite watch lists do without MongoDB, the features kept in it are turned off then
+    let db = match env::var("MONGODB_URI") {
+        Ok(mongo_uri) => Some(open_mongo(&mongo_uri).await),
+        Err(_) => None,
+    };
+
+    // one-shot copy of the watch list into the SQLite database, the bot is not started
+    #[cfg(feature = "sqlite")]
+    if env::args().nth(1).as_deref() == Some("copy-watch-list") {
+        let db = db.expect("Expected a MONGODB_URI to copy the watch list from");
+        match copy_watch_list(&db).await {
+            Ok(copied) => info!("Copied {} watch list entries", copied),
+            Err(why) => error!("Could not copy the watch list: {:?}", why),
+        }
+        return;
+    }
+
+    // one-shot backup of every watch list, the bot is not started
+    if env::args().nth(1).as_deref() == Some("export-watch-list") {
+        let path = env::args()
+            .nth(2)
+            .unwrap_or_else(|| "mokuroku-backup.json".to_owned());
+        match export_watch_lists(db.as_ref(), &path).await {
+            Ok(exported) => info!("Exported {} watch list entries to {}", exported, path),
+            Err(why) => error!("Could not export the watch lists: {:?}", why),
+        }
+        return;
+    }
+
     // Set gateway intents, which decides what events the bot will be notified about
-    let intents = GatewayIntents::GUILD_MESSAGES
+    let intents = GatewayIntents::GUILDS
+        | GatewayIntents::GUILD_MEMBERS
+        | GatewayIntents::GUILD_MESSAGES
         | GatewayIntents::DIRECT_MESSAGES
         | GatewayIntents::MESSAGE_CONTENT
         | GatewayIntents::GUILD_MESSAGE_REACTIONS
@@ -124,10 +461,27 @@ async fn main() {
         Err(why) => panic!("Could not access application info: {:?}", why),
     };
 
+    // command errors are sent to the owners as digests
+    let owner_log_target = match env::var("OWNER_LOG_CHANNEL_ID") {
+        Ok(channel_id) => OwnerLogTarget::Channel(ChannelId(
+            channel_id
+                .parse()
+                .expect("OWNER_LOG_CHANNEL_ID must be a channel id"),
+        )),
+        Err(_) => OwnerLogTarget::Owner(*owners.iter().next().expect("The bot has no owner")),
+    };
+
     let framework = StandardFramework::new()
-        .configure(|c| c.owners(owners).prefix(prefix))
+        .configure(|c| {
+            c.owners(owners.clone())
+                .prefix(&prefix)
+                .dynamic_prefix(guild_prefix)
+        })
         .before(before)
         .after(after)
+        .on_dispatch_error(dispatch_error)
+        .group(&MEDIA_GROUP)
+        .group(&WATCHLIST_GROUP)
         .group(&GENERAL_GROUP);
 
     let mut client = Client::builder(&token, intents)
@@ -136,22 +490,92 @@ async fn main() {
         .await
         .expect("Error creating client");
 
-    // initiate mongo client
-    let mongo_options = MongoClientOptions::parse(mongo_uri)
-        .await
-        .expect("Couldn't parse the Mongo URI");
-    let mongo =
-        MongoClient::with_options(mongo_options).expect("Couldn't instantiate mongo client");
+    if let Some(db) = &db {
+        prepare_mongo(db).await;
+    }
 
-    // sync mongo indexes
-    sync_indexes::<WatchInfoCollConf>(&mongo.database(&mongo_database))
-        .await
-        .expect("Can not sync indexes for Watchinfo collection");
+    let anilist = Arc::new(open_anilist());
+    let store = open_watch_list(db.as_ref());
+    let mut metadata = open_metadata(anilist.clone());
+    // lookups by id are answered from the `media` collection once a provider answered them
+    let media_cache = db.as_ref().map(|db| {
+        Arc::new(CachedProvider::new(
+            metadata.clone(),
+            db.repository::<CachedMedia>().get_underlying(),
+            store.clone(),
+        ))
+    });
+    if let Some(media_cache) = &media_cache {
+        metadata = media_cache.clone();
+    }
+    let sync = db.as_ref().map(|db| {
+        Arc::new(AniListSync::new(
+            store.clone(),
+            db.repository::<AniListLink>().get_underlying(),
+            anilist.clone(),
+        ))
+    });
+    // changes made through the bot are mirrored to linked AniList accounts
+    let watch_list: Arc<dyn WatchListStore> = match &sync {
+        Some(sync) => Arc::new(SyncedWatchListStore::new(store, sync.clone())),
+        None => store,
+    };
+    let owner_log = Arc::new(OwnerLog::new());
+    let suggestion_cache = Arc::new(SuggestionCache::new());
 
     {
         let mut data = client.data.write().await;
         data.insert::<ShardManagerContainer>(client.shard_manager.clone());
-        data.insert::<DatabaseContainer>(mongo.clone());
+        if let Some(db) = &db {
+            data.insert::<DatabaseContainer>(db.clone());
+        }
+        if let Some(sync) = &sync {
+            data.insert::<AniListSyncContainer>(sync.clone());
+        }
+        data.insert::<WatchListContainer>(watch_list.clone());
+        data.insert::<AniListContainer>(anilist.clone());
+        data.insert::<MetadataContainer>(metadata.clone());
+        data.insert::<OwnerLogContainer>(owner_log.clone());
+        data.insert::<SuggestionCacheContainer>(suggestion_cache.clone());
+        data.insert::<GuildPrefixContainer>(HashMap::new());
+        data.insert::<PrefixContainer>(prefix);
+        data.insert::<OwnersContainer>(owners);
+    }
+
+    // send error digests in the background
+    tokio::spawn(owner_log::run(
+        client.cache_and_http.http.clone(),
+        owner_log,
+        owner_log_target,
+    ));
+
+    if let Some(db) = db {
+        // keep suggestion boards up to date in the background
+        tokio::spawn(suggestions::run(
+            client.cache_and_http.clone(),
+            db.clone(),
+            watch_list.clone(),
+            metadata.clone(),
+            suggestion_cache,
+        ));
+
+        // announce aired episodes in the background
+        tokio::spawn(notifier::run(
+            client.cache_and_http.http.clone(),
+            db,
+            watch_list,
+            metadata,
+        ));
+    }
+
+    // pull linked AniList lists in the background
+    if let Some(sync) = sync {
+        tokio::spawn(sync.run());
+    }
+
+    // refresh stale cached media in the background
+    if let Some(media_cache) = media_cache {
+        tokio::spawn(media_cache.run());
     }
 
     let shard_manager = client.shard_manager.clone();
@@ -0,0 +1,403 @@
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+    fmt::{Display, Write},
+    io::Read,
+};
+
+use flate2::read::GzDecoder;
+use mongodm::prelude::BsonDateTime;
+use roxmltree::{Document, Node};
+use serenity::model::id::UserId;
+use thiserror::Error;
+
+use crate::{
+    anilist::{AniListClient, AniListError},
+    db::watchlist::{WatchInfo, WatchStatus, MAX_RATING},
+    error::BotResult,
+    graphql::{find_mal_media, FindMalMedia},
+    media::{Media, MediaType},
+};
+
+/// AniList pages hold at most 50 media
+const MAL_IDS_PER_QUERY: usize = 50;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum MalError {
+    #[error(
+        "That is not a MyAnimeList export, attach the `.xml` or `.xml.gz` file from \
+        https://myanimelist.net/panel.php?go=export"
+    )]
+    NotAnExport,
+    #[error("I could not read the export: {0}")]
+    InvalidXml(String),
+}
+
+/// One `<anime>` or `<manga>` entry of a MyAnimeList export
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MalEntry {
+    pub mal_id: i64,
+    pub media_type: MediaType,
+    pub title: String,
+    pub status: WatchStatus,
+    /// `None` when the entry is not scored
+    pub score: Option<u8>,
+    pub progress: i64,
+    pub progress_volumes: i64,
+    pub repeat: i64,
+}
+
+#[derive(Debug, Default)]
+pub struct MalExport {
+    pub entries: Vec<MalEntry>,
+    /// Entries without an id or with a status MAL does not have
+    pub invalid: usize,
+}
+
+/// Parses a MyAnimeList export, gzipped the way MAL hands it out or already unpacked
+pub fn parse_export(file: &[u8]) -> Result<MalExport, MalError> {
+    let mut text = String::new();
+    if file.starts_with(&[0x1f, 0x8b]) {
+        GzDecoder::new(file)
+            .read_to_string(&mut text)
+            .map_err(|_| MalError::NotAnExport)?;
+    } else {
+        text = String::from_utf8(file.to_vec()).map_err(|_| MalError::NotAnExport)?;
+    }
+
+    let document = Document::parse(&text).map_err(|why| MalError::InvalidXml(why.to_string()))?;
+    let root = document.root_element();
+    if !root.has_tag_name("myanimelist") {
+        return Err(MalError::NotAnExport);
+    }
+
+    let mut export = MalExport::default();
+    for node in root.children().filter(Node::is_element) {
+        let entry = match node.tag_name().name() {
+            "anime" => parse_entry(node, MediaType::Anime),
+            "manga" => parse_entry(node, MediaType::Manga),
+            _ => continue,
+        };
+        match entry {
+            Some(entry) => export.entries.push(entry),
+            None => export.invalid += 1,
+        }
+    }
+    Ok(export)
+}
+
+fn parse_entry(node: Node, media_type: MediaType) -> Option<MalEntry> {
+    let (id_tag, title_tag, progress_tag, repeat_tag, repeating_tag) = match media_type {
+        MediaType::Manga => (
+            "manga_mangadb_id",
+            "manga_title",
+            "my_read_chapters",
+            "my_times_read",
+            "my_rereading",
+        ),
+        _ => (
+            "series_animedb_id",
+            "series_title",
+            "my_watched_episodes",
+            "my_times_watched",
+            "my_rewatching",
+        ),
+    };
+    let mal_id = child_text(node, id_tag)?.parse().ok()?;
+    let mut status = parse_status(child_text(node, "my_status")?)?;
+    if child_number(node, repeating_tag) > 0 {
+        status = WatchStatus::Repeating;
+    }
+    let score = u8::try_from(child_number(node, "my_score"))
+        .ok()
+        .filter(|score| (1..=MAX_RATING).contains(score));
+
+    Some(MalEntry {
+        mal_id,
+        media_type,
+        title: child_text(node, title_tag).unwrap_or_default().to_owned(),
+        status,
+        score,
+        progress: child_number(node, progress_tag),
+        // anime entries have none
+        progress_volumes: child_number(node, "my_read_volumes"),
+        repeat: child_number(node, repeat_tag),
+    })
+}
+
+/// Exports spell the status out, older ones number it
+fn parse_status(status: &str) -> Option<WatchStatus> {
+    match status.to_lowercase().as_str() {
+        "watching" | "reading" | "1" => Some(WatchStatus::Consuming),
+        "completed" | "2" => Some(WatchStatus::Finished),
+        "on-hold" | "3" => Some(WatchStatus::Paused),
+        "dropped" | "4" => Some(WatchStatus::Dropped),
+        "plan to watch" | "plan to read" | "6" => Some(WatchStatus::Planning),
+        _ => None,
+    }
+}
+
+/// Writes the `media_type` entries as an export MAL and AniList import, with how many were
+/// written. Entries without a MAL id and ones that are only suggested are left out.
+pub fn write_export(
+    media_type: &MediaType,
+    entries: &[(WatchInfo, Option<Media>)],
+) -> (String, usize) {
+    let is_manga = *media_type == MediaType::Manga;
+    let mut xml = "<?xml version=\"1.0\" encoding=\"UTF-8\" ?>\n<myanimelist>\n".to_owned();
+    xml.push_str("\t<myinfo>\n");
+    write_tag(&mut xml, "user_export_type", if is_manga { 2 } else { 1 });
+    xml.push_str("\t</myinfo>\n");
+
+    let mut written = 0;
+    for (info, media) in entries {
+        if info.media_type != *media_type || info.watch_status == WatchStatus::NotSeen {
+            continue;
+        }
+        let (mal_id, media) = match media {
+            Some(media) => match media.id_mal {
+                Some(mal_id) => (mal_id, media),
+                None => continue,
+            },
+            None => continue,
+        };
+        let score = info.rating.unwrap_or_default();
+        let repeating = (info.watch_status == WatchStatus::Repeating) as u8;
+        let status = match info.watch_status {
+            WatchStatus::Consuming if is_manga => "Reading",
+            WatchStatus::Consuming => "Watching",
+            // MAL marks rewatches on completed entries
+            WatchStatus::Finished | WatchStatus::Repeating => "Completed",
+            WatchStatus::Paused => "On-Hold",
+            WatchStatus::Dropped => "Dropped",
+            WatchStatus::Planning if is_manga => "Plan to Read",
+            _ => "Plan to Watch",
+        };
+
+        if is_manga {
+            xml.push_str("\t<manga>\n");
+            write_tag(&mut xml, "manga_mangadb_id", mal_id);
+            write_tag(&mut xml, "manga_title", cdata(&media.title));
+            write_tag(
+                &mut xml,
+                "manga_chapters",
+                media.chapters.unwrap_or_default(),
+            );
+            write_tag(&mut xml, "my_read_volumes", info.progress_volumes);
+            write_tag(&mut xml, "my_read_chapters", info.progress);
+            write_tag(&mut xml, "my_score", score);
+            write_tag(&mut xml, "my_status", status);
+            write_tag(&mut xml, "my_times_read", info.repeat);
+            write_tag(&mut xml, "my_rereading", repeating);
+            write_tag(&mut xml, "update_on_import", 1);
+            xml.push_str("\t</manga>\n");
+        } else {
+            xml.push_str("\t<anime>\n");
+            write_tag(&mut xml, "series_animedb_id", mal_id);
+            write_tag(&mut xml, "series_title", cdata(&media.title));
+            write_tag(
+                &mut xml,
+                "series_episodes",
+                media.episodes.unwrap_or_default(),
+            );
+            write_tag(&mut xml, "my_watched_episodes", info.progress);
+            write_tag(&mut xml, "my_score", score);
+            write_tag(&mut xml, "my_status", status);
+            write_tag(&mut xml, "my_times_watched", info.repeat);
+            write_tag(&mut xml, "my_rewatching", repeating);
+            write_tag(&mut xml, "update_on_import", 1);
+            xml.push_str("\t</anime>\n");
+        }
+        written += 1;
+    }
+    xml.push_str("</myanimelist>\n");
+    (xml, written)
+}
+
+fn write_tag(xml: &mut String, name: &str, value: impl Display) {
+    // writing to a `String` can not fail
+    let _ = writeln!(xml, "\t\t<{0}>{1}</{0}>", name, value);
+}
+
+/// Titles are free text, a `]]>` in one is split over two sections
+fn cdata(text: &str) -> String {
+    format!("<![CDATA[{}]]>", text.replace("]]>", "]]]]><![CDATA[>"))
+}
+
+fn child_text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.children()
+        .find(|child| child.has_tag_name(name))
+        .and_then(|child| child.text())
+        .map(str::trim)
+}
+
+fn child_number(node: Node, name: &str) -> i64 {
+    child_text(node, name)
+        .and_then(|text| text.parse::<i64>().ok())
+        .unwrap_or_default()
+        .max(0)
+}
+
+/// AniList ids by MAL id, MAL numbers anime and manga separately
+#[derive(Debug, Default)]
+pub struct MalIds {
+    anime: HashMap<i64, i64>,
+    manga: HashMap<i64, i64>,
+}
+
+impl MalIds {
+    pub fn insert(&mut self, media_type: &MediaType, mal_id: i64, anilist_id: i64) {
+        match media_type {
+            MediaType::Manga => self.manga.insert(mal_id, anilist_id),
+            _ => self.anime.insert(mal_id, anilist_id),
+        };
+    }
+
+    pub fn anilist_id(&self, entry: &MalEntry) -> Option<i64> {
+        match entry.media_type {
+            MediaType::Manga => self.manga.get(&entry.mal_id).copied(),
+            _ => self.anime.get(&entry.mal_id).copied(),
+        }
+    }
+}
+
+/// Looks the AniList ids of `entries` up, `MAL_IDS_PER_QUERY` at a time
+pub async fn resolve_ids(client: &AniListClient, entries: &[MalEntry]) -> BotResult<MalIds> {
+    let mut ids = MalIds::default();
+    for media_type in [MediaType::Anime, MediaType::Manga] {
+        let mut mal_ids = entries
+            .iter()
+            .filter(|entry| entry.media_type == media_type)
+            .map(|entry| entry.mal_id)
+            .collect::<Vec<_>>();
+        mal_ids.sort_unstable();
+        mal_ids.dedup();
+
+        for chunk in mal_ids.chunks(MAL_IDS_PER_QUERY) {
+            let response = client
+                .query::<FindMalMedia>(find_mal_media::Variables {
+                    mal_ids: Some(chunk.iter().copied().map(Some).collect()),
+                    media_type: Some(media_type.into()),
+                })
+                .await?;
+
+            // Check for errors
+            if let Some(errors) = response.errors {
+                return Err(AniListError::from_graphql(&errors).into());
+            }
+
+            let media = response
+                .data
+                .and_then(|data| data.page)
+                .and_then(|page| page.media)
+                .unwrap_or_default();
+            for media in media.into_iter().flatten() {
+                if let Some(mal_id) = media.id_mal {
+                    ids.insert(&media_type, mal_id, media.id);
+                }
+            }
+        }
+    }
+    Ok(ids)
+}
+
+/// An entry an import adds or changes
+#[derive(Debug, Clone)]
+pub struct ImportChange {
+    pub title: String,
+    /// `None` for entries the watch list does not have yet
+    pub before: Option<WatchInfo>,
+    pub after: WatchInfo,
+}
+
+/// What an import would do to a watch list, saved unless it is a dry run
+#[derive(Debug, Default)]
+pub struct ImportPlan {
+    pub changes: Vec<ImportChange>,
+    /// Entries that are already the same, repeated or invalid
+    pub skipped: usize,
+    /// Entries AniList has no media for
+    pub unresolved: Vec<MalEntry>,
+}
+
+impl ImportPlan {
+    pub fn imported(&self) -> usize {
+        self.changes
+            .iter()
+            .filter(|change| change.before.is_none())
+            .count()
+    }
+
+    pub fn updated(&self) -> usize {
+        self.changes.len() - self.imported()
+    }
+
+    /// The entries to save
+    pub fn into_entries(self) -> Vec<WatchInfo> {
+        self.changes
+            .into_iter()
+            .map(|change| change.after)
+            .collect()
+    }
+}
+
+/// Diffs `export` against the `existing` entries of `user_id`. Statuses and progress come from
+/// MAL, suggestions and ratings MAL does not have are kept and rewatches are never lost.
+pub fn plan_import(
+    export: MalExport,
+    ids: &MalIds,
+    existing: Vec<WatchInfo>,
+    user_id: UserId,
+    now: BsonDateTime,
+) -> ImportPlan {
+    let mut existing = existing
+        .into_iter()
+        .map(|info| (info.anilist_media_id, info))
+        .collect::<HashMap<_, _>>();
+    let mut seen = HashSet::new();
+    let mut plan = ImportPlan {
+        skipped: export.invalid,
+        ..ImportPlan::default()
+    };
+
+    for entry in export.entries {
+        let media_id = match ids.anilist_id(&entry) {
+            Some(media_id) => media_id,
+            None => {
+                plan.unresolved.push(entry);
+                continue;
+            }
+        };
+        // AniList merged a few media MAL keeps apart
+        if !seen.insert(media_id) {
+            plan.skipped += 1;
+            continue;
+        }
+
+        let before = existing.remove(&media_id);
+        let mut after = before
+            .clone()
+            .unwrap_or_else(|| WatchInfo::new(media_id, user_id, entry.media_type, now));
+        after.change_status(entry.status);
+        if entry.score.is_some() {
+            after.rating = entry.score;
+        }
+        after.progress = entry.progress;
+        if entry.media_type == MediaType::Manga {
+            after.progress_volumes = entry.progress_volumes;
+        }
+        after.repeat = after.repeat.max(entry.repeat);
+
+        if before.as_ref() == Some(&after) {
+            plan.skipped += 1;
+            continue;
+        }
+        after.updated_at = now;
+        plan.changes.push(ImportChange {
+            title: entry.title,
+            before,
+            after,
+        });
+    }
+    plan
+}
@@ -0,0 +1,344 @@
+use std::fmt;
+
+use html2md::parse_html;
+use mongodm::prelude::Bson;
+use serde::{Deserialize, Serialize};
+use serenity::{builder::CreateEmbed, utils::Colour};
+
+use crate::{
+    db::watchlist::{WatchInfo, MAX_RATING},
+    strings::card,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum MediaType {
+    Anime,
+    Manga,
+}
+
+impl From<MediaType> for Bson {
+    fn from(val: MediaType) -> Self {
+        match val {
+            MediaType::Anime => Bson::String("ANIME".to_owned()),
+            MediaType::Manga => Bson::String("MANGA".to_owned()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum MediaFormat {
+    Tv,
+    TvShort,
+    Movie,
+    Special,
+    Ova,
+    Ona,
+    Music,
+    Manga,
+    Novel,
+    OneShot,
+}
+
+impl fmt::Display for MediaFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tv => write!(f, "📺 Tv"),
+            Self::TvShort => write!(f, "📺 Tv Short"),
+            Self::Movie => write!(f, "🎥 Movie"),
+            Self::Special => write!(f, "🌟 Special"),
+            Self::Ova => write!(f, "💽 OVA"),
+            Self::Ona => write!(f, "💻 ONA"),
+            Self::Music => write!(f, "🎵 Music"),
+            Self::Manga => write!(f, "📔 Manga"),
+            Self::Novel => write!(f, "📙 Novel"),
+            Self::OneShot => write!(f, "📄 One Shot"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum MediaStatus {
+    Finished,
+    Releasing,
+    NotYetReleased,
+    Cancelled,
+    Hiatus,
+}
+
+impl fmt::Display for MediaStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotYetReleased => write!(f, "Not Released Yet"),
+            Self::Hiatus => write!(f, "Hiatus"),
+            Self::Finished => write!(f, "Finished"),
+            Self::Cancelled => write!(f, "Cancelled"),
+            Self::Releasing => write!(f, "Releasing"),
+        }
+    }
+}
+
+/// How search results are ordered, the most of it first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaSort {
+    Score,
+    Popularity,
+    Trending,
+    Newest,
+    Oldest,
+    Title,
+    Favourites,
+}
+
+/// A date any part of which may not be known yet
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FuzzyDate {
+    pub year: Option<i64>,
+    pub month: Option<i64>,
+    pub day: Option<i64>,
+}
+
+impl FuzzyDate {
+    /// Parses the `2023-09-29` at the start of a date or timestamp
+    pub fn parse(date: &str) -> Self {
+        let mut parts = date
+            .get(..10)
+            .unwrap_or(date)
+            .split('-')
+            .map(|part| part.parse().ok());
+        FuzzyDate {
+            year: parts.next().flatten(),
+            month: parts.next().flatten(),
+            day: parts.next().flatten(),
+        }
+    }
+}
+
+impl fmt::Display for FuzzyDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.year, self.month, self.day) {
+            (Some(year), Some(month), Some(day)) => write!(f, "{}-{}-{}", day, month, year),
+            _ => write!(f, "?"),
+        }
+    }
+}
+
+/// An anime or manga as every metadata provider describes it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Media {
+    /// The AniList id, what watch lists are keyed by whichever provider found the media
+    pub id: i64,
+    pub id_mal: Option<i64>,
+    pub site_url: Option<String>,
+    pub title: String,
+    pub media_type: MediaType,
+    pub format: Option<MediaFormat>,
+    pub status: Option<MediaStatus>,
+    pub genres: Vec<String>,
+    pub start_date: Option<FuzzyDate>,
+    pub end_date: Option<FuzzyDate>,
+    pub cover_image: Option<String>,
+    /// Only AniList knows airing schedules
+    pub next_airing: Option<NextAiring>,
+    /// Minutes per episode
+    pub duration: Option<i64>,
+    pub episodes: Option<i64>,
+    pub chapters: Option<i64>,
+    pub volumes: Option<i64>,
+    /// Out of 100
+    pub average_score: Option<i64>,
+    /// HTML, plain text from providers that have no markup
+    pub description: Option<String>,
+}
+
+impl Media {
+    /// A media nothing but the title is known of
+    pub fn new(id: i64, media_type: MediaType, title: impl Into<String>) -> Self {
+        Media {
+            id,
+            id_mal: None,
+            site_url: None,
+            title: title.into(),
+            media_type,
+            format: None,
+            status: None,
+            genres: vec![],
+            start_date: None,
+            end_date: None,
+            cover_image: None,
+            next_airing: None,
+            duration: None,
+            episodes: None,
+            chapters: None,
+            volumes: None,
+            average_score: None,
+            description: None,
+        }
+    }
+
+    /// Episodes for anime, chapters for manga
+    pub fn total_progress(&self) -> Option<i64> {
+        match self.media_type {
+            MediaType::Manga => self.chapters,
+            MediaType::Anime => self.episodes,
+        }
+    }
+
+    /// The title as a link to the media's page, if it has one
+    pub fn title_link(&self) -> String {
+        match &self.site_url {
+            Some(url) => format!("[{}]({})", self.title, url),
+            None => self.title.clone(),
+        }
+    }
+
+    pub fn embed_card(&self, watch_info: Option<&WatchInfo>) -> CreateEmbed {
+        let mut embed = CreateEmbed::default();
+        embed.title(&self.title);
+        embed.color(Colour::new(0x345A78));
+        if let Some(thumbnail) = &self.cover_image {
+            embed.thumbnail(thumbnail);
+        }
+        embed.description(parse_html(
+            self.description.as_deref().unwrap_or("No description"),
+        ));
+        embed.field(
+            card::STATUS,
+            self.status
+                .map_or_else(|| "Unknown".to_owned(), |status| status.to_string()),
+            true,
+        );
+        embed.field(
+            card::FORMAT,
+            self.format
+                .map_or_else(|| "Unknown".to_owned(), |format| format.to_string()),
+            true,
+        );
+        let is_manga = self.media_type == MediaType::Manga;
+        embed.fields(vec![
+            (
+                card::GENRES,
+                if self.genres.is_empty() {
+                    "?".to_owned()
+                } else {
+                    self.genres.join(", ")
+                },
+                false,
+            ),
+            (
+                if is_manga {
+                    card::PUBLISHED
+                } else {
+                    card::AIRED
+                },
+                format!(
+                    "**{}** to **{}**",
+                    self.start_date.unwrap_or_default(),
+                    self.end_date.unwrap_or_default()
+                ),
+                false,
+            ),
+        ]);
+        if is_manga {
+            embed.fields(vec![
+                (
+                    card::CHAPTERS,
+                    self.chapters
+                        .map_or_else(|| "?".to_owned(), |chapters| chapters.to_string()),
+                    true,
+                ),
+                (
+                    card::VOLUMES,
+                    self.volumes
+                        .map_or_else(|| "?".to_owned(), |volumes| volumes.to_string()),
+                    true,
+                ),
+            ]);
+        } else {
+            embed.fields(vec![
+                (
+                    card::EPISODES,
+                    self.episodes
+                        .map_or_else(|| "?".to_owned(), |episodes| episodes.to_string()),
+                    true,
+                ),
+                (
+                    card::DURATION,
+                    format!(
+                        "{} min",
+                        self.duration
+                            .map_or_else(|| "?".to_owned(), |duration| duration.to_string())
+                    ),
+                    true,
+                ),
+            ]);
+        }
+        embed.field(
+            card::RATING,
+            format!(
+                "**{}/100**",
+                self.average_score
+                    .map_or_else(|| "?".to_owned(), |rating| rating.to_string())
+            ),
+            true,
+        );
+        if let Some(info) = watch_info {
+            if info.progress > 0 || info.watch_status.is_active() {
+                embed.field(
+                    card::PROGRESS,
+                    format!("**{}**", info.progress_text(self.total_progress())),
+                    true,
+                );
+            }
+            if let Some(rating) = info.rating {
+                embed.field(
+                    card::USER_RATING,
+                    format!("**{}/{}**", rating, MAX_RATING),
+                    true,
+                );
+            }
+        }
+
+        embed
+    }
+}
+
+/// One page of search results
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaPage {
+    pub media: Vec<Media>,
+    pub current_page: i64,
+    pub last_page: i64,
+    pub has_next_page: bool,
+}
+
+impl Default for MediaPage {
+    fn default() -> Self {
+        MediaPage {
+            media: vec![],
+            current_page: 1,
+            last_page: 1,
+            has_next_page: false,
+        }
+    }
+}
+
+/// The next episode of a releasing anime
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NextAiring {
+    pub episode: i64,
+    /// Unix seconds
+    pub airing_at: i64,
+}
+
+/// An episode that aired, `id` tells announcements of the same airing apart
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AiredEpisode {
+    pub id: i64,
+    pub media_id: i64,
+    pub episode: i64,
+    /// Unix seconds
+    pub airing_at: i64,
+}
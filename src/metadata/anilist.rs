@@ -0,0 +1,379 @@
+use std::{convert::TryFrom, sync::Arc};
+
+use serenity::async_trait;
+
+use crate::{
+    anilist::{AniListClient, AniListError},
+    graphql::{
+        aired_episodes, find_media, list_media, lookup_media_page, AiredEpisodes, FindMedia,
+        ListMedia, LookupMediaPage,
+    },
+    media::{
+        AiredEpisode, FuzzyDate, Media, MediaFormat, MediaPage, MediaSort, MediaStatus, MediaType,
+        NextAiring,
+    },
+};
+
+use super::{MediaLookup, MediaSearch, MetadataError, MetadataProvider};
+
+/// AniList pages hold at most 50 media
+const MEDIA_PER_QUERY: usize = 50;
+
+/// The GraphQL API the rest of the bot is built around, the only provider with airing schedules
+pub struct AniListProvider {
+    client: Arc<AniListClient>,
+}
+
+impl AniListProvider {
+    pub fn new(client: Arc<AniListClient>) -> Self {
+        AniListProvider { client }
+    }
+}
+
+#[async_trait]
+impl MetadataProvider for AniListProvider {
+    fn name(&self) -> &'static str {
+        "AniList"
+    }
+
+    async fn search(&self, search: &MediaSearch) -> Result<MediaPage, MetadataError> {
+        let response = self
+            .client
+            .query::<LookupMediaPage>(search_variables(search))
+            .await?;
+
+        // Check for errors
+        if let Some(errors) = response.errors {
+            return Err(AniListError::from_graphql(&errors).into());
+        }
+
+        let page = response
+            .data
+            .and_then(|data| data.page)
+            .ok_or(AniListError::NotFound)?;
+        let page_info = page.page_info.unwrap_or_default();
+        Ok(MediaPage {
+            media: page
+                .media
+                .unwrap_or_default()
+                .into_iter()
+                .flatten()
+                .map(Media::from)
+                .collect(),
+            current_page: page_info.current_page,
+            last_page: page_info.last_page,
+            has_next_page: page_info.has_next_page,
+        })
+    }
+
+    async fn media(&self, lookup: &MediaLookup) -> Result<Option<Media>, MetadataError> {
+        // an unknown id comes back as a "Not Found." error without media
+        let response = self
+            .client
+            .query::<FindMedia>(find_media::Variables {
+                id: lookup.id,
+                id_mal: lookup.id_mal,
+                media_type: Some(lookup.media_type.into()),
+                is_adult: lookup.is_adult,
+            })
+            .await?;
+        let media = match response.data.and_then(|data| data.media) {
+            Some(media) => media,
+            None => match response.errors.as_deref().map(AniListError::from_graphql) {
+                Some(AniListError::NotFound) | None => return Ok(None),
+                Some(why) => return Err(why.into()),
+            },
+        };
+        let media = lookup_media_page::LookupMediaPagePageMedia::try_from(media)
+            .map_err(AniListError::from)?;
+        Ok(Some(media.into()))
+    }
+
+    async fn media_by_ids(&self, ids: &[i64]) -> Result<Vec<Media>, MetadataError> {
+        let mut found = vec![];
+        for chunk in ids.chunks(MEDIA_PER_QUERY) {
+            let response = self
+                .client
+                .query::<ListMedia>(list_media::Variables {
+                    ids: Some(chunk.iter().copied().map(Some).collect()),
+                    per_page: Some(MEDIA_PER_QUERY as i64),
+                })
+                .await?;
+
+            // Check for errors
+            if let Some(errors) = response.errors {
+                return Err(AniListError::from_graphql(&errors).into());
+            }
+
+            let media = response
+                .data
+                .and_then(|data| data.page)
+                .and_then(|page| page.media)
+                .unwrap_or_default();
+            for media in media.into_iter().flatten() {
+                let media = lookup_media_page::LookupMediaPagePageMedia::try_from(media)
+                    .map_err(AniListError::from)?;
+                found.push(media.into());
+            }
+        }
+        Ok(found)
+    }
+
+    async fn aired_episodes(
+        &self,
+        media_ids: &[i64],
+        aired_after: i64,
+        aired_before: i64,
+    ) -> Result<Vec<AiredEpisode>, MetadataError> {
+        let mut aired_episodes = vec![];
+        let mut page = 1;
+        loop {
+            let response = self
+                .client
+                .query::<AiredEpisodes>(aired_episodes::Variables {
+                    media_ids: Some(media_ids.iter().copied().map(Some).collect()),
+                    aired_after: Some(aired_after),
+                    aired_before: Some(aired_before),
+                    page,
+                })
+                .await?;
+
+            // Check for errors
+            if let Some(errors) = response.errors {
+                return Err(AniListError::from_graphql(&errors).into());
+            }
+
+            let page_data = response.data.and_then(|data| data.page);
+            let has_next_page = page_data
+                .as_ref()
+                .and_then(|page| page.page_info.as_ref())
+                .map(|page_info| page_info.has_next_page)
+                .unwrap_or(false);
+            aired_episodes.extend(
+                page_data
+                    .and_then(|page| page.airing_schedules)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .flatten()
+                    .map(|episode| AiredEpisode {
+                        id: episode.id,
+                        media_id: episode.media_id,
+                        episode: episode.episode,
+                        airing_at: episode.airing_at,
+                    }),
+            );
+
+            if !has_next_page {
+                return Ok(aired_episodes);
+            }
+            page += 1;
+        }
+    }
+}
+
+fn search_variables(search: &MediaSearch) -> lookup_media_page::Variables {
+    let mut variables = lookup_media_page::Variables {
+        media_type: search.media_type.into(),
+        page: search.page,
+        per_page: Some(search.per_page),
+        search: search.search.clone(),
+        is_adult: search.is_adult,
+        status: search.status.map(Into::into),
+        sort: search.sort.map(|sort| vec![Some(sort.into())]),
+        ..lookup_media_page::Variables::default()
+    };
+    if let Some(year) = search.year {
+        // manga have no seasons, match their start date instead
+        match search.media_type {
+            MediaType::Manga => variables.start_date_like = Some(format!("{}%", year)),
+            MediaType::Anime => variables.season_year = Some(year),
+        }
+    }
+    if !search.formats.is_empty() {
+        variables.format_in = Some(
+            search
+                .formats
+                .iter()
+                .map(|&format| Some(format.into()))
+                .collect(),
+        );
+    }
+    if !search.genres.is_empty() {
+        variables.genre_in = Some(search.genres.iter().cloned().map(Some).collect());
+    }
+    variables
+}
+
+/// The title the user prefers on AniList, falling back on the English and native ones
+fn preferred_title(titles: &[Option<String>]) -> String {
+    titles
+        .iter()
+        .flatten()
+        .next()
+        .cloned()
+        .unwrap_or_else(|| "?".to_owned())
+}
+
+impl From<lookup_media_page::LookupMediaPagePageMedia> for Media {
+    fn from(media: lookup_media_page::LookupMediaPagePageMedia) -> Self {
+        let title = media.title.map_or_else(
+            || "?".to_owned(),
+            |title| {
+                preferred_title(&[
+                    title.user_preferred,
+                    title.english,
+                    title.romaji,
+                    title.native,
+                ])
+            },
+        );
+        Media {
+            id: media.id,
+            id_mal: media.id_mal,
+            site_url: media.site_url,
+            title,
+            media_type: media.media_type.map_or(MediaType::Anime, Into::into),
+            format: media
+                .format
+                .and_then(|format| MediaFormat::try_from(format).ok()),
+            status: media
+                .status
+                .and_then(|status| MediaStatus::try_from(status).ok()),
+            genres: media
+                .genres
+                .unwrap_or_default()
+                .into_iter()
+                .flatten()
+                .collect(),
+            start_date: media.start_date.map(|date| FuzzyDate {
+                year: date.year,
+                month: date.month,
+                day: date.day,
+            }),
+            end_date: media.end_date.map(|date| FuzzyDate {
+                year: date.year,
+                month: date.month,
+                day: date.day,
+            }),
+            cover_image: media.cover_image.and_then(|cover| cover.medium),
+            next_airing: media.next_airing_episode.map(|next| NextAiring {
+                episode: next.episode,
+                airing_at: next.airing_at,
+            }),
+            duration: media.duration,
+            episodes: media.episodes,
+            chapters: media.chapters,
+            volumes: media.volumes,
+            average_score: media.average_score,
+            description: media.description,
+        }
+    }
+}
+
+impl From<lookup_media_page::MediaType> for MediaType {
+    fn from(val: lookup_media_page::MediaType) -> Self {
+        match val {
+            lookup_media_page::MediaType::Manga => MediaType::Manga,
+            _ => MediaType::Anime,
+        }
+    }
+}
+
+impl From<MediaType> for lookup_media_page::MediaType {
+    fn from(val: MediaType) -> Self {
+        match val {
+            MediaType::Anime => Self::Anime,
+            MediaType::Manga => Self::Manga,
+        }
+    }
+}
+
+impl From<MediaType> for find_media::MediaType {
+    fn from(val: MediaType) -> Self {
+        match val {
+            MediaType::Anime => Self::Anime,
+            MediaType::Manga => Self::Manga,
+        }
+    }
+}
+
+/// Formats AniList added after the schema was generated are left unknown
+impl TryFrom<lookup_media_page::MediaFormat> for MediaFormat {
+    type Error = ();
+
+    fn try_from(val: lookup_media_page::MediaFormat) -> Result<Self, Self::Error> {
+        use lookup_media_page::MediaFormat as Format;
+        match val {
+            Format::Tv => Ok(Self::Tv),
+            Format::TvShort => Ok(Self::TvShort),
+            Format::Movie => Ok(Self::Movie),
+            Format::Special => Ok(Self::Special),
+            Format::Ova => Ok(Self::Ova),
+            Format::Ona => Ok(Self::Ona),
+            Format::Music => Ok(Self::Music),
+            Format::Manga => Ok(Self::Manga),
+            Format::Novel => Ok(Self::Novel),
+            Format::OneShot => Ok(Self::OneShot),
+            Format::Other(_) => Err(()),
+        }
+    }
+}
+
+impl From<MediaFormat> for lookup_media_page::MediaFormat {
+    fn from(val: MediaFormat) -> Self {
+        match val {
+            MediaFormat::Tv => Self::Tv,
+            MediaFormat::TvShort => Self::TvShort,
+            MediaFormat::Movie => Self::Movie,
+            MediaFormat::Special => Self::Special,
+            MediaFormat::Ova => Self::Ova,
+            MediaFormat::Ona => Self::Ona,
+            MediaFormat::Music => Self::Music,
+            MediaFormat::Manga => Self::Manga,
+            MediaFormat::Novel => Self::Novel,
+            MediaFormat::OneShot => Self::OneShot,
+        }
+    }
+}
+
+impl TryFrom<lookup_media_page::MediaStatus> for MediaStatus {
+    type Error = ();
+
+    fn try_from(val: lookup_media_page::MediaStatus) -> Result<Self, Self::Error> {
+        use lookup_media_page::MediaStatus as Status;
+        match val {
+            Status::Finished => Ok(Self::Finished),
+            Status::Releasing => Ok(Self::Releasing),
+            Status::NotYetReleased => Ok(Self::NotYetReleased),
+            Status::Cancelled => Ok(Self::Cancelled),
+            Status::Hiatus => Ok(Self::Hiatus),
+            Status::Other(_) => Err(()),
+        }
+    }
+}
+
+impl From<MediaStatus> for lookup_media_page::MediaStatus {
+    fn from(val: MediaStatus) -> Self {
+        match val {
+            MediaStatus::Finished => Self::Finished,
+            MediaStatus::Releasing => Self::Releasing,
+            MediaStatus::NotYetReleased => Self::NotYetReleased,
+            MediaStatus::Cancelled => Self::Cancelled,
+            MediaStatus::Hiatus => Self::Hiatus,
+        }
+    }
+}
+
+impl From<MediaSort> for lookup_media_page::MediaSort {
+    fn from(val: MediaSort) -> Self {
+        match val {
+            MediaSort::Score => Self::ScoreDesc,
+            MediaSort::Popularity => Self::PopularityDesc,
+            MediaSort::Trending => Self::TrendingDesc,
+            MediaSort::Newest => Self::StartDateDesc,
+            MediaSort::Oldest => Self::StartDate,
+            MediaSort::Title => Self::TitleRomaji,
+            MediaSort::Favourites => Self::FavouritesDesc,
+        }
+    }
+}
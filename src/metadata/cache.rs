@@ -0,0 +1,244 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use mongodm::prelude::{BsonDateTime, MongoCollection};
+use serenity::async_trait;
+use tracing::{error, info, warn};
+
+use crate::{
+    db::{
+        media::{CachedMedia, CachedMediaCollectionExt},
+        watchlist::WatchListStore,
+    },
+    media::{AiredEpisode, Media, MediaPage},
+};
+
+use super::{MediaLookup, MediaSearch, MetadataError, MetadataProvider};
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// Media refreshed per `REFRESH_INTERVAL`, one `media_by_ids` query on AniList
+const REFRESH_BATCH: i64 = 50;
+/// How long media the providers no longer know are left alone
+const MISSING_RETRY_MILLIS: i64 = 7 * 24 * 60 * 60 * 1000;
+/// How stale `read_at` may get before a read moves it, so lookups don't each cost a write
+const READ_MARK_MILLIS: i64 = 24 * 60 * 60 * 1000;
+
+/// Keeps every media `provider` hands out in the `media` collection, and answers lookups by
+/// AniList id from it. Cache failures are logged, the provider is asked instead.
+pub struct CachedProvider {
+    provider: Arc<dyn MetadataProvider>,
+    cache: MongoCollection<CachedMedia>,
+    /// Media on watch lists are refreshed even when nobody looked them up
+    watch_list: Arc<dyn WatchListStore>,
+}
+
+impl CachedProvider {
+    pub fn new(
+        provider: Arc<dyn MetadataProvider>,
+        cache: MongoCollection<CachedMedia>,
+        watch_list: Arc<dyn WatchListStore>,
+    ) -> Self {
+        CachedProvider {
+            provider,
+            cache,
+            watch_list,
+        }
+    }
+
+    /// Caches `media` in the background, lookups don't wait for it
+    fn save(&self, media: &[Media]) {
+        if media.is_empty() {
+            return;
+        }
+        let now = BsonDateTime::now();
+        let media = media
+            .iter()
+            .map(|media| CachedMedia::new(media.clone(), now))
+            .collect::<Vec<_>>();
+        let cache = self.cache.clone();
+        tokio::spawn(async move {
+            let count = media.len();
+            if let Err(why) = cache.save_media(media).await {
+                warn!("Could not cache {} media: {:?}", count, why);
+            }
+        });
+    }
+
+    async fn cached(&self, media_ids: &[i64]) -> Vec<Media> {
+        match self.cache.find_cached_media(media_ids).await {
+            Ok(cached) => {
+                self.mark_read(&cached);
+                cached.into_iter().map(|cached| cached.media).collect()
+            }
+            Err(why) => {
+                warn!("Could not read cached media: {:?}", why);
+                vec![]
+            }
+        }
+    }
+
+    /// Moves `read_at` of the `cached` media that were not read for a while, in the background
+    fn mark_read(&self, cached: &[CachedMedia]) {
+        let now = BsonDateTime::now();
+        let media_ids = cached
+            .iter()
+            .filter(|cached| {
+                cached.read_at.timestamp_millis() + READ_MARK_MILLIS < now.timestamp_millis()
+            })
+            .map(|cached| cached.anilist_media_id)
+            .collect::<Vec<_>>();
+        if media_ids.is_empty() {
+            return;
+        }
+        let cache = self.cache.clone();
+        tokio::spawn(async move {
+            if let Err(why) = cache.mark_read(&media_ids, now).await {
+                warn!(
+                    "Could not mark {} cached media read: {:?}",
+                    media_ids.len(),
+                    why
+                );
+            }
+        });
+    }
+
+    /// Fetches the media that are due for a refresh again, and drops those nobody lists or
+    /// looks up anymore
+    async fn refresh(&self) -> anyhow::Result<()> {
+        let now = BsonDateTime::now();
+        let stale = self.cache.find_stale_media(now, REFRESH_BATCH).await?;
+        if stale.is_empty() {
+            return Ok(());
+        }
+        let stale_ids = stale
+            .iter()
+            .map(|cached| cached.anilist_media_id)
+            .collect::<Vec<_>>();
+        let listed = self
+            .watch_list
+            .find_listed_media(&stale_ids)
+            .await?
+            .into_iter()
+            .collect::<HashSet<_>>();
+        let (stale, unused): (Vec<_>, Vec<_>) = stale.into_iter().partition(|cached| {
+            cached.worth_refreshing(listed.contains(&cached.anilist_media_id), now)
+        });
+        if !unused.is_empty() {
+            let unused = unused
+                .iter()
+                .map(|cached| cached.anilist_media_id)
+                .collect::<Vec<_>>();
+            info!("Dropping {} unused cached media", unused.len());
+            self.cache.delete_media(&unused).await?;
+        }
+        if stale.is_empty() {
+            return Ok(());
+        }
+
+        let media_ids = stale
+            .iter()
+            .map(|cached| cached.anilist_media_id)
+            .collect::<Vec<_>>();
+        let read_at = stale
+            .iter()
+            .map(|cached| (cached.anilist_media_id, cached.read_at))
+            .collect::<HashMap<_, _>>();
+        let media = self.provider.media_by_ids(&media_ids).await?;
+        // refreshing is no read, the media keep when they were last looked up
+        let fetched = media
+            .iter()
+            .map(|media| CachedMedia {
+                read_at: read_at.get(&media.id).copied().unwrap_or(now),
+                ..CachedMedia::new(media.clone(), now)
+            })
+            .collect::<Vec<_>>();
+        self.cache.save_media(fetched).await?;
+
+        let found = media.iter().map(|media| media.id).collect::<HashSet<_>>();
+        let missing = media_ids
+            .into_iter()
+            .filter(|media_id| !found.contains(media_id))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            info!("Providers no longer know media {:?}", missing);
+            let retry_after =
+                BsonDateTime::from_millis(now.timestamp_millis() + MISSING_RETRY_MILLIS);
+            self.cache.postpone_refresh(&missing, retry_after).await?;
+        }
+        Ok(())
+    }
+
+    /// Refreshes stale media, every `REFRESH_INTERVAL`
+    pub async fn run(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(REFRESH_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(why) = self.refresh().await {
+                error!("Could not refresh cached media: {:?}", why);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl MetadataProvider for CachedProvider {
+    fn name(&self) -> &'static str {
+        self.provider.name()
+    }
+
+    async fn search(&self, search: &MediaSearch) -> Result<MediaPage, MetadataError> {
+        let page = self.provider.search(search).await?;
+        self.save(&page.media);
+        Ok(page)
+    }
+
+    async fn media(&self, lookup: &MediaLookup) -> Result<Option<Media>, MetadataError> {
+        // the cache does not know which media are adult, those lookups go to the provider
+        if let (Some(id), None) = (lookup.id, lookup.is_adult) {
+            if let Some(media) = self
+                .cached(&[id])
+                .await
+                .into_iter()
+                .find(|media| media.media_type == lookup.media_type)
+            {
+                return Ok(Some(media));
+            }
+        }
+
+        let media = self.provider.media(lookup).await?;
+        if let Some(media) = &media {
+            self.save(std::slice::from_ref(media));
+        }
+        Ok(media)
+    }
+
+    async fn media_by_ids(&self, ids: &[i64]) -> Result<Vec<Media>, MetadataError> {
+        let mut media = self.cached(ids).await;
+        let cached = media.iter().map(|media| media.id).collect::<HashSet<_>>();
+        let missing = ids
+            .iter()
+            .copied()
+            .filter(|id| !cached.contains(id))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            let fetched = self.provider.media_by_ids(&missing).await?;
+            self.save(&fetched);
+            media.extend(fetched);
+        }
+        Ok(media)
+    }
+
+    async fn aired_episodes(
+        &self,
+        media_ids: &[i64],
+        aired_after: i64,
+        aired_before: i64,
+    ) -> Result<Vec<AiredEpisode>, MetadataError> {
+        self.provider
+            .aired_episodes(media_ids, aired_after, aired_before)
+            .await
+    }
+}
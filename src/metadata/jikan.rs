@@ -0,0 +1,425 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use reqwest::Client;
+use serde::Deserialize;
+use serenity::{
+    async_trait,
+    futures::{stream, StreamExt, TryStreamExt},
+};
+
+use crate::{
+    media::{
+        AiredEpisode, FuzzyDate, Media, MediaFormat, MediaPage, MediaSort, MediaStatus, MediaType,
+    },
+    strings::url::JIKAN_API,
+};
+
+use super::{
+    get_json, kitsu::KitsuProvider, MediaLookup, MediaSearch, MetadataError, MetadataProvider,
+};
+
+const NAME: &str = "Jikan";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Jikan allows 60 requests a minute, spread out so bursts stay under its 3 a second
+const REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+/// Jikan requests in flight at once, `REQUEST_INTERVAL` still spaces out when they are sent
+const MAX_CONCURRENT_REQUESTS: usize = 3;
+/// Jikan pages hold at most 25 media
+const MAX_PER_PAGE: i64 = 25;
+
+#[derive(Deserialize, Debug)]
+struct Data<T> {
+    data: T,
+}
+
+#[derive(Deserialize, Debug)]
+struct SearchPage {
+    data: Vec<JikanMedia>,
+    pagination: Pagination,
+}
+
+#[derive(Deserialize, Debug)]
+struct Pagination {
+    current_page: i64,
+    last_visible_page: i64,
+    has_next_page: bool,
+}
+
+#[derive(Deserialize, Debug)]
+struct JikanMedia {
+    mal_id: i64,
+    url: Option<String>,
+    images: Option<Images>,
+    title: Option<String>,
+    title_english: Option<String>,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    status: Option<String>,
+    /// Anime air, manga are published
+    aired: Option<DateRange>,
+    published: Option<DateRange>,
+    /// Like `"24 min per ep"` or `"1 hr 52 min"`
+    duration: Option<String>,
+    episodes: Option<i64>,
+    chapters: Option<i64>,
+    volumes: Option<i64>,
+    /// Out of 10
+    score: Option<f64>,
+    synopsis: Option<String>,
+    #[serde(default)]
+    genres: Vec<Genre>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Images {
+    jpg: Option<Image>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Image {
+    image_url: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct DateRange {
+    from: Option<String>,
+    to: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Genre {
+    name: String,
+}
+
+/// Jikan, an unofficial MyAnimeList API. It only knows MAL ids, Kitsu's mappings translate them
+/// to AniList ids and media without one are left out.
+pub struct JikanProvider {
+    http: Client,
+    api_url: String,
+    mappings: KitsuProvider,
+    next_request: Mutex<Instant>,
+}
+
+impl JikanProvider {
+    pub fn new() -> Self {
+        JikanProvider {
+            http: Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .expect("Can not build the Jikan HTTP client"),
+            api_url: JIKAN_API.to_owned(),
+            mappings: KitsuProvider::new(),
+            next_request: Mutex::new(Instant::now()),
+        }
+    }
+
+    async fn get<T: serde::de::DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> Result<Option<T>, MetadataError> {
+        let wait = {
+            let mut next_request = self.next_request.lock().unwrap();
+            let now = Instant::now();
+            let sent_at = (*next_request).max(now);
+            *next_request = sent_at + REQUEST_INTERVAL;
+            sent_at - now
+        };
+        if wait > Duration::from_secs(0) {
+            tokio::time::sleep(wait).await;
+        }
+        get_json(
+            NAME,
+            self.http
+                .get(format!("{}/{}", self.api_url, path))
+                .query(query),
+        )
+        .await
+    }
+
+    /// `media` with its AniList id, `None` if it has none
+    async fn with_anilist_id(
+        &self,
+        media: JikanMedia,
+        media_type: MediaType,
+    ) -> Result<Option<Media>, MetadataError> {
+        Ok(self
+            .mappings
+            .map_id(media_type, "myanimelist", media.mal_id, "anilist")
+            .await?
+            .map(|id| to_media(id, media, media_type)))
+    }
+}
+
+impl Default for JikanProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl MetadataProvider for JikanProvider {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    async fn search(&self, search: &MediaSearch) -> Result<MediaPage, MetadataError> {
+        if search.year.is_some() {
+            return Err(MetadataError::Unsupported(NAME, "search by year"));
+        }
+        // Jikan filters by MAL's genre ids, not names
+        if !search.genres.is_empty() {
+            return Err(MetadataError::Unsupported(NAME, "search by genre"));
+        }
+        if search.formats.len() > 1 {
+            return Err(MetadataError::Unsupported(NAME, "search several formats"));
+        }
+
+        let mut query = vec![
+            ("page", search.page.to_string()),
+            ("limit", search.per_page.min(MAX_PER_PAGE).to_string()),
+        ];
+        if let Some(text) = &search.search {
+            query.push(("q", text.clone()));
+        }
+        if search.is_adult != Some(true) {
+            query.push(("sfw", "true".to_owned()));
+        }
+        if let Some(&format) = search.formats.first() {
+            query.push(("type", media_kind(format).to_owned()));
+        }
+        if let Some(status) = search.status {
+            query.push((
+                "status",
+                status_filter(status, search.media_type)?.to_owned(),
+            ));
+        }
+        if let Some(sort) = search.sort {
+            let (order_by, direction) = order(sort)?;
+            query.push(("order_by", order_by.to_owned()));
+            query.push(("sort", direction.to_owned()));
+        }
+
+        let page = self
+            .get::<SearchPage>(type_path(search.media_type), &query)
+            .await?
+            .ok_or(MetadataError::Unsupported(NAME, "search that"))?;
+        let mut media = vec![];
+        for found in page.data {
+            media.extend(self.with_anilist_id(found, search.media_type).await?);
+        }
+        Ok(MediaPage {
+            media,
+            current_page: page.pagination.current_page,
+            last_page: page.pagination.last_visible_page.max(1),
+            has_next_page: page.pagination.has_next_page,
+        })
+    }
+
+    async fn media(&self, lookup: &MediaLookup) -> Result<Option<Media>, MetadataError> {
+        let id_mal = match (lookup.id_mal, lookup.id) {
+            (Some(id_mal), _) => Some(id_mal),
+            (None, Some(id)) => {
+                self.mappings
+                    .map_id(lookup.media_type, "anilist", id, "myanimelist")
+                    .await?
+            }
+            (None, None) => None,
+        };
+        let id_mal = match id_mal {
+            Some(id_mal) => id_mal,
+            None => return Ok(None),
+        };
+        let media = self
+            .get::<Data<JikanMedia>>(&format!("{}/{}", type_path(lookup.media_type), id_mal), &[])
+            .await?;
+        match (media, lookup.id) {
+            (Some(media), Some(id)) => Ok(Some(to_media(id, media.data, lookup.media_type))),
+            (Some(media), None) => self.with_anilist_id(media.data, lookup.media_type).await,
+            (None, _) => Ok(None),
+        }
+    }
+
+    async fn media_by_ids(&self, ids: &[i64]) -> Result<Vec<Media>, MetadataError> {
+        // AniList ids do not say whether they are anime or manga, anime are tried first. Kitsu
+        // maps them in batches, Jikan is then asked for each media on its own.
+        let mut found = vec![];
+        let mut missing = ids.to_vec();
+        for &media_type in &[MediaType::Anime, MediaType::Manga] {
+            if missing.is_empty() {
+                break;
+            }
+            let id_mals = self
+                .mappings
+                .map_ids(media_type, "anilist", &missing, "myanimelist")
+                .await?;
+            missing.retain(|id| !id_mals.contains_key(id));
+            let media = stream::iter(id_mals)
+                .map(|(id, id_mal)| async move {
+                    let media = self
+                        .get::<Data<JikanMedia>>(
+                            &format!("{}/{}", type_path(media_type), id_mal),
+                            &[],
+                        )
+                        .await?;
+                    Ok::<_, MetadataError>(media.map(|media| to_media(id, media.data, media_type)))
+                })
+                .buffer_unordered(MAX_CONCURRENT_REQUESTS)
+                .try_collect::<Vec<_>>()
+                .await?;
+            found.extend(media.into_iter().flatten());
+        }
+        Ok(found)
+    }
+
+    async fn aired_episodes(
+        &self,
+        _media_ids: &[i64],
+        _aired_after: i64,
+        _aired_before: i64,
+    ) -> Result<Vec<AiredEpisode>, MetadataError> {
+        Err(MetadataError::Unsupported(NAME, "list aired episodes"))
+    }
+}
+
+fn type_path(media_type: MediaType) -> &'static str {
+    match media_type {
+        MediaType::Anime => "anime",
+        MediaType::Manga => "manga",
+    }
+}
+
+fn to_media(id: i64, media: JikanMedia, media_type: MediaType) -> Media {
+    let title = media
+        .title_english
+        .or(media.title)
+        .unwrap_or_else(|| "?".to_owned());
+    let dates = match media_type {
+        MediaType::Anime => media.aired,
+        MediaType::Manga => media.published,
+    };
+    let (start_date, end_date) = match dates {
+        Some(dates) => (
+            dates.from.as_deref().map(FuzzyDate::parse),
+            dates.to.as_deref().map(FuzzyDate::parse),
+        ),
+        None => (None, None),
+    };
+    Media {
+        id_mal: Some(media.mal_id),
+        site_url: media.url,
+        format: media.kind.as_deref().and_then(parse_kind),
+        status: media.status.as_deref().and_then(parse_status),
+        genres: media.genres.into_iter().map(|genre| genre.name).collect(),
+        start_date,
+        end_date,
+        cover_image: media
+            .images
+            .and_then(|images| images.jpg)
+            .and_then(|jpg| jpg.image_url),
+        duration: media.duration.as_deref().and_then(parse_duration),
+        episodes: media.episodes,
+        chapters: media.chapters,
+        volumes: media.volumes,
+        average_score: media.score.map(|score| (score * 10.0).round() as i64),
+        description: media.synopsis,
+        ..Media::new(id, media_type, title)
+    }
+}
+
+/// Minutes in `"1 hr 52 min"`, `"24 min per ep"` counts one episode
+fn parse_duration(duration: &str) -> Option<i64> {
+    let words = duration.split_whitespace().collect::<Vec<_>>();
+    let minutes = words
+        .windows(2)
+        .filter_map(|pair| {
+            let amount = pair[0].parse::<i64>().ok()?;
+            match pair[1].trim_end_matches('.') {
+                "hr" | "hrs" => Some(amount * 60),
+                "min" | "mins" => Some(amount),
+                _ => None,
+            }
+        })
+        .sum::<i64>();
+    if minutes > 0 {
+        Some(minutes)
+    } else {
+        None
+    }
+}
+
+fn media_kind(format: MediaFormat) -> &'static str {
+    match format {
+        MediaFormat::Tv | MediaFormat::TvShort => "tv",
+        MediaFormat::Movie => "movie",
+        MediaFormat::Special => "special",
+        MediaFormat::Ova => "ova",
+        MediaFormat::Ona => "ona",
+        MediaFormat::Music => "music",
+        MediaFormat::Manga => "manga",
+        MediaFormat::Novel => "lightnovel",
+        MediaFormat::OneShot => "oneshot",
+    }
+}
+
+fn parse_kind(kind: &str) -> Option<MediaFormat> {
+    match kind.to_lowercase().as_str() {
+        "tv" => Some(MediaFormat::Tv),
+        "movie" => Some(MediaFormat::Movie),
+        "special" | "tv special" => Some(MediaFormat::Special),
+        "ova" => Some(MediaFormat::Ova),
+        "ona" => Some(MediaFormat::Ona),
+        "music" => Some(MediaFormat::Music),
+        "manga" | "manhwa" | "manhua" | "doujinshi" => Some(MediaFormat::Manga),
+        "novel" | "light novel" => Some(MediaFormat::Novel),
+        "one-shot" => Some(MediaFormat::OneShot),
+        _ => None,
+    }
+}
+
+fn status_filter(
+    status: MediaStatus,
+    media_type: MediaType,
+) -> Result<&'static str, MetadataError> {
+    match (status, media_type) {
+        (MediaStatus::Releasing, MediaType::Anime) => Ok("airing"),
+        (MediaStatus::Releasing, MediaType::Manga) => Ok("publishing"),
+        (MediaStatus::Finished, _) => Ok("complete"),
+        (MediaStatus::NotYetReleased, _) => Ok("upcoming"),
+        (MediaStatus::Hiatus, MediaType::Manga) => Ok("hiatus"),
+        (MediaStatus::Cancelled, MediaType::Manga) => Ok("discontinued"),
+        (MediaStatus::Hiatus, MediaType::Anime) | (MediaStatus::Cancelled, MediaType::Anime) => {
+            Err(MetadataError::Unsupported(
+                NAME,
+                "search cancelled or paused anime",
+            ))
+        }
+    }
+}
+
+fn parse_status(status: &str) -> Option<MediaStatus> {
+    match status {
+        "Currently Airing" | "Publishing" => Some(MediaStatus::Releasing),
+        "Finished Airing" | "Finished" => Some(MediaStatus::Finished),
+        "Not yet aired" | "Not yet published" => Some(MediaStatus::NotYetReleased),
+        "On Hiatus" => Some(MediaStatus::Hiatus),
+        "Discontinued" => Some(MediaStatus::Cancelled),
+        _ => None,
+    }
+}
+
+fn order(sort: MediaSort) -> Result<(&'static str, &'static str), MetadataError> {
+    match sort {
+        MediaSort::Score => Ok(("score", "desc")),
+        // MAL's popularity is a rank, the most popular is 1
+        MediaSort::Popularity => Ok(("popularity", "asc")),
+        MediaSort::Newest => Ok(("start_date", "desc")),
+        MediaSort::Oldest => Ok(("start_date", "asc")),
+        MediaSort::Title => Ok(("title", "asc")),
+        MediaSort::Favourites => Ok(("favorites", "desc")),
+        MediaSort::Trending => Err(MetadataError::Unsupported(NAME, "sort by trending")),
+    }
+}
@@ -0,0 +1,522 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use reqwest::{header, Client};
+use serde::Deserialize;
+use serde_json::Value;
+use serenity::async_trait;
+
+use crate::{
+    media::{
+        AiredEpisode, FuzzyDate, Media, MediaFormat, MediaPage, MediaSort, MediaStatus, MediaType,
+    },
+    strings::url::{KITSU_API, KITSU_SITE},
+};
+
+use super::{get_json, MediaLookup, MediaSearch, MetadataError, MetadataProvider};
+
+const NAME: &str = "Kitsu";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Kitsu publishes no rate limit, requests are spread out to stay polite
+const REQUEST_INTERVAL: Duration = Duration::from_millis(250);
+/// Kitsu pages hold at most 20 media
+const MAX_PER_PAGE: i64 = 20;
+/// External ids looked up in one mappings request, each mapping is a resource on the page
+const IDS_PER_REQUEST: usize = MAX_PER_PAGE as usize;
+
+/// A JSON:API document, `data` is one resource or a list of them
+#[derive(Deserialize, Debug)]
+struct Document<T> {
+    data: T,
+    #[serde(default)]
+    included: Vec<Resource>,
+    #[serde(default)]
+    meta: Meta,
+    #[serde(default)]
+    links: Links,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct Meta {
+    count: Option<i64>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct Links {
+    next: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct Resource {
+    id: String,
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    attributes: Value,
+    #[serde(default)]
+    relationships: HashMap<String, Relationship>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct Relationship {
+    data: Option<Linkage>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+enum Linkage {
+    One(Identifier),
+    Many(Vec<Identifier>),
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct Identifier {
+    id: String,
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct MediaAttributes {
+    slug: Option<String>,
+    canonical_title: Option<String>,
+    #[serde(default)]
+    titles: HashMap<String, Option<String>>,
+    synopsis: Option<String>,
+    subtype: Option<String>,
+    status: Option<String>,
+    start_date: Option<String>,
+    end_date: Option<String>,
+    poster_image: Option<PosterImage>,
+    episode_count: Option<i64>,
+    episode_length: Option<i64>,
+    chapter_count: Option<i64>,
+    volume_count: Option<i64>,
+    /// A percentage like `"82.35"`
+    average_rating: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PosterImage {
+    medium: Option<String>,
+    small: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct MappingAttributes {
+    external_site: String,
+    external_id: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct CategoryAttributes {
+    title: String,
+}
+
+/// Kitsu's JSON:API. It knows the AniList and MAL ids of its media through its mappings,
+/// media without an AniList mapping can not be put on a watch list and are left out.
+pub struct KitsuProvider {
+    http: Client,
+    api_url: String,
+    next_request: Mutex<Instant>,
+}
+
+impl KitsuProvider {
+    pub fn new() -> Self {
+        KitsuProvider {
+            http: Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .expect("Can not build the Kitsu HTTP client"),
+            api_url: KITSU_API.to_owned(),
+            next_request: Mutex::new(Instant::now()),
+        }
+    }
+
+    async fn get<T: serde::de::DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> Result<Option<Document<T>>, MetadataError> {
+        let wait = {
+            let mut next_request = self.next_request.lock().unwrap();
+            let now = Instant::now();
+            let sent_at = (*next_request).max(now);
+            *next_request = sent_at + REQUEST_INTERVAL;
+            sent_at - now
+        };
+        if wait > Duration::from_secs(0) {
+            tokio::time::sleep(wait).await;
+        }
+        get_json(
+            NAME,
+            self.http
+                .get(format!("{}/{}", self.api_url, path))
+                .header(header::ACCEPT, "application/vnd.api+json")
+                .query(query),
+        )
+        .await
+    }
+
+    /// The media `external_ids` on `site` map to as `(external id, media)` pairs, next to the
+    /// resources they include. Ids are looked up `IDS_PER_REQUEST` at a time.
+    async fn find_mapped(
+        &self,
+        media_type: MediaType,
+        site: &str,
+        external_ids: &[i64],
+    ) -> Result<(Vec<(i64, Resource)>, Vec<Resource>), MetadataError> {
+        let mut items = vec![];
+        let mut included = vec![];
+        for chunk in external_ids.chunks(IDS_PER_REQUEST) {
+            let document = self
+                .get::<Vec<Resource>>(
+                    "mappings",
+                    &[
+                        (
+                            "filter[externalSite]",
+                            format!("{}/{}", site, type_path(media_type)),
+                        ),
+                        (
+                            "filter[externalId]",
+                            chunk
+                                .iter()
+                                .map(i64::to_string)
+                                .collect::<Vec<_>>()
+                                .join(","),
+                        ),
+                        ("include", "item.mappings,item.categories".to_owned()),
+                        ("page[limit]", IDS_PER_REQUEST.to_string()),
+                    ],
+                )
+                .await?;
+            let document = match document {
+                Some(document) => document,
+                None => continue,
+            };
+            for mapping in &document.data {
+                let external_id =
+                    serde_json::from_value::<MappingAttributes>(mapping.attributes.clone())
+                        .ok()
+                        .and_then(|mapping| mapping.external_id.parse().ok());
+                let item = related(mapping, &document.included, "item")
+                    .find(|item| item.kind == type_path(media_type));
+                if let (Some(external_id), Some(item)) = (external_id, item) {
+                    items.push((external_id, item.clone()));
+                }
+            }
+            included.extend(document.included);
+        }
+        Ok((items, included))
+    }
+
+    /// The id the media `external_id` on `from` has on `to`, sites are `anilist` or
+    /// `myanimelist`
+    pub async fn map_id(
+        &self,
+        media_type: MediaType,
+        from: &str,
+        external_id: i64,
+        to: &str,
+    ) -> Result<Option<i64>, MetadataError> {
+        Ok(self
+            .map_ids(media_type, from, &[external_id], to)
+            .await?
+            .remove(&external_id))
+    }
+
+    /// `map_id` for many ids at once, ids without a `to` mapping are left out
+    pub async fn map_ids(
+        &self,
+        media_type: MediaType,
+        from: &str,
+        external_ids: &[i64],
+        to: &str,
+    ) -> Result<HashMap<i64, i64>, MetadataError> {
+        let (items, included) = self.find_mapped(media_type, from, external_ids).await?;
+        Ok(items
+            .iter()
+            .filter_map(|(external_id, item)| {
+                mapped_id(item, &included, media_type, to).map(|id| (*external_id, id))
+            })
+            .collect())
+    }
+}
+
+impl Default for KitsuProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl MetadataProvider for KitsuProvider {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    async fn search(&self, search: &MediaSearch) -> Result<MediaPage, MetadataError> {
+        let per_page = search.per_page.min(MAX_PER_PAGE);
+        let mut query = vec![
+            ("page[limit]", per_page.to_string()),
+            ("page[offset]", ((search.page - 1) * per_page).to_string()),
+            ("include", "mappings,categories".to_owned()),
+        ];
+        if search.is_adult == Some(true) {
+            // adult media are hidden from requests without a Kitsu account
+            return Err(MetadataError::Unsupported(NAME, "search adult media"));
+        }
+        match &search.search {
+            Some(text) => query.push(("filter[text]", text.clone())),
+            // Kitsu ranks text searches by relevance and refuses to sort them
+            None => {
+                if let Some(sort) = search.sort {
+                    query.push(("sort", sort_field(sort)?.to_owned()));
+                }
+            }
+        }
+        if let Some(year) = search.year {
+            let filter = match search.media_type {
+                MediaType::Anime => "filter[seasonYear]",
+                MediaType::Manga => "filter[year]",
+            };
+            query.push((filter, year.to_string()));
+        }
+        if !search.formats.is_empty() {
+            query.push((
+                "filter[subtype]",
+                search
+                    .formats
+                    .iter()
+                    .map(|&format| subtype(format))
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ));
+        }
+        if !search.genres.is_empty() {
+            query.push((
+                "filter[categories]",
+                search
+                    .genres
+                    .iter()
+                    .map(|genre| genre.to_lowercase().replace(' ', "-"))
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ));
+        }
+        if let Some(status) = search.status {
+            query.push(("filter[status]", status_filter(status)?.to_owned()));
+        }
+
+        let document = self
+            .get::<Vec<Resource>>(type_path(search.media_type), &query)
+            .await?
+            .ok_or(MetadataError::Unsupported(NAME, "search that"))?;
+        let count = document.meta.count.unwrap_or_default();
+        Ok(MediaPage {
+            media: document
+                .data
+                .iter()
+                .filter_map(|resource| to_media(resource, &document.included, search.media_type))
+                .collect(),
+            current_page: search.page,
+            last_page: ((count + per_page - 1) / per_page).max(1),
+            has_next_page: document.links.next.is_some(),
+        })
+    }
+
+    async fn media(&self, lookup: &MediaLookup) -> Result<Option<Media>, MetadataError> {
+        let (site, id) = match (lookup.id, lookup.id_mal) {
+            (Some(id), _) => ("anilist", id),
+            (None, Some(id_mal)) => ("myanimelist", id_mal),
+            (None, None) => return Ok(None),
+        };
+        let (items, included) = self.find_mapped(lookup.media_type, site, &[id]).await?;
+        Ok(items
+            .first()
+            .and_then(|(_, item)| to_media(item, &included, lookup.media_type)))
+    }
+
+    async fn media_by_ids(&self, ids: &[i64]) -> Result<Vec<Media>, MetadataError> {
+        // Kitsu has no AniList ids of its own, only mappings, and does not tell anime and manga
+        // ids apart. Ids are tried as anime first.
+        let mut found = vec![];
+        let mut missing = ids.to_vec();
+        for &media_type in &[MediaType::Anime, MediaType::Manga] {
+            if missing.is_empty() {
+                break;
+            }
+            let (items, included) = self.find_mapped(media_type, "anilist", &missing).await?;
+            found.extend(
+                items
+                    .iter()
+                    .filter_map(|(_, item)| to_media(item, &included, media_type)),
+            );
+            missing.retain(|id| !found.iter().any(|media: &Media| media.id == *id));
+        }
+        Ok(found)
+    }
+
+    async fn aired_episodes(
+        &self,
+        _media_ids: &[i64],
+        _aired_after: i64,
+        _aired_before: i64,
+    ) -> Result<Vec<AiredEpisode>, MetadataError> {
+        Err(MetadataError::Unsupported(NAME, "list aired episodes"))
+    }
+}
+
+fn type_path(media_type: MediaType) -> &'static str {
+    match media_type {
+        MediaType::Anime => "anime",
+        MediaType::Manga => "manga",
+    }
+}
+
+/// The external id of the `site` mapping `resource` has, among the `included` resources
+fn mapped_id(
+    resource: &Resource,
+    included: &[Resource],
+    media_type: MediaType,
+    site: &str,
+) -> Option<i64> {
+    let external_site = format!("{}/{}", site, type_path(media_type));
+    related(resource, included, "mappings")
+        .filter_map(|mapping| {
+            serde_json::from_value::<MappingAttributes>(mapping.attributes.clone()).ok()
+        })
+        .find(|mapping| mapping.external_site == external_site)
+        .and_then(|mapping| mapping.external_id.parse().ok())
+}
+
+/// The `included` resources `resource` links to through `relationship`
+fn related<'a>(
+    resource: &'a Resource,
+    included: &'a [Resource],
+    relationship: &str,
+) -> impl Iterator<Item = &'a Resource> + 'a {
+    let identifiers = match resource
+        .relationships
+        .get(relationship)
+        .and_then(|relationship| relationship.data.as_ref())
+    {
+        Some(Linkage::One(identifier)) => vec![identifier],
+        Some(Linkage::Many(identifiers)) => identifiers.iter().collect(),
+        None => vec![],
+    };
+    included.iter().filter(move |included| {
+        identifiers
+            .iter()
+            .any(|identifier| identifier.id == included.id && identifier.kind == included.kind)
+    })
+}
+
+/// `None` for media without an AniList mapping
+fn to_media(resource: &Resource, included: &[Resource], media_type: MediaType) -> Option<Media> {
+    let id = mapped_id(resource, included, media_type, "anilist")?;
+    let attributes = serde_json::from_value::<MediaAttributes>(resource.attributes.clone()).ok()?;
+    let title = attributes
+        .titles
+        .get("en")
+        .cloned()
+        .flatten()
+        .or(attributes.canonical_title)
+        .unwrap_or_else(|| "?".to_owned());
+    Some(Media {
+        id_mal: mapped_id(resource, included, media_type, "myanimelist"),
+        site_url: attributes
+            .slug
+            .map(|slug| format!("{}/{}/{}", KITSU_SITE, type_path(media_type), slug)),
+        format: attributes.subtype.as_deref().and_then(parse_subtype),
+        status: attributes.status.as_deref().and_then(parse_status),
+        genres: related(resource, included, "categories")
+            .filter_map(|category| {
+                serde_json::from_value::<CategoryAttributes>(category.attributes.clone()).ok()
+            })
+            .map(|category| category.title)
+            .collect(),
+        start_date: attributes.start_date.as_deref().map(FuzzyDate::parse),
+        end_date: attributes.end_date.as_deref().map(FuzzyDate::parse),
+        cover_image: attributes
+            .poster_image
+            .and_then(|poster| poster.medium.or(poster.small)),
+        duration: attributes.episode_length,
+        episodes: attributes.episode_count,
+        chapters: attributes.chapter_count,
+        volumes: attributes.volume_count,
+        average_score: attributes
+            .average_rating
+            .and_then(|rating| rating.parse::<f64>().ok())
+            .map(|rating| rating.round() as i64),
+        description: attributes.synopsis,
+        ..Media::new(id, media_type, title)
+    })
+}
+
+fn subtype(format: MediaFormat) -> &'static str {
+    match format {
+        MediaFormat::Tv | MediaFormat::TvShort => "TV",
+        MediaFormat::Movie => "movie",
+        MediaFormat::Special => "special",
+        MediaFormat::Ova => "OVA",
+        MediaFormat::Ona => "ONA",
+        MediaFormat::Music => "music",
+        MediaFormat::Manga => "manga",
+        MediaFormat::Novel => "novel",
+        MediaFormat::OneShot => "oneshot",
+    }
+}
+
+fn parse_subtype(subtype: &str) -> Option<MediaFormat> {
+    match subtype.to_lowercase().as_str() {
+        "tv" => Some(MediaFormat::Tv),
+        "movie" => Some(MediaFormat::Movie),
+        "special" => Some(MediaFormat::Special),
+        "ova" => Some(MediaFormat::Ova),
+        "ona" => Some(MediaFormat::Ona),
+        "music" => Some(MediaFormat::Music),
+        "manga" | "manhwa" | "manhua" | "oel" | "doujin" => Some(MediaFormat::Manga),
+        "novel" => Some(MediaFormat::Novel),
+        "oneshot" => Some(MediaFormat::OneShot),
+        _ => None,
+    }
+}
+
+fn status_filter(status: MediaStatus) -> Result<&'static str, MetadataError> {
+    match status {
+        MediaStatus::Releasing => Ok("current"),
+        MediaStatus::Finished => Ok("finished"),
+        MediaStatus::NotYetReleased => Ok("tba,unreleased,upcoming"),
+        MediaStatus::Cancelled | MediaStatus::Hiatus => Err(MetadataError::Unsupported(
+            NAME,
+            "search cancelled or paused media",
+        )),
+    }
+}
+
+fn parse_status(status: &str) -> Option<MediaStatus> {
+    match status {
+        "current" => Some(MediaStatus::Releasing),
+        "finished" => Some(MediaStatus::Finished),
+        "tba" | "unreleased" | "upcoming" => Some(MediaStatus::NotYetReleased),
+        _ => None,
+    }
+}
+
+fn sort_field(sort: MediaSort) -> Result<&'static str, MetadataError> {
+    match sort {
+        MediaSort::Score => Ok("-averageRating"),
+        MediaSort::Popularity => Ok("-userCount"),
+        MediaSort::Newest => Ok("-startDate"),
+        MediaSort::Oldest => Ok("startDate"),
+        MediaSort::Title => Ok("slug"),
+        MediaSort::Favourites => Ok("-favoritesCount"),
+        MediaSort::Trending => Err(MetadataError::Unsupported(NAME, "sort by trending")),
+    }
+}
@@ -0,0 +1,267 @@
+use std::{future::Future, pin::Pin, sync::Arc};
+
+use reqwest::StatusCode;
+use serenity::async_trait;
+use thiserror::Error;
+use tracing::warn;
+
+use crate::{
+    anilist::AniListError,
+    media::{AiredEpisode, Media, MediaFormat, MediaPage, MediaSort, MediaStatus, MediaType},
+};
+
+pub mod anilist;
+pub mod cache;
+#[cfg(feature = "jikan")]
+pub mod jikan;
+#[cfg(feature = "kitsu")]
+pub mod kitsu;
+
+#[derive(Error, Debug)]
+pub enum MetadataError {
+    #[error(transparent)]
+    AniList(#[from] AniListError),
+    #[error("Request to {0} failed")]
+    Network(&'static str, #[source] reqwest::Error),
+    #[error("{0} answered {1}")]
+    Unavailable(&'static str, StatusCode),
+    #[error("{0} is rate limiting us")]
+    RateLimited(&'static str),
+    #[error("Invalid response from {0}")]
+    InvalidResponse(&'static str, #[source] serde_json::Error),
+    #[error("{0} can not {1}")]
+    Unsupported(&'static str, &'static str),
+}
+
+impl MetadataError {
+    /// The provider is down or busy rather than refusing the request, the next one may answer
+    pub fn is_outage(&self) -> bool {
+        match self {
+            MetadataError::AniList(why) => matches!(
+                why,
+                AniListError::Network(_)
+                    | AniListError::Unavailable(_)
+                    | AniListError::RateLimited
+                    | AniListError::InvalidResponse(_)
+            ),
+            MetadataError::Network(_, _)
+            | MetadataError::Unavailable(_, _)
+            | MetadataError::RateLimited(_)
+            | MetadataError::InvalidResponse(_, _) => true,
+            MetadataError::Unsupported(_, _) => false,
+        }
+    }
+}
+
+/// Sends `request` to the REST provider `name`, `None` when it has nothing at that url
+#[cfg(any(feature = "kitsu", feature = "jikan"))]
+async fn get_json<T: serde::de::DeserializeOwned>(
+    name: &'static str,
+    request: reqwest::RequestBuilder,
+) -> Result<Option<T>, MetadataError> {
+    let response = request
+        .send()
+        .await
+        .map_err(|why| MetadataError::Network(name, why))?;
+    match response.status() {
+        StatusCode::NOT_FOUND => return Ok(None),
+        StatusCode::TOO_MANY_REQUESTS => return Err(MetadataError::RateLimited(name)),
+        status if !status.is_success() => return Err(MetadataError::Unavailable(name, status)),
+        _ => {}
+    }
+    let text = response
+        .text()
+        .await
+        .map_err(|why| MetadataError::Network(name, why))?;
+    serde_json::from_str(&text)
+        .map(Some)
+        .map_err(|why| MetadataError::InvalidResponse(name, why))
+}
+
+/// A filtered search, every filter is optional
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaSearch {
+    pub media_type: MediaType,
+    pub search: Option<String>,
+    /// Starts at 1
+    pub page: i64,
+    pub per_page: i64,
+    /// The season year of anime, the year manga started in
+    pub year: Option<i64>,
+    pub formats: Vec<MediaFormat>,
+    pub genres: Vec<String>,
+    pub status: Option<MediaStatus>,
+    pub sort: Option<MediaSort>,
+    pub is_adult: Option<bool>,
+}
+
+impl Default for MediaSearch {
+    fn default() -> Self {
+        MediaSearch {
+            media_type: MediaType::Anime,
+            search: None,
+            page: 1,
+            per_page: 10,
+            year: None,
+            formats: vec![],
+            genres: vec![],
+            status: None,
+            sort: None,
+            is_adult: None,
+        }
+    }
+}
+
+/// An exact media, by AniList or MAL id
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaLookup {
+    pub id: Option<i64>,
+    pub id_mal: Option<i64>,
+    pub media_type: MediaType,
+    pub is_adult: Option<bool>,
+}
+
+impl MediaLookup {
+    pub fn by_id(id: i64, media_type: MediaType) -> Self {
+        MediaLookup {
+            id: Some(id),
+            id_mal: None,
+            media_type,
+            is_adult: None,
+        }
+    }
+
+    /// Parses an AniList id, an AniList url or a `mal:<id>`, `None` if `input` is a plain search
+    pub fn parse(input: &str, media_type: MediaType) -> Option<Self> {
+        let input = input.trim();
+        let mut lookup = Self {
+            id: None,
+            id_mal: None,
+            media_type,
+            is_adult: None,
+        };
+
+        if let Some(id_mal) = input.strip_prefix("mal:") {
+            lookup.id_mal = Some(id_mal.trim().parse().ok()?);
+        } else if let Ok(id) = input.parse() {
+            lookup.id = Some(id);
+        } else {
+            // https://anilist.co/<anime|manga>/<id>/<slug>
+            let path = input
+                .trim_start_matches("https://")
+                .trim_start_matches("http://")
+                .trim_start_matches("www.")
+                .strip_prefix("anilist.co/")?;
+            let mut segments = path.split('/');
+            lookup.media_type = match segments.next()? {
+                "anime" => MediaType::Anime,
+                "manga" => MediaType::Manga,
+                _ => return None,
+            };
+            lookup.id = Some(segments.next()?.parse().ok()?);
+        }
+
+        Some(lookup)
+    }
+}
+
+/// Where media come from. Every provider hands out media keyed by their AniList id, which is
+/// what watch lists store.
+#[async_trait]
+pub trait MetadataProvider: Send + Sync {
+    /// Shown to users when the provider fails
+    fn name(&self) -> &'static str;
+
+    async fn search(&self, search: &MediaSearch) -> Result<MediaPage, MetadataError>;
+
+    /// `None` if the provider has no such media
+    async fn media(&self, lookup: &MediaLookup) -> Result<Option<Media>, MetadataError>;
+
+    /// The media of `ids` the provider knows, in no particular order
+    async fn media_by_ids(&self, ids: &[i64]) -> Result<Vec<Media>, MetadataError>;
+
+    /// Episodes of `media_ids` that aired between the two unix timestamps, oldest first
+    async fn aired_episodes(
+        &self,
+        media_ids: &[i64],
+        aired_after: i64,
+        aired_before: i64,
+    ) -> Result<Vec<AiredEpisode>, MetadataError>;
+}
+
+/// What the `async_trait` methods of a provider return
+type ProviderCall<'a, T> = Pin<Box<dyn Future<Output = Result<T, MetadataError>> + Send + 'a>>;
+
+/// Asks the providers in order, moving on to the next one while they are down or can not do
+/// what was asked. Fails with the error of the first provider when none answers.
+pub struct FallbackProvider {
+    providers: Vec<Arc<dyn MetadataProvider>>,
+}
+
+impl FallbackProvider {
+    /// `providers` must not be empty, the first one is the primary
+    pub fn new(providers: Vec<Arc<dyn MetadataProvider>>) -> Self {
+        assert!(
+            !providers.is_empty(),
+            "No metadata provider to fall back on"
+        );
+        FallbackProvider { providers }
+    }
+
+    async fn first_answer<'a, T, F>(&'a self, action: &str, call: F) -> Result<T, MetadataError>
+    where
+        F: Fn(&'a dyn MetadataProvider) -> ProviderCall<'a, T>,
+    {
+        let mut first_error = None;
+        for provider in &self.providers {
+            match call(provider.as_ref()).await {
+                Ok(answer) => return Ok(answer),
+                Err(why) if why.is_outage() || matches!(why, MetadataError::Unsupported(_, _)) => {
+                    warn!(
+                        "{} could not {} ({}), falling back",
+                        provider.name(),
+                        action,
+                        why
+                    );
+                    first_error.get_or_insert(why);
+                }
+                Err(why) => return Err(why),
+            }
+        }
+        Err(first_error.expect("Providers answered nothing"))
+    }
+}
+
+#[async_trait]
+impl MetadataProvider for FallbackProvider {
+    fn name(&self) -> &'static str {
+        self.providers[0].name()
+    }
+
+    async fn search(&self, search: &MediaSearch) -> Result<MediaPage, MetadataError> {
+        self.first_answer("search", |provider| provider.search(search))
+            .await
+    }
+
+    async fn media(&self, lookup: &MediaLookup) -> Result<Option<Media>, MetadataError> {
+        self.first_answer("find media", |provider| provider.media(lookup))
+            .await
+    }
+
+    async fn media_by_ids(&self, ids: &[i64]) -> Result<Vec<Media>, MetadataError> {
+        self.first_answer("list media", |provider| provider.media_by_ids(ids))
+            .await
+    }
+
+    async fn aired_episodes(
+        &self,
+        media_ids: &[i64],
+        aired_after: i64,
+        aired_before: i64,
+    ) -> Result<Vec<AiredEpisode>, MetadataError> {
+        self.first_answer("list aired episodes", |provider| {
+            provider.aired_episodes(media_ids, aired_after, aired_before)
+        })
+        .await
+    }
+}
@@ -0,0 +1,311 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Result;
+use mongodm::{
+    prelude::{BsonDateTime, MongoDatabase},
+    ToRepository,
+};
+use serenity::{
+    http::Http,
+    model::id::{ChannelId, UserId},
+};
+use tracing::{error, info, warn};
+
+use crate::{
+    db::{
+        guild_config::{GuildConfig, GuildConfigCollectionExt},
+        notifications::{
+            Announcement, AnnouncementCollectionExt, NotificationSettings,
+            NotificationSettingsCollectionExt,
+        },
+        watchlist::{WatchInfo, WatchListStore, WatchStatus},
+    },
+    error::discord_error_code,
+    media::{Media, MediaType},
+    metadata::{MediaLookup, MetadataProvider},
+};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// Episodes that aired while the bot was down are still announced if they are this recent
+const LOOKBACK_SECS: i64 = 6 * 60 * 60;
+/// Announcements that keep failing are dropped once they are this old
+const DELIVERY_DEADLINE_MILLIS: i64 = 24 * 60 * 60 * 1000;
+/// Discord's JSON error codes for announcements no retry gets through: Unknown Channel, Missing
+/// Access, Missing Permissions and Cannot Send Messages To This User
+const UNDELIVERABLE: [isize; 4] = [10003, 50001, 50013, 50007];
+
+/// Announces newly aired episodes to the users watching them, every `POLL_INTERVAL`. Posts to
+/// the announcement channel of the guild a user opted in from if it has one, DMs them otherwise.
+pub async fn run(
+    http: Arc<Http>,
+    db: MongoDatabase,
+    watch_list: Arc<dyn WatchListStore>,
+    metadata: Arc<dyn MetadataProvider>,
+) {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(why) = queue_aired_episodes(&db, watch_list.as_ref(), metadata.as_ref()).await {
+            error!("Could not poll aired episodes: {:?}", why);
+        }
+        if let Err(why) =
+            deliver_announcements(&http, &db, watch_list.as_ref(), metadata.as_ref()).await
+        {
+            error!("Could not deliver announcements: {:?}", why);
+        }
+    }
+}
+
+/// Queues an announcement for every recently aired episode of an anime an opted-in user is
+/// watching, announcements queued before are skipped
+async fn queue_aired_episodes(
+    db: &MongoDatabase,
+    watch_list: &dyn WatchListStore,
+    metadata: &dyn MetadataProvider,
+) -> Result<()> {
+    let settings = db
+        .repository::<NotificationSettings>()
+        .find_enabled()
+        .await?;
+    if settings.is_empty() {
+        return Ok(());
+    }
+
+    let user_ids = settings
+        .iter()
+        .map(|settings| settings.discord_user_id)
+        .collect::<Vec<_>>();
+    let watching = watch_list
+        .find_consuming(MediaType::Anime, &user_ids)
+        .await?;
+    let media_ids = watching
+        .iter()
+        .map(|info| info.anilist_media_id)
+        .collect::<BTreeSet<_>>();
+    if media_ids.is_empty() {
+        return Ok(());
+    }
+
+    let now = BsonDateTime::now();
+    let aired_before = now.timestamp_millis() / 1000;
+    let aired_episodes = metadata
+        .aired_episodes(
+            &media_ids.into_iter().collect::<Vec<_>>(),
+            aired_before - LOOKBACK_SECS,
+            aired_before,
+        )
+        .await?;
+
+    let announcement_repo = db.repository::<Announcement>();
+    for episode in aired_episodes {
+        for info in watching
+            .iter()
+            .filter(|info| info.anilist_media_id == episode.media_id)
+        {
+            let deliver_after = settings
+                .iter()
+                .find(|settings| settings.discord_user_id == info.discord_user_id)
+                .map_or(now, |settings| settings.deliver_after(now));
+            let queued = announcement_repo
+                .queue_announcement(&Announcement {
+                    airing_id: episode.id,
+                    anilist_media_id: episode.media_id,
+                    episode: episode.episode,
+                    discord_user_id: info.discord_user_id,
+                    deliver_after,
+                    delivered_at: None,
+                    created_at: now,
+                })
+                .await?;
+            if queued {
+                info!(
+                    "Queued episode {} of {} for {}",
+                    episode.episode, episode.media_id, info.discord_user_id
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Sends every announcement that is due, each one is sent at most once. Announcements that
+/// fail are retried on the next poll until they are `DELIVERY_DEADLINE_MILLIS` old.
+async fn deliver_announcements(
+    http: &Http,
+    db: &MongoDatabase,
+    watch_list: &dyn WatchListStore,
+    metadata: &dyn MetadataProvider,
+) -> Result<()> {
+    let announcement_repo = db.repository::<Announcement>();
+    let mut media_cache: HashMap<i64, Option<Media>> = HashMap::new();
+    let mut recipient_cache: HashMap<UserId, Recipient> = HashMap::new();
+
+    for announcement in announcement_repo.find_due_announcements().await? {
+        let delivery = deliver_announcement(
+            http,
+            db,
+            watch_list,
+            metadata,
+            &announcement,
+            &mut media_cache,
+            &mut recipient_cache,
+        )
+        .await;
+        if let Err(why) = delivery {
+            let age =
+                BsonDateTime::now().timestamp_millis() - announcement.created_at.timestamp_millis();
+            if age < DELIVERY_DEADLINE_MILLIS {
+                warn!(
+                    "Could not deliver episode {} of {} to {}, retrying later: {:?}",
+                    announcement.episode,
+                    announcement.anilist_media_id,
+                    announcement.discord_user_id,
+                    why
+                );
+                continue;
+            }
+            warn!(
+                "Giving up on episode {} of {} for {}: {:?}",
+                announcement.episode,
+                announcement.anilist_media_id,
+                announcement.discord_user_id,
+                why
+            );
+        }
+        announcement_repo.mark_delivered(&announcement).await?;
+    }
+    Ok(())
+}
+
+/// Where a user's announcements go, `OptedOut` if they turned them off since they were queued
+#[derive(Debug, Clone, Copy)]
+enum Recipient {
+    OptedOut,
+    Dm,
+    Channel(ChannelId),
+}
+
+/// Announces `announcement` if the user still wants it, `Ok` once it needs no retry
+async fn deliver_announcement(
+    http: &Http,
+    db: &MongoDatabase,
+    watch_list: &dyn WatchListStore,
+    metadata: &dyn MetadataProvider,
+    announcement: &Announcement,
+    media_cache: &mut HashMap<i64, Option<Media>>,
+    recipient_cache: &mut HashMap<UserId, Recipient>,
+) -> Result<()> {
+    let recipient = match recipient_cache.get(&announcement.discord_user_id) {
+        Some(recipient) => *recipient,
+        None => {
+            let recipient = find_recipient(db, announcement.discord_user_id).await?;
+            recipient_cache.insert(announcement.discord_user_id, recipient);
+            recipient
+        }
+    };
+    let channel_id = match recipient {
+        Recipient::OptedOut => return Ok(()),
+        Recipient::Dm => None,
+        Recipient::Channel(channel_id) => Some(channel_id),
+    };
+
+    // users who stopped watching since the episode aired don't want it anymore
+    let watch_info = watch_list
+        .find_watch_info(announcement.anilist_media_id, announcement.discord_user_id)
+        .await?;
+    if !matches!(&watch_info, Some(info) if info.watch_status == WatchStatus::Consuming) {
+        return Ok(());
+    }
+
+    let media = match media_cache.get(&announcement.anilist_media_id) {
+        Some(media) => media.clone(),
+        None => {
+            let media = metadata
+                .media(&MediaLookup::by_id(
+                    announcement.anilist_media_id,
+                    MediaType::Anime,
+                ))
+                .await?;
+            media_cache.insert(announcement.anilist_media_id, media.clone());
+            media
+        }
+    };
+    let media = match media {
+        Some(media) => media,
+        None => return Ok(()),
+    };
+
+    if let Err(why) = announce(http, announcement, &media, watch_info.as_ref(), channel_id).await {
+        // outages and rate limits pass, the announcement is retried until its deadline
+        if !matches!(discord_error_code(&why), Some(code) if UNDELIVERABLE.contains(&code)) {
+            return Err(why.into());
+        }
+        // closed DMs and gone channels would fail forever, so the announcement is dropped
+        warn!(
+            "Could not announce episode {} of {} to {}: {:?}",
+            announcement.episode, media.id, announcement.discord_user_id, why
+        );
+    }
+    Ok(())
+}
+
+/// Whether `user_id` still gets announcements, and in the announcement channel of the guild they
+/// opted in from if it set one
+async fn find_recipient(db: &MongoDatabase, user_id: UserId) -> Result<Recipient> {
+    let settings = db
+        .repository::<NotificationSettings>()
+        .find_settings(user_id)
+        .await?;
+    let guild_id = match settings {
+        Some(settings) if settings.enabled => settings.discord_guild_id,
+        _ => return Ok(Recipient::OptedOut),
+    };
+    let config = match guild_id {
+        Some(guild_id) => db.repository::<GuildConfig>().find_config(guild_id).await?,
+        None => None,
+    };
+    Ok(
+        match config.and_then(|config| config.announcement_channel) {
+            Some(channel_id) => Recipient::Channel(channel_id),
+            None => Recipient::Dm,
+        },
+    )
+}
+
+async fn announce(
+    http: &Http,
+    announcement: &Announcement,
+    media: &Media,
+    watch_info: Option<&WatchInfo>,
+    channel_id: Option<ChannelId>,
+) -> serenity::Result<()> {
+    let content = format!(
+        "📺 Episode **{}** of **{}** just aired!",
+        announcement.episode, media.title
+    );
+    let embed = media.embed_card(watch_info);
+
+    match channel_id {
+        Some(channel_id) => {
+            channel_id
+                .send_message(http, |m| {
+                    m.content(format!("<@{}> {}", announcement.discord_user_id, content))
+                        .set_embed(embed)
+                })
+                .await?;
+        }
+        None => {
+            announcement
+                .discord_user_id
+                .create_dm_channel(http)
+                .await?
+                .send_message(http, |m| m.content(content).set_embed(embed))
+                .await?;
+        }
+    }
+    Ok(())
+}
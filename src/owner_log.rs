@@ -0,0 +1,235 @@
+use std::{
+    cmp::min,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serenity::{
+    builder::CreateEmbed,
+    http::Http,
+    model::id::{ChannelId, UserId},
+};
+use tracing::error;
+
+use crate::{embeds::make_error_embed, error::ErrorOrigin};
+
+/// Errors are collected this long before the first digest goes out
+const DIGEST_INTERVAL: Duration = Duration::from_secs(60);
+/// Every digest doubles the wait for the next one up to this, so an outage is not a message flood
+const MAX_DIGEST_INTERVAL: Duration = Duration::from_secs(30 * 60);
+/// Discord caps embeds at 25 fields, errors past that are only counted
+const MAX_GROUPS: usize = 25;
+const FIELD_NAME_LENGTH: usize = 256;
+const FIELD_VALUE_LENGTH: usize = 1024;
+/// Discord caps the characters of all embed texts together
+const EMBED_LENGTH: usize = 6000;
+/// Kept free for the title and the description
+const HEADER_LENGTH: usize = 200;
+
+/// Where error digests go, `OWNER_LOG_CHANNEL_ID` if set or the bot owner's DMs
+#[derive(Debug, Clone, Copy)]
+pub enum OwnerLogTarget {
+    Channel(ChannelId),
+    Owner(UserId),
+}
+
+/// Errors of the same command with the same cause, only the last one is kept in full
+struct ErrorGroup {
+    summary: String,
+    count: u64,
+    first_id: String,
+    last_id: String,
+    last_origin: String,
+    last_chain: Vec<String>,
+}
+
+#[derive(Default)]
+struct Digest {
+    groups: Vec<ErrorGroup>,
+    skipped: u64,
+}
+
+/// Collects command errors for the owners, see `run`
+#[derive(Default)]
+pub struct OwnerLog {
+    digest: Mutex<Digest>,
+}
+
+impl OwnerLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an error to the next digest, grouped with the errors of the same command that have
+    /// the same innermost cause
+    pub fn record(&self, id: &str, origin: &ErrorOrigin, chain: Vec<String>) {
+        let summary = group_summary(origin, &chain);
+        let mut digest = match self.digest.lock() {
+            Ok(digest) => digest,
+            Err(_) => return,
+        };
+        if let Some(group) = digest
+            .groups
+            .iter_mut()
+            .find(|group| group.summary == summary)
+        {
+            group.count += 1;
+            group.last_id = id.to_owned();
+            group.last_origin = origin.to_string();
+            group.last_chain = chain;
+        } else if digest.groups.len() < MAX_GROUPS {
+            digest.groups.push(ErrorGroup {
+                summary,
+                count: 1,
+                first_id: id.to_owned(),
+                last_id: id.to_owned(),
+                last_origin: origin.to_string(),
+                last_chain: chain,
+            });
+        } else {
+            digest.skipped += 1;
+        }
+    }
+
+    fn take(&self) -> Digest {
+        self.digest
+            .lock()
+            .map(|mut digest| std::mem::take(&mut *digest))
+            .unwrap_or_default()
+    }
+
+    /// Puts back a digest that could not be sent, the errors recorded since are newer
+    fn restore(&self, older: Digest) {
+        let mut digest = match self.digest.lock() {
+            Ok(digest) => digest,
+            Err(_) => return,
+        };
+        let newer = std::mem::replace(&mut *digest, older);
+        digest.skipped += newer.skipped;
+        for newer_group in newer.groups {
+            if let Some(group) = digest
+                .groups
+                .iter_mut()
+                .find(|group| group.summary == newer_group.summary)
+            {
+                group.count += newer_group.count;
+                group.last_id = newer_group.last_id;
+                group.last_origin = newer_group.last_origin;
+                group.last_chain = newer_group.last_chain;
+            } else if digest.groups.len() < MAX_GROUPS {
+                digest.groups.push(newer_group);
+            } else {
+                digest.skipped += newer_group.count;
+            }
+        }
+    }
+}
+
+/// The command and the innermost cause of an error. Components are told apart by their kind and
+/// action, the state in the rest of their custom id would give every click its own group.
+fn group_summary(origin: &ErrorOrigin, chain: &[String]) -> String {
+    let command = origin
+        .command
+        .splitn(3, ':')
+        .take(2)
+        .collect::<Vec<_>>()
+        .join(":");
+    format!("{}: {}", command, chain.last().map_or("", String::as_str))
+}
+
+/// Sends the collected errors to `target` as one digest embed, backing off while errors keep
+/// coming and going back to `DIGEST_INTERVAL` once a quiet interval passes
+pub async fn run(http: Arc<Http>, owner_log: Arc<OwnerLog>, target: OwnerLogTarget) {
+    let mut interval = DIGEST_INTERVAL;
+    loop {
+        tokio::time::sleep(interval).await;
+        let digest = owner_log.take();
+        if digest.groups.is_empty() {
+            interval = DIGEST_INTERVAL;
+            continue;
+        }
+
+        let embed = digest_embed(&digest).await;
+        let sent = match target {
+            OwnerLogTarget::Channel(channel_id) => channel_id
+                .send_message(&http, |m| m.set_embed(embed))
+                .await
+                .map(|_| ()),
+            OwnerLogTarget::Owner(user_id) => match user_id.create_dm_channel(&http).await {
+                Ok(channel) => channel
+                    .send_message(&http, |m| m.set_embed(embed))
+                    .await
+                    .map(|_| ()),
+                Err(why) => Err(why),
+            },
+        };
+        if let Err(why) = sent {
+            error!("Could not send the error digest to {:?}: {:?}", target, why);
+            owner_log.restore(digest);
+        }
+        interval = min(interval * 2, MAX_DIGEST_INTERVAL);
+    }
+}
+
+/// One field per group while they fit in `EMBED_LENGTH`, the groups past that are only counted
+async fn digest_embed(digest: &Digest) -> CreateEmbed {
+    let total = digest.groups.iter().map(|group| group.count).sum::<u64>() + digest.skipped;
+    let mut skipped = digest.skipped;
+    let mut budget = EMBED_LENGTH - HEADER_LENGTH;
+    let mut fields = vec![];
+    for group in &digest.groups {
+        let ids = if group.count == 1 {
+            format!("`{}`", group.last_id)
+        } else {
+            format!("`{}` … `{}`", group.first_id, group.last_id)
+        };
+        let value = format!(
+            "{} in {}\n```\n{}\n```",
+            ids,
+            group.last_origin,
+            group.last_chain.join("\ncaused by: ")
+        );
+        let name = truncate(
+            &format!("×{} {}", group.count, group.summary),
+            FIELD_NAME_LENGTH,
+        );
+        let value = truncate(&value, FIELD_VALUE_LENGTH);
+        let length = name.chars().count() + value.chars().count();
+        if length <= budget {
+            budget -= length;
+            fields.push((name, value, false));
+        } else {
+            skipped += group.count;
+        }
+    }
+
+    make_error_embed(|e| {
+        e.title(format!(
+            "🚨 {} error{} since the last digest",
+            total,
+            if total == 1 { "" } else { "s" }
+        ));
+        if skipped > 0 {
+            e.description(format!(
+                "{} more errors of other kinds were only counted",
+                skipped
+            ));
+        }
+        e.fields(fields)
+    })
+    .await
+}
+
+fn truncate(text: &str, length: usize) -> String {
+    if text.chars().count() <= length {
+        return text.to_owned();
+    }
+    let mut truncated = text.chars().take(length - 5).collect::<String>();
+    // keep the code block closed
+    if truncated.matches("```").count() % 2 == 1 {
+        truncated.push_str("…\n```");
+    } else {
+        truncated.push('…');
+    }
+    truncated
+}
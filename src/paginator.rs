@@ -1,140 +1,240 @@
-use std::cmp::max;
+use std::{
+    cmp::{max, min},
+    sync::Arc,
+};
 
-use crate::graphql::{
-    lookup_media_page::{self, MediaType},
-    LookupMediaPage,
+use crate::custom_id::{CardAction, CardState, ComponentId, ListAction, ListState};
+use crate::db::watchlist::{
+    WatchInfo, WatchListFilter, WatchListSort, WatchListStore, WatchStatus, MAX_RATING,
 };
-use crate::strings::url::ANILIST_API;
+use crate::error::{BotError, BotResult};
+use crate::media::{Media, MediaPage};
+use crate::metadata::{MediaLookup, MediaSearch, MetadataProvider};
+use crate::suggestions::Suggestion;
 
-use graphql_client::reqwest::post_graphql;
-use reqwest::Client;
+use mongodm::prelude::Bson;
 use serenity::model::interactions::message_component::ButtonStyle;
 use serenity::{
-    builder::{CreateActionRow, CreateButton},
-    model::id::EmojiId,
+    builder::{
+        CreateActionRow, CreateButton, CreateComponents, CreateEmbed, CreateSelectMenu,
+        CreateSelectMenuOption,
+    },
+    model::{channel::ReactionType, id::EmojiId, user::User},
+    utils::Colour,
 };
-use thiserror::Error;
-use tracing::{error, info};
-
-#[derive(Error, Debug)]
-pub enum MediaPaginatorError {
-    #[error("Request error")]
-    RequestError(#[from] reqwest::Error),
-    #[error("Error with GraphQL")]
-    GraphQLError,
-}
+use tracing::info;
 
-type Media = lookup_media_page::LookupMediaPagePageMedia;
-type PageInfo = lookup_media_page::LookupMediaPagePagePageInfo;
+/// Discord caps select option labels at 100 characters
+const SELECT_OPTION_LENGTH: usize = 100;
 
 pub struct MediaPaginator {
-    variables: lookup_media_page::Variables,
-    page_info: PageInfo,
-    media: Vec<Media>,
+    provider: Arc<dyn MetadataProvider>,
+    search: MediaSearch,
+    page: MediaPage,
     index: usize,
 }
 
 impl MediaPaginator {
     pub async fn new(
-        variables: lookup_media_page::Variables,
-    ) -> Result<MediaPaginator, MediaPaginatorError> {
+        provider: Arc<dyn MetadataProvider>,
+        search: MediaSearch,
+    ) -> BotResult<MediaPaginator> {
         let mut media_paginator = MediaPaginator {
-            variables,
-            page_info: PageInfo::default(),
-            media: vec![],
+            provider,
+            search,
+            page: MediaPage::default(),
             index: 0,
         };
         media_paginator.query().await?;
         Ok(media_paginator)
     }
 
-    async fn query(&mut self) -> Result<(), MediaPaginatorError> {
+    /// Resolves an exact id instead of searching, the paginator then holds just that media
+    pub async fn find(
+        provider: Arc<dyn MetadataProvider>,
+        lookup: MediaLookup,
+    ) -> BotResult<Option<MediaPaginator>> {
         info!(
-            "querying page {} of {} {}",
-            &self.variables.page,
-            &self.variables.search,
-            match self.variables.media_type {
-                MediaType::Anime => "Anime",
-                MediaType::Manga => "Manga",
-                _ => "unknown type",
-            }
+            "finding media id {:?} mal id {:?}",
+            &lookup.id, &lookup.id_mal
         );
 
-        let client = Client::new();
+        let media = match provider.media(&lookup).await? {
+            Some(media) => media,
+            None => return Ok(None),
+        };
 
-        // Query from ANILIST_API
-        let response =
-            post_graphql::<LookupMediaPage, _>(&client, ANILIST_API, self.variables.clone())
-                .await?;
+        Ok(Some(MediaPaginator {
+            provider,
+            search: MediaSearch {
+                media_type: lookup.media_type,
+                ..MediaSearch::default()
+            },
+            page: MediaPage {
+                media: vec![media],
+                ..MediaPage::default()
+            },
+            index: 0,
+        }))
+    }
 
-        // Check for errors
-        if let Some(errors) = response.errors {
-            error!("GraphQL errors: {:?}", errors);
-            return Err(MediaPaginatorError::GraphQLError);
-        }
+    async fn query(&mut self) -> BotResult<()> {
+        info!(
+            "querying page {} of {} {:?} from {}",
+            &self.search.page,
+            self.search.search.as_deref().unwrap_or("filters"),
+            self.search.media_type,
+            self.provider.name()
+        );
 
-        // get the page
-        let page = response.data.unwrap().page.unwrap();
-        self.page_info = page.page_info.unwrap();
-        self.media = page.media.unwrap().into_iter().flatten().collect();
+        self.page = self.provider.search(&self.search).await?;
         Ok(())
     }
 
     pub fn current_page(&self) -> Option<Media> {
-        self.media.get(self.index).cloned()
+        self.page.media.get(self.index).cloned()
     }
 
-    pub async fn next_page(&mut self) -> Option<Media> {
+    /// All the media on the queried page
+    pub fn results(&self) -> &[Media] {
+        &self.page.media
+    }
+
+    /// The search page and the position on it, to rebuild the paginator later
+    pub fn position(&self) -> (i64, usize) {
+        (self.search.page, self.index)
+    }
+
+    pub async fn next_page(&mut self) -> BotResult<Option<Media>> {
         if !self.has_next() {
-            return None;
+            return Ok(None);
         }
 
         self.index += 1;
         // if the index is in the next page, query the page first
-        if self.index >= self.media.len() {
+        if self.index >= self.page.media.len() {
             self.index = 0;
 
             // update page number
-            self.variables.page += 1;
+            self.search.page += 1;
 
-            self.query().await.ok()?;
+            self.query().await?;
         }
-        self.media.get(self.index).cloned()
+        Ok(self.page.media.get(self.index).cloned())
     }
 
-    pub async fn prev_page(&mut self) -> Option<Media> {
+    pub async fn prev_page(&mut self) -> BotResult<Option<Media>> {
         if !self.has_prev() {
-            return None;
+            return Ok(None);
         }
 
         if self.index == 0 {
             // update page number
-            self.variables.page -= 1;
+            self.search.page -= 1;
 
-            self.query().await.ok()?;
+            self.query().await?;
 
-            self.index = max(self.media.len() - 1, 0);
+            self.index = self.page.media.len().saturating_sub(1);
         } else {
             self.index -= 1;
         }
 
-        self.media.get(self.index).cloned()
+        Ok(self.page.media.get(self.index).cloned())
     }
 
     fn has_next(&self) -> bool {
-        self.index + 1 < self.media.len() || self.page_info.has_next_page
+        self.index + 1 < self.page.media.len() || self.page.has_next_page
     }
 
     fn has_prev(&self) -> bool {
-        self.index > 0 || self.page_info.current_page != 1
+        self.index > 0 || self.page.current_page != 1
     }
 
-    pub fn action_row(&self) -> CreateActionRow {
+    /// Jumps to the media at `index` on the current page
+    pub fn jump_to(&mut self, index: usize) -> Option<Media> {
+        let media = self.page.media.get(index).cloned()?;
+        self.index = index;
+        Some(media)
+    }
+
+    /// Lists the titles on the current page with their format and start year
+    pub fn results_embed(&self) -> CreateEmbed {
+        let mut embed = CreateEmbed::default();
+        embed.title(format!(
+            "🔎 Results · page {}/{}",
+            self.page.current_page,
+            max(self.page.last_page, 1)
+        ));
+        embed.color(Colour::new(0x345A78));
+
+        let first = (self.page.current_page - 1) * self.search.per_page;
+        let lines = self
+            .page
+            .media
+            .iter()
+            .enumerate()
+            .map(|(i, media)| {
+                let line = format!(
+                    "**{}.** {} · {}",
+                    first + i as i64 + 1,
+                    media.title_link(),
+                    Self::result_details(media)
+                );
+                // mark the media the card is on
+                if i == self.index {
+                    format!("▶ {}", line)
+                } else {
+                    line
+                }
+            })
+            .collect::<Vec<_>>();
+        embed.description(lines.join("\n"));
+        embed
+    }
+
+    fn result_details(media: &Media) -> String {
+        format!(
+            "{} · {}",
+            media
+                .format
+                .map_or_else(|| "?".to_owned(), |format| format.to_string()),
+            media
+                .start_date
+                .as_ref()
+                .and_then(|date| date.year)
+                .map_or("?".to_owned(), |year| year.to_string())
+        )
+    }
+
+    /// Navigation, jump, entry and status rows of a media card, the menu shows `watch_info`'s
+    /// status. The results overview only gets the navigation and jump rows. Every custom id
+    /// carries `state` so the card can be rebuilt whenever it is used.
+    pub fn components(
+        &self,
+        watch_info: Option<&WatchInfo>,
+        state: &CardState,
+    ) -> CreateComponents {
+        let mut components = CreateComponents::default();
+        components.add_action_row(self.action_row(state));
+        // a single found media has nowhere to jump to
+        if self.page.media.len() > 1 {
+            components.add_action_row(self.jump_row(state));
+        }
+        if !state.show_results {
+            components
+                .add_action_row(self.entry_row(state))
+                .add_action_row(self.status_row(watch_info, state));
+        }
+        components
+    }
+
+    fn action_row(&self, state: &CardState) -> CreateActionRow {
+        let show_results = state.show_results;
         let mut ar = CreateActionRow::default();
         ar.add_button(
             CreateButton::default()
                 .style(ButtonStyle::Secondary)
-                .custom_id("PREV_PAGE")
+                .custom_id(card_id(CardAction::PrevPage, state))
                 .emoji(EmojiId(877152666046832670))
                 .disabled(!self.has_prev())
                 .to_owned(),
@@ -142,28 +242,457 @@ impl MediaPaginator {
         ar.add_button(
             CreateButton::default()
                 .style(ButtonStyle::Primary)
-                .custom_id("WATCH")
-                .emoji('👀')
+                .custom_id(card_id(CardAction::Suggest, state))
+                .emoji('🌟')
                 .to_owned(),
         );
         ar.add_button(
             CreateButton::default()
-                .style(ButtonStyle::Primary)
-                .custom_id("FINISH")
-                .emoji('🏁')
+                .style(ButtonStyle::Secondary)
+                .custom_id(card_id(CardAction::NextPage, state))
+                .emoji(EmojiId(877152666080387122))
+                .disabled(!self.has_next())
+                .to_owned(),
+        );
+        ar.add_button(
+            CreateButton::default()
+                .style(ButtonStyle::Secondary)
+                .custom_id(card_id(CardAction::Results, state))
+                .emoji(if show_results { '🎴' } else { '🔎' })
+                .label(if show_results { "Card" } else { "Results" })
+                .to_owned(),
+        );
+        ar
+    }
+
+    fn jump_row(&self, state: &CardState) -> CreateActionRow {
+        let mut menu = CreateSelectMenu::default();
+        menu.custom_id(card_id(CardAction::JumpTo, state))
+            .placeholder("Jump to");
+        menu.options(|opts| {
+            for (i, media) in self.page.media.iter().enumerate() {
+                opts.add_option(
+                    CreateSelectMenuOption::default()
+                        .label(
+                            media
+                                .title
+                                .chars()
+                                .take(SELECT_OPTION_LENGTH)
+                                .collect::<String>(),
+                        )
+                        .value(i)
+                        .description(Self::result_details(media))
+                        .default_selection(i == self.index)
+                        .to_owned(),
+                );
+            }
+            opts
+        });
+
+        let mut ar = CreateActionRow::default();
+        ar.add_select_menu(menu);
+        ar
+    }
+
+    fn entry_row(&self, state: &CardState) -> CreateActionRow {
+        let mut ar = CreateActionRow::default();
+        ar.add_button(
+            CreateButton::default()
+                .style(ButtonStyle::Secondary)
+                .custom_id(card_id(CardAction::ProgressDec, state))
+                .label("-1")
+                .to_owned(),
+        );
+        ar.add_button(
+            CreateButton::default()
+                .style(ButtonStyle::Secondary)
+                .custom_id(card_id(CardAction::ProgressInc, state))
+                .label("+1")
                 .to_owned(),
         );
         ar.add_button(
             CreateButton::default()
                 .style(ButtonStyle::Primary)
-                .custom_id("SUGGEST")
-                .emoji('🌟')
+                .custom_id(card_id(CardAction::Rate, state))
+                .emoji('⭐')
+                .label("Rate")
+                .to_owned(),
+        );
+        ar
+    }
+
+    fn status_row(&self, watch_info: Option<&WatchInfo>, state: &CardState) -> CreateActionRow {
+        let media_type = &self.search.media_type;
+        let current = watch_info.map_or(WatchStatus::NotSeen, |info| info.watch_status);
+
+        let mut menu = CreateSelectMenu::default();
+        menu.custom_id(card_id(CardAction::Status, state))
+            .placeholder("Set status");
+        menu.options(|opts| {
+            for status in WatchStatus::ALL {
+                opts.add_option(
+                    CreateSelectMenuOption::default()
+                        .label(status.as_label(media_type))
+                        .value(Bson::from(status).as_str().unwrap_or_default())
+                        .emoji(ReactionType::Unicode(
+                            status.as_emoji(media_type).to_owned(),
+                        ))
+                        .default_selection(status == current)
+                        .to_owned(),
+                );
+            }
+            opts
+        });
+
+        let mut ar = CreateActionRow::default();
+        ar.add_select_menu(menu);
+        ar
+    }
+}
+
+fn card_id(action: CardAction, state: &CardState) -> String {
+    ComponentId::Card(action, state.clone()).to_string()
+}
+
+const WATCH_LIST_PAGE_SIZE: i64 = 10;
+
+pub struct WatchListPaginator {
+    provider: Arc<dyn MetadataProvider>,
+    repo: Arc<dyn WatchListStore>,
+    user: User,
+    filter: WatchListFilter,
+    sort: WatchListSort,
+    page: u64,
+    total: u64,
+    entries: Vec<(WatchInfo, Option<Media>)>,
+}
+
+impl WatchListPaginator {
+    pub async fn new(
+        provider: Arc<dyn MetadataProvider>,
+        repo: Arc<dyn WatchListStore>,
+        user: User,
+        filter: WatchListFilter,
+        sort: WatchListSort,
+        page: u64,
+    ) -> BotResult<WatchListPaginator> {
+        let mut watch_list_paginator = WatchListPaginator {
+            provider,
+            repo,
+            user,
+            filter,
+            sort,
+            page,
+            total: 0,
+            entries: vec![],
+        };
+        watch_list_paginator.query().await?;
+        Ok(watch_list_paginator)
+    }
+
+    async fn query(&mut self) -> BotResult<()> {
+        info!(
+            "querying page {} of {}'s watch list",
+            &self.page,
+            self.user.tag()
+        );
+
+        self.total = self
+            .repo
+            .count_watch_list(self.user.id, self.filter)
+            .await
+            .map_err(BotError::Storage)?;
+        // the list may have shrunk since the page was posted
+        self.page = min(self.page, self.last_page());
+        let watch_list = self
+            .repo
+            .find_watch_list(
+                self.user.id,
+                self.filter,
+                self.sort,
+                self.page * WATCH_LIST_PAGE_SIZE as u64,
+                WATCH_LIST_PAGE_SIZE,
+            )
+            .await
+            .map_err(BotError::Storage)?;
+
+        // Hydrate the whole page at once
+        let ids = watch_list
+            .iter()
+            .map(|info| info.anilist_media_id)
+            .collect::<Vec<_>>();
+        let media = self.provider.media_by_ids(&ids).await?;
+        self.entries = watch_list
+            .into_iter()
+            .map(|info| {
+                let media = media
+                    .iter()
+                    .find(|media| media.id == info.anilist_media_id)
+                    .cloned();
+                (info, media)
+            })
+            .collect();
+        Ok(())
+    }
+
+    /// The page the list is on, to rebuild the paginator later
+    pub fn state(&self) -> ListState {
+        ListState {
+            user: self.user.id,
+            filter: self.filter,
+            sort: self.sort,
+            page: self.page,
+        }
+    }
+
+    fn list_id(&self, action: ListAction) -> String {
+        ComponentId::List(action, self.state()).to_string()
+    }
+
+    fn last_page(&self) -> u64 {
+        self.total.saturating_sub(1) / WATCH_LIST_PAGE_SIZE as u64
+    }
+
+    fn has_next(&self) -> bool {
+        self.page < self.last_page()
+    }
+
+    fn has_prev(&self) -> bool {
+        self.page > 0
+    }
+
+    pub fn embed(&self) -> CreateEmbed {
+        let mut embed = CreateEmbed::default();
+        embed.title(format!("📜 {}'s watch list", self.user.name));
+        embed.color(Colour::new(0x345A78));
+        if let Some(avatar) = self.user.avatar_url() {
+            embed.thumbnail(avatar);
+        }
+
+        let first = self.page * WATCH_LIST_PAGE_SIZE as u64;
+        let lines = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(i, (info, media))| {
+                let title = match media {
+                    Some(media) => media.title_link(),
+                    None => format!("Unknown media #{}", info.anilist_media_id),
+                };
+                let mut line = format!("**{}.** {}", first + i as u64 + 1, title);
+                if info.watch_status != WatchStatus::NotSeen {
+                    line.push_str(&format!(
+                        " {}",
+                        info.watch_status.as_emoji(&info.media_type)
+                    ));
+                }
+                if info.repeat > 0 {
+                    line.push_str(&format!(" 🔁×{}", info.repeat));
+                }
+                if info.suggests {
+                    line.push_str(" 🌟");
+                }
+                if let Some(rating) = info.rating {
+                    line.push_str(&format!(" ⭐ {}/{}", rating, MAX_RATING));
+                }
+                line
+            })
+            .collect::<Vec<String>>();
+        embed.description(if lines.is_empty() {
+            "Nothing here yet...".to_owned()
+        } else {
+            lines.join("\n")
+        });
+        embed.footer(|f| {
+            f.text(format!(
+                "{} • Page {}/{} • {} entries",
+                self.filter,
+                self.page + 1,
+                self.last_page() + 1,
+                self.total
+            ))
+        });
+
+        embed
+    }
+
+    pub fn action_row(&self) -> CreateActionRow {
+        let mut ar = CreateActionRow::default();
+        ar.add_button(
+            CreateButton::default()
+                .style(ButtonStyle::Secondary)
+                .custom_id(self.list_id(ListAction::PrevPage))
+                .emoji(EmojiId(877152666046832670))
+                .disabled(!self.has_prev())
+                .to_owned(),
+        );
+        ar.add_button(
+            CreateButton::default()
+                .style(ButtonStyle::Secondary)
+                .custom_id(self.list_id(ListAction::NextPage))
+                .emoji(EmojiId(877152666080387122))
+                .disabled(!self.has_next())
+                .to_owned(),
+        );
+        ar
+    }
+}
+
+const SUGGESTION_PAGE_SIZE: usize = 10;
+/// Suggesters named on a line before the rest are only counted
+const SHOWN_SUGGESTERS: usize = 3;
+
+pub struct SuggestionPaginator {
+    provider: Arc<dyn MetadataProvider>,
+    guild_name: String,
+    suggestions: Arc<Vec<Suggestion>>,
+    page: u64,
+    media: Vec<Media>,
+}
+
+impl SuggestionPaginator {
+    pub async fn new(
+        provider: Arc<dyn MetadataProvider>,
+        guild_name: String,
+        suggestions: Arc<Vec<Suggestion>>,
+        page: u64,
+    ) -> BotResult<SuggestionPaginator> {
+        let mut suggestion_paginator = SuggestionPaginator {
+            provider,
+            guild_name,
+            suggestions,
+            page: 0,
+            media: vec![],
+        };
+        // suggestions may have been taken back since the page was posted
+        suggestion_paginator.page = min(page, suggestion_paginator.last_page());
+        suggestion_paginator.query().await?;
+        Ok(suggestion_paginator)
+    }
+
+    fn current_suggestions(&self) -> &[Suggestion] {
+        let first = min(
+            self.page as usize * SUGGESTION_PAGE_SIZE,
+            self.suggestions.len(),
+        );
+        let last = min(first + SUGGESTION_PAGE_SIZE, self.suggestions.len());
+        &self.suggestions[first..last]
+    }
+
+    async fn query(&mut self) -> BotResult<()> {
+        info!(
+            "querying page {} of the suggestions in {}",
+            &self.page, &self.guild_name
+        );
+        let ids = self
+            .current_suggestions()
+            .iter()
+            .map(|suggestion| suggestion.media_id)
+            .collect::<Vec<_>>();
+        if ids.is_empty() {
+            self.media = vec![];
+            return Ok(());
+        }
+
+        // Hydrate the whole page at once
+        self.media = self.provider.media_by_ids(&ids).await?;
+        Ok(())
+    }
+
+    fn last_page(&self) -> u64 {
+        (self.suggestions.len().saturating_sub(1) / SUGGESTION_PAGE_SIZE) as u64
+    }
+
+    fn suggestions_id(&self, action: ListAction) -> String {
+        ComponentId::Suggestions(action, self.page).to_string()
+    }
+
+    fn has_next(&self) -> bool {
+        self.page < self.last_page()
+    }
+
+    fn has_prev(&self) -> bool {
+        self.page > 0
+    }
+
+    pub fn page(&self) -> u64 {
+        self.page
+    }
+
+    pub fn embed(&self) -> CreateEmbed {
+        let mut embed = CreateEmbed::default();
+        embed.title(format!("🌟 What {} suggests", self.guild_name));
+        embed.color(Colour::new(0x345A78));
+
+        let first = self.page as usize * SUGGESTION_PAGE_SIZE;
+        let lines = self
+            .current_suggestions()
+            .iter()
+            .enumerate()
+            .map(|(i, suggestion)| {
+                let title = match self
+                    .media
+                    .iter()
+                    .find(|media| media.id == suggestion.media_id)
+                {
+                    Some(media) => media.title_link(),
+                    None => format!("Unknown media #{}", suggestion.media_id),
+                };
+                let mut suggesters = suggestion
+                    .suggested_by
+                    .iter()
+                    .take(SHOWN_SUGGESTERS)
+                    .map(|user_id| format!("<@{}>", user_id))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                if suggestion.suggested_by.len() > SHOWN_SUGGESTERS {
+                    suggesters.push_str(&format!(
+                        " +{}",
+                        suggestion.suggested_by.len() - SHOWN_SUGGESTERS
+                    ));
+                }
+                format!(
+                    "**{}.** {} 🌟 {} {} {}\nby {}",
+                    first + i + 1,
+                    title,
+                    suggestion.suggested_by.len(),
+                    WatchStatus::Finished.as_emoji(&suggestion.media_type),
+                    suggestion.finished,
+                    suggesters
+                )
+            })
+            .collect::<Vec<String>>();
+        embed.description(if lines.is_empty() {
+            "Nobody suggests anything yet, press 🌟 on a card to suggest it".to_owned()
+        } else {
+            lines.join("\n")
+        });
+        embed.footer(|f| {
+            f.text(format!(
+                "Page {}/{} • {} titles",
+                self.page + 1,
+                self.last_page() + 1,
+                self.suggestions.len()
+            ))
+        });
+
+        embed
+    }
+
+    pub fn action_row(&self) -> CreateActionRow {
+        let mut ar = CreateActionRow::default();
+        ar.add_button(
+            CreateButton::default()
+                .style(ButtonStyle::Secondary)
+                .custom_id(self.suggestions_id(ListAction::PrevPage))
+                .emoji(EmojiId(877152666046832670))
+                .disabled(!self.has_prev())
                 .to_owned(),
         );
         ar.add_button(
             CreateButton::default()
                 .style(ButtonStyle::Secondary)
-                .custom_id("NEXT_PAGE")
+                .custom_id(self.suggestions_id(ListAction::NextPage))
                 .emoji(EmojiId(877152666080387122))
                 .disabled(!self.has_next())
                 .to_owned(),
@@ -0,0 +1,293 @@
+use thiserror::Error;
+
+use crate::{
+    media::{MediaFormat, MediaSort, MediaStatus, MediaType},
+    metadata::{MediaLookup, MediaSearch},
+};
+
+/// The keys of `key:value` filters
+const FILTER_KEYS: &[&str] = &["year", "format", "genre", "status", "sort", "adult"];
+
+/// Genres AniList knows, filters match them ignoring case, spaces and dashes
+const GENRES: &[&str] = &[
+    "Action",
+    "Adventure",
+    "Comedy",
+    "Drama",
+    "Ecchi",
+    "Fantasy",
+    "Hentai",
+    "Horror",
+    "Mahou Shoujo",
+    "Mecha",
+    "Music",
+    "Mystery",
+    "Psychological",
+    "Romance",
+    "Sci-Fi",
+    "Slice of Life",
+    "Sports",
+    "Supernatural",
+    "Thriller",
+];
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum QueryError {
+    #[error(
+        "I don't know the `{key}:` filter, did you mean `{known}:`? Titles with a colon go in \
+        quotes, like `\"Re:Zero\"`"
+    )]
+    UnknownKey { key: String, known: &'static str },
+    #[error("`{value}` is not a valid `{key}:`, try {expected}")]
+    InvalidValue {
+        key: String,
+        value: String,
+        expected: &'static str,
+    },
+    #[error("Tell me a title or some filters to search for")]
+    Empty,
+}
+
+/// What a lookup resolves to, an exact media or a filtered search
+#[derive(Debug, PartialEq, Eq)]
+pub enum MediaQuery {
+    Find(MediaLookup),
+    Search(MediaSearch),
+}
+
+impl MediaQuery {
+    /// Parses `frieren year:2023 format:tv genre:fantasy status:releasing sort:score`,
+    /// everything that is not a `key:value` filter is part of the title
+    pub fn parse(input: &str, media_type: MediaType) -> Result<Self, QueryError> {
+        if let Some(lookup) = MediaLookup::parse(input, media_type) {
+            return Ok(Self::Find(lookup));
+        }
+
+        let mut title = vec![];
+        let mut has_filters = false;
+        let mut search = MediaSearch {
+            media_type,
+            ..MediaSearch::default()
+        };
+        for (token, quoted) in tokenize(input) {
+            let (key, value) = match token.split_once(':') {
+                Some((key, value)) if !quoted && !value.is_empty() => (key.to_lowercase(), value),
+                _ => {
+                    title.push(token);
+                    continue;
+                }
+            };
+            if !FILTER_KEYS.contains(&key.as_str()) {
+                // a typo of a filter is reported, anything else is part of a title like `re:zero`
+                match FILTER_KEYS.iter().find(|known| is_typo_of(&key, known)) {
+                    Some(known) => return Err(QueryError::UnknownKey { key, known }),
+                    None => {
+                        title.push(token);
+                        continue;
+                    }
+                }
+            }
+            let invalid = |expected| QueryError::InvalidValue {
+                key: key.clone(),
+                value: value.to_owned(),
+                expected,
+            };
+
+            match key.as_str() {
+                "year" => {
+                    search.year = Some(
+                        value
+                            .parse::<i64>()
+                            .map_err(|_| invalid("a year like `2023`"))?,
+                    );
+                }
+                "format" => {
+                    let formats = split_values(value)
+                        .map(|format| parse_format(&format))
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or_else(|| {
+                            invalid(
+                                "`tv`, `tv_short`, `movie`, `special`, `ova`, `ona`, `music`, \
+                                `manga`, `novel` or `one_shot`",
+                            )
+                        })?;
+                    search.formats.extend(formats);
+                }
+                "genre" => {
+                    let genres = split_values(value)
+                        .map(|genre| parse_genre(&genre).map(str::to_owned))
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or_else(|| {
+                            invalid("an AniList genre like `fantasy` or `slice_of_life`")
+                        })?;
+                    search.genres.extend(genres);
+                }
+                "status" => {
+                    search.status = Some(parse_status(&value.to_lowercase()).ok_or_else(|| {
+                        invalid("`releasing`, `finished`, `upcoming`, `cancelled` or `hiatus`")
+                    })?);
+                }
+                "sort" => {
+                    search.sort = Some(parse_sort(&value.to_lowercase()).ok_or_else(|| {
+                        invalid(
+                            "`score`, `popularity`, `trending`, `newest`, `oldest`, `title` \
+                            or `favourites`",
+                        )
+                    })?);
+                }
+                "adult" => {
+                    search.is_adult = Some(match value.to_lowercase().as_str() {
+                        "true" | "yes" => true,
+                        "false" | "no" => false,
+                        _ => return Err(invalid("`yes` or `no`")),
+                    });
+                }
+                _ => unreachable!("Unhandled filter key {}", key),
+            }
+            has_filters = true;
+        }
+
+        if title.is_empty() {
+            if !has_filters {
+                return Err(QueryError::Empty);
+            }
+            // without a title AniList orders by id, popular media make a better first page
+            search.sort.get_or_insert(MediaSort::Popularity);
+        } else {
+            search.search = Some(title.join(" "));
+        }
+
+        Ok(Self::Search(search))
+    }
+
+    /// Keeps adult media out of the results, overriding an `adult:` filter
+    pub fn hide_adult(&mut self) {
+        match self {
+            Self::Find(lookup) => lookup.is_adult = Some(false),
+            Self::Search(search) => search.is_adult = Some(false),
+        }
+    }
+}
+
+/// Whether `key` is one edit away from the filter key `known`, like `yaer` or `genres`
+fn is_typo_of(key: &str, known: &str) -> bool {
+    let key = key.chars().collect::<Vec<_>>();
+    let known = known.chars().collect::<Vec<_>>();
+    let (shorter, longer) = if key.len() <= known.len() {
+        (&key, &known)
+    } else {
+        (&known, &key)
+    };
+    if longer.len() - shorter.len() > 1 {
+        return false;
+    }
+    let prefix = shorter
+        .iter()
+        .zip(longer.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    if prefix == shorter.len() {
+        return true;
+    }
+    if shorter.len() == longer.len() {
+        // one substituted or two swapped characters
+        shorter[prefix + 1..] == longer[prefix + 1..]
+            || (prefix + 1 < shorter.len()
+                && shorter[prefix] == longer[prefix + 1]
+                && shorter[prefix + 1] == longer[prefix]
+                && shorter[prefix + 2..] == longer[prefix + 2..])
+    } else {
+        // one missing character
+        shorter[prefix..] == longer[prefix + 1..]
+    }
+}
+
+/// Splits on whitespace, keeping `"quoted words"` together, and flags the quoted tokens
+fn tokenize(input: &str) -> Vec<(String, bool)> {
+    let mut tokens = vec![];
+    let mut token = String::new();
+    let mut quoted = false;
+    for c in input.chars() {
+        match c {
+            '"' if quoted => {
+                tokens.push((std::mem::take(&mut token), true));
+                quoted = false;
+            }
+            '"' if token.is_empty() => quoted = true,
+            c if c.is_whitespace() && !quoted => {
+                if !token.is_empty() {
+                    tokens.push((std::mem::take(&mut token), false));
+                }
+            }
+            c => token.push(c),
+        }
+    }
+    if !token.is_empty() {
+        tokens.push((token, quoted));
+    }
+
+    tokens.retain(|(token, _)| !token.is_empty());
+    tokens
+}
+
+fn split_values(value: &str) -> impl Iterator<Item = String> + '_ {
+    value
+        .split(',')
+        .filter(|value| !value.is_empty())
+        .map(|value| value.to_lowercase().replace('-', "_"))
+}
+
+fn parse_format(format: &str) -> Option<MediaFormat> {
+    match format {
+        "tv" => Some(MediaFormat::Tv),
+        "tv_short" | "short" => Some(MediaFormat::TvShort),
+        "movie" => Some(MediaFormat::Movie),
+        "special" => Some(MediaFormat::Special),
+        "ova" => Some(MediaFormat::Ova),
+        "ona" => Some(MediaFormat::Ona),
+        "music" => Some(MediaFormat::Music),
+        "manga" => Some(MediaFormat::Manga),
+        "novel" | "light_novel" => Some(MediaFormat::Novel),
+        "one_shot" | "oneshot" => Some(MediaFormat::OneShot),
+        _ => None,
+    }
+}
+
+fn parse_genre(genre: &str) -> Option<&'static str> {
+    let normalize = |genre: &str| {
+        genre
+            .chars()
+            .filter(|c| c.is_alphanumeric())
+            .collect::<String>()
+            .to_lowercase()
+    };
+    let genre = normalize(genre);
+    GENRES
+        .iter()
+        .find(|known| normalize(known) == genre)
+        .copied()
+}
+
+fn parse_status(status: &str) -> Option<MediaStatus> {
+    match status {
+        "releasing" | "airing" | "ongoing" => Some(MediaStatus::Releasing),
+        "finished" | "completed" => Some(MediaStatus::Finished),
+        "upcoming" | "not_yet_released" => Some(MediaStatus::NotYetReleased),
+        "cancelled" | "canceled" => Some(MediaStatus::Cancelled),
+        "hiatus" => Some(MediaStatus::Hiatus),
+        _ => None,
+    }
+}
+
+fn parse_sort(sort: &str) -> Option<MediaSort> {
+    match sort {
+        "score" => Some(MediaSort::Score),
+        "popularity" | "popular" => Some(MediaSort::Popularity),
+        "trending" => Some(MediaSort::Trending),
+        "newest" => Some(MediaSort::Newest),
+        "oldest" => Some(MediaSort::Oldest),
+        "title" => Some(MediaSort::Title),
+        "favourites" | "favorites" => Some(MediaSort::Favourites),
+        _ => None,
+    }
+}
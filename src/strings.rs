@@ -1,5 +1,9 @@
 pub mod url {
     pub const ANILIST_API: &str = "https://graphql.anilist.co/";
+    pub const ANILIST_AUTHORIZE: &str = "https://anilist.co/api/v2/oauth/authorize";
+    pub const KITSU_API: &str = "https://kitsu.io/api/edge";
+    pub const KITSU_SITE: &str = "https://kitsu.io";
+    pub const JIKAN_API: &str = "https://api.jikan.moe/v4";
 }
 
 pub mod card {
@@ -7,7 +11,12 @@ pub mod card {
     pub const FORMAT: &str = "📂 Format";
     pub const GENRES: &str = "🎴 Genres";
     pub const AIRED: &str = "🗓️ Aired";
+    pub const PUBLISHED: &str = "🗓️ Published";
     pub const EPISODES: &str = "💽 Total Episodes";
     pub const DURATION: &str = "⏱️ Duration";
+    pub const CHAPTERS: &str = "📑 Total Chapters";
+    pub const VOLUMES: &str = "📚 Total Volumes";
     pub const RATING: &str = "⭐ Average Rating";
+    pub const USER_RATING: &str = "🌠 Personal Rating";
+    pub const PROGRESS: &str = "📈 Progress";
 }
@@ -0,0 +1,304 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use mongodm::{prelude::MongoDatabase, ToRepository};
+use serenity::{
+    http::Http,
+    model::id::{GuildId, UserId},
+    CacheAndHttp,
+};
+use tracing::{error, warn};
+
+use crate::{
+    db::{
+        guild_config::{GuildConfig, GuildConfigCollectionExt},
+        watchlist::{WatchInfo, WatchListStore},
+    },
+    error::{discord_error_code, BotError, BotResult},
+    media::MediaType,
+    metadata::MetadataProvider,
+    paginator::SuggestionPaginator,
+};
+
+const BOARD_INTERVAL: Duration = Duration::from_secs(15 * 60);
+/// How long a guild's member list is trusted
+const MEMBERSHIP_TTL: Duration = Duration::from_secs(60 * 60);
+/// Discord lists at most this many members per request
+const MEMBER_PAGE_SIZE: u64 = 1000;
+/// How long a ranking is paged through before the suggestions are ranked again
+const RANKING_TTL: Duration = Duration::from_secs(5 * 60);
+/// Discord's JSON error code for deleted messages
+const UNKNOWN_MESSAGE: isize = 10008;
+
+/// A title members of a guild suggest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suggestion {
+    pub media_id: i64,
+    pub media_type: MediaType,
+    pub suggested_by: Vec<UserId>,
+    /// How many members finished it
+    pub finished: usize,
+}
+
+/// Groups the `suggested` and `finished` entries of `members` by media, the most suggested first
+/// and ties going to what more members finished
+pub fn rank_suggestions(
+    suggested: Vec<WatchInfo>,
+    finished: Vec<WatchInfo>,
+    members: &HashSet<UserId>,
+) -> Vec<Suggestion> {
+    let mut by_media = BTreeMap::new();
+    for info in suggested
+        .into_iter()
+        .filter(|info| members.contains(&info.discord_user_id))
+    {
+        by_media
+            .entry(info.anilist_media_id)
+            .or_insert_with(|| Suggestion {
+                media_id: info.anilist_media_id,
+                media_type: info.media_type,
+                suggested_by: vec![],
+                finished: 0,
+            })
+            .suggested_by
+            .push(info.discord_user_id);
+    }
+    for info in finished
+        .into_iter()
+        .filter(|info| members.contains(&info.discord_user_id))
+    {
+        if let Some(suggestion) = by_media.get_mut(&info.anilist_media_id) {
+            suggestion.finished += 1;
+        }
+    }
+
+    let mut suggestions = by_media.into_values().collect::<Vec<_>>();
+    suggestions.sort_by(|a, b| {
+        b.suggested_by
+            .len()
+            .cmp(&a.suggested_by.len())
+            .then(b.finished.cmp(&a.finished))
+            .then(a.media_id.cmp(&b.media_id))
+    });
+    suggestions
+}
+
+#[derive(Clone)]
+struct Ranking {
+    suggestions: Arc<Vec<Suggestion>>,
+    ranked_at: Instant,
+}
+
+#[derive(Clone)]
+struct Members {
+    user_ids: Arc<HashSet<UserId>>,
+    listed_at: Instant,
+}
+
+/// The suggestions of every guild as last ranked, and who is in which guild. Member lists are
+/// fetched once per `MEMBERSHIP_TTL`, which needs the privileged server members intent.
+#[derive(Default)]
+pub struct SuggestionCache {
+    members: Mutex<HashMap<GuildId, Members>>,
+    rankings: Mutex<HashMap<GuildId, Ranking>>,
+}
+
+impl SuggestionCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// What the members of `guild_id` suggest, as ranked up to `RANKING_TTL` ago so pages of
+    /// the same ranking are shown
+    pub async fn suggestions(
+        &self,
+        http: &impl AsRef<Http>,
+        watch_list: &dyn WatchListStore,
+        guild_id: GuildId,
+    ) -> BotResult<Arc<Vec<Suggestion>>> {
+        let ranking = self
+            .rankings
+            .lock()
+            .ok()
+            .and_then(|rankings| rankings.get(&guild_id).cloned());
+        match ranking {
+            Some(ranking) if ranking.ranked_at.elapsed() < RANKING_TTL => Ok(ranking.suggestions),
+            _ => self.rank(http, watch_list, guild_id).await,
+        }
+    }
+
+    /// Ranks what the members of `guild_id` suggest again
+    pub async fn rank(
+        &self,
+        http: &impl AsRef<Http>,
+        watch_list: &dyn WatchListStore,
+        guild_id: GuildId,
+    ) -> BotResult<Arc<Vec<Suggestion>>> {
+        let suggested = watch_list
+            .find_suggested()
+            .await
+            .map_err(BotError::Storage)?;
+        let mut media_ids = suggested
+            .iter()
+            .map(|info| info.anilist_media_id)
+            .collect::<Vec<_>>();
+        media_ids.sort_unstable();
+        media_ids.dedup();
+        let finished = watch_list
+            .find_finished(&media_ids)
+            .await
+            .map_err(BotError::Storage)?;
+
+        let members = self.members(http, guild_id).await?;
+
+        let suggestions = Arc::new(rank_suggestions(suggested, finished, &members));
+        if let Ok(mut rankings) = self.rankings.lock() {
+            rankings.insert(
+                guild_id,
+                Ranking {
+                    suggestions: suggestions.clone(),
+                    ranked_at: Instant::now(),
+                },
+            );
+        }
+        Ok(suggestions)
+    }
+
+    /// Everyone in `guild_id`, as listed up to `MEMBERSHIP_TTL` ago
+    async fn members(
+        &self,
+        http: &impl AsRef<Http>,
+        guild_id: GuildId,
+    ) -> BotResult<Arc<HashSet<UserId>>> {
+        let members = self
+            .members
+            .lock()
+            .ok()
+            .and_then(|members| members.get(&guild_id).cloned());
+        if let Some(members) = members {
+            if members.listed_at.elapsed() < MEMBERSHIP_TTL {
+                return Ok(members.user_ids);
+            }
+        }
+
+        let mut user_ids = HashSet::new();
+        let mut after = None;
+        loop {
+            let page = guild_id
+                .members(http, Some(MEMBER_PAGE_SIZE), after)
+                .await?;
+            let last_page = (page.len() as u64) < MEMBER_PAGE_SIZE;
+            after = page.last().map(|member| member.user.id);
+            user_ids.extend(page.into_iter().map(|member| member.user.id));
+            if last_page {
+                break;
+            }
+        }
+
+        let user_ids = Arc::new(user_ids);
+        if let Ok(mut members) = self.members.lock() {
+            members.insert(
+                guild_id,
+                Members {
+                    user_ids: user_ids.clone(),
+                    listed_at: Instant::now(),
+                },
+            );
+        }
+        Ok(user_ids)
+    }
+}
+
+/// Keeps the suggestion board of every guild that has one up to date, every `BOARD_INTERVAL`
+pub async fn run(
+    cache_and_http: Arc<CacheAndHttp>,
+    db: MongoDatabase,
+    watch_list: Arc<dyn WatchListStore>,
+    metadata: Arc<dyn MetadataProvider>,
+    suggestion_cache: Arc<SuggestionCache>,
+) {
+    let mut interval = tokio::time::interval(BOARD_INTERVAL);
+    loop {
+        interval.tick().await;
+        let boards = match db
+            .repository::<GuildConfig>()
+            .find_suggestion_boards()
+            .await
+        {
+            Ok(boards) => boards,
+            Err(why) => {
+                error!("Could not find suggestion boards: {:?}", why);
+                continue;
+            }
+        };
+        for config in boards {
+            if let Err(why) = update_board(
+                &cache_and_http,
+                &db,
+                watch_list.as_ref(),
+                &metadata,
+                &suggestion_cache,
+                &config,
+            )
+            .await
+            {
+                warn!(
+                    "Could not update the suggestion board of {}: {:?}",
+                    config.discord_guild_id, why
+                );
+            }
+        }
+    }
+}
+
+/// Edits the board message, or posts and pins a new one when it was deleted
+async fn update_board(
+    cache_and_http: &Arc<CacheAndHttp>,
+    db: &MongoDatabase,
+    watch_list: &dyn WatchListStore,
+    metadata: &Arc<dyn MetadataProvider>,
+    suggestion_cache: &SuggestionCache,
+    config: &GuildConfig,
+) -> Result<()> {
+    let channel_id = match config.suggestion_channel {
+        Some(channel_id) => channel_id,
+        None => return Ok(()),
+    };
+    let guild_id = config.discord_guild_id;
+    let suggestions = suggestion_cache
+        .rank(&cache_and_http.http, watch_list, guild_id)
+        .await?;
+    let guild_name = guild_id
+        .name(&cache_and_http.cache)
+        .unwrap_or_else(|| "this server".to_owned());
+    let paginator = SuggestionPaginator::new(metadata.clone(), guild_name, suggestions, 0).await?;
+    let embed = paginator.embed();
+
+    let http = &cache_and_http.http;
+    if let Some(message_id) = config.suggestion_message {
+        match channel_id
+            .edit_message(http, message_id, |m| m.set_embed(embed.clone()))
+            .await
+        {
+            Ok(_) => return Ok(()),
+            Err(why) if discord_error_code(&why) == Some(UNKNOWN_MESSAGE) => {}
+            Err(why) => return Err(why.into()),
+        }
+    }
+    let message = channel_id
+        .send_message(http, |m| m.set_embed(embed))
+        .await?;
+    if let Err(why) = message.pin(http).await {
+        warn!(
+            "Could not pin the suggestion board of {}, is Manage Messages missing? {:?}",
+            guild_id, why
+        );
+    }
+    db.repository::<GuildConfig>()
+        .set_suggestion_message(guild_id, message.id)
+        .await
+}
@@ -0,0 +1,561 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Result;
+use mongodm::prelude::{BsonDateTime, MongoCollection};
+use serenity::{async_trait, model::id::UserId};
+use tracing::{error, info, warn};
+
+use crate::{
+    anilist::{AniListClient, AniListError},
+    db::{
+        anilist_link::{AniListLink, AniListLinkCollectionExt},
+        watchlist::{WatchInfo, WatchListFilter, WatchListSort, WatchListStore, WatchStatus},
+    },
+    graphql::{
+        delete_media_list_entry, find_media_list_entry, media_list_collection,
+        save_media_list_entry, DeleteMediaListEntry, FindMediaListEntry, MediaListCollection,
+        SaveMediaListEntry,
+    },
+    media::{Media, MediaType},
+};
+
+const PULL_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// An entry of a linked AniList list, as `plan_sync` compares it
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteEntry {
+    pub media_id: i64,
+    pub media_type: MediaType,
+    pub status: WatchStatus,
+    pub progress: i64,
+    pub progress_volumes: i64,
+    /// Out of 10 like ratings, `None` when unscored
+    pub score: Option<u8>,
+    pub repeat: i64,
+    /// Unix seconds
+    pub updated_at: i64,
+}
+
+/// What one pull changes on each side
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SyncPlan {
+    /// Entries AniList changed last, to save as they are
+    pub pull: Vec<WatchInfo>,
+    /// Entries changed here last, to send to AniList
+    pub push: Vec<WatchInfo>,
+}
+
+impl RemoteEntry {
+    /// Whether `info` already says what this entry says. AniList has no suggestions and
+    /// clearing a score there keeps the rating here.
+    fn matches(&self, info: &WatchInfo) -> bool {
+        info.watch_status == self.status
+            && info.progress == self.progress
+            && (self.media_type != MediaType::Manga
+                || info.progress_volumes == self.progress_volumes)
+            && (self.score.is_none() || info.rating == self.score)
+            && info.repeat == self.repeat
+    }
+
+    fn apply_to(&self, info: &mut WatchInfo) {
+        info.change_status(self.status);
+        info.progress = self.progress;
+        if self.media_type == MediaType::Manga {
+            info.progress_volumes = self.progress_volumes;
+        }
+        if self.score.is_some() {
+            info.rating = self.score;
+        }
+        info.repeat = self.repeat;
+        info.updated_at = BsonDateTime::from_millis(self.updated_at * 1000);
+    }
+}
+
+/// Lines up the `local` watch list of `user_id` with its `remote` AniList list, whichever side
+/// was updated last wins. AniList only keeps seconds, so ties go to the local entry. Entries only
+/// kept here are pushed if they changed since `pulled_at`, they were deleted on AniList otherwise.
+/// An entry listed twice on AniList is only lined up once.
+pub fn plan_sync(
+    local: Vec<WatchInfo>,
+    remote: Vec<RemoteEntry>,
+    user_id: UserId,
+    pulled_at: Option<BsonDateTime>,
+) -> SyncPlan {
+    let mut local = local
+        .into_iter()
+        .map(|info| (info.anilist_media_id, info))
+        .collect::<HashMap<_, _>>();
+    let mut plan = SyncPlan::default();
+
+    let mut seen = HashSet::new();
+    for entry in remote
+        .into_iter()
+        .filter(|entry| seen.insert(entry.media_id))
+    {
+        match local.remove(&entry.media_id) {
+            None => {
+                let updated_at = BsonDateTime::from_millis(entry.updated_at * 1000);
+                let mut info =
+                    WatchInfo::new(entry.media_id, user_id, entry.media_type, updated_at);
+                entry.apply_to(&mut info);
+                plan.pull.push(info);
+            }
+            Some(info) if entry.matches(&info) => {}
+            Some(mut info) => {
+                if entry.updated_at > info.updated_at.timestamp_millis() / 1000 {
+                    entry.apply_to(&mut info);
+                    plan.pull.push(info);
+                } else {
+                    plan.push.push(info);
+                }
+            }
+        }
+    }
+
+    let mut only_local = local
+        .into_values()
+        .filter(|info| info.watch_status != WatchStatus::NotSeen)
+        .collect::<Vec<_>>();
+    only_local.sort_by_key(|info| info.anilist_media_id);
+    for mut info in only_local {
+        match pulled_at {
+            Some(pulled_at) if info.updated_at <= pulled_at => {
+                info.change_status(WatchStatus::NotSeen);
+                info.updated_at = pulled_at;
+                plan.pull.push(info);
+            }
+            _ => plan.push.push(info),
+        }
+    }
+    plan
+}
+
+fn remote_status(status: WatchStatus) -> Option<save_media_list_entry::MediaListStatus> {
+    use save_media_list_entry::MediaListStatus;
+
+    match status {
+        WatchStatus::NotSeen => None,
+        WatchStatus::Planning => Some(MediaListStatus::Planning),
+        WatchStatus::Consuming => Some(MediaListStatus::Current),
+        WatchStatus::Finished => Some(MediaListStatus::Completed),
+        WatchStatus::Paused => Some(MediaListStatus::Paused),
+        WatchStatus::Dropped => Some(MediaListStatus::Dropped),
+        WatchStatus::Repeating => Some(MediaListStatus::Repeating),
+    }
+}
+
+fn local_status(status: media_list_collection::MediaListStatus) -> Option<WatchStatus> {
+    use media_list_collection::MediaListStatus;
+
+    match status {
+        MediaListStatus::Planning => Some(WatchStatus::Planning),
+        MediaListStatus::Current => Some(WatchStatus::Consuming),
+        MediaListStatus::Completed => Some(WatchStatus::Finished),
+        MediaListStatus::Paused => Some(WatchStatus::Paused),
+        MediaListStatus::Dropped => Some(WatchStatus::Dropped),
+        MediaListStatus::Repeating => Some(WatchStatus::Repeating),
+        MediaListStatus::Other(_) => None,
+    }
+}
+
+/// Keeps watch lists in sync with the AniList accounts their users linked
+pub struct AniListSync {
+    /// The store itself, saving pulled entries through `SyncedWatchListStore` would push them back
+    watch_list: Arc<dyn WatchListStore>,
+    links: MongoCollection<AniListLink>,
+    anilist: Arc<AniListClient>,
+}
+
+impl AniListSync {
+    pub fn new(
+        watch_list: Arc<dyn WatchListStore>,
+        links: MongoCollection<AniListLink>,
+        anilist: Arc<AniListClient>,
+    ) -> Self {
+        AniListSync {
+            watch_list,
+            links,
+            anilist,
+        }
+    }
+
+    /// Sends `info` to the AniList account of its user, if they linked one
+    pub async fn push(&self, info: &WatchInfo) -> Result<()> {
+        match self.links.find_link(info.discord_user_id).await? {
+            Some(link) => self.push_to(&link, info).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn push_to(&self, link: &AniListLink, info: &WatchInfo) -> Result<()> {
+        let result = match remote_status(info.watch_status) {
+            Some(status) => self.save_remote(link, info, status).await,
+            None => self.delete_remote(link, info.anilist_media_id).await,
+        };
+        if let Err(AniListError::Unauthorized) = result {
+            self.unlink(link).await?;
+        }
+        Ok(result?)
+    }
+
+    async fn save_remote(
+        &self,
+        link: &AniListLink,
+        info: &WatchInfo,
+        status: save_media_list_entry::MediaListStatus,
+    ) -> Result<(), AniListError> {
+        let response = self
+            .anilist
+            .query_as::<SaveMediaListEntry>(
+                save_media_list_entry::Variables {
+                    media_id: Some(info.anilist_media_id),
+                    status: Some(status),
+                    progress: Some(info.progress),
+                    progress_volumes: if info.media_type == MediaType::Manga {
+                        Some(info.progress_volumes)
+                    } else {
+                        None
+                    },
+                    score_raw: info.rating.map(|rating| i64::from(rating) * 10),
+                    repeat: Some(info.repeat),
+                },
+                &link.token,
+            )
+            .await?;
+        match response.errors {
+            Some(errors) => Err(AniListError::from_graphql(&errors)),
+            None => Ok(()),
+        }
+    }
+
+    /// Removes the media from the AniList list, it is fine if it is not there
+    async fn delete_remote(&self, link: &AniListLink, media_id: i64) -> Result<(), AniListError> {
+        let response = self
+            .anilist
+            .query_as::<FindMediaListEntry>(
+                find_media_list_entry::Variables {
+                    user_id: Some(link.anilist_user_id),
+                    media_id: Some(media_id),
+                },
+                &link.token,
+            )
+            .await?;
+        let entry_id = match (
+            response.errors,
+            response.data.and_then(|data| data.media_list),
+        ) {
+            (_, Some(entry)) => entry.id,
+            (Some(errors), None) => match AniListError::from_graphql(&errors) {
+                AniListError::NotFound => return Ok(()),
+                error => return Err(error),
+            },
+            (None, None) => return Ok(()),
+        };
+
+        let response = self
+            .anilist
+            .query_as::<DeleteMediaListEntry>(
+                delete_media_list_entry::Variables { id: Some(entry_id) },
+                &link.token,
+            )
+            .await?;
+        match response.errors {
+            Some(errors) => Err(AniListError::from_graphql(&errors)),
+            None => Ok(()),
+        }
+    }
+
+    /// The whole AniList list of `link`, anime and manga
+    async fn remote_entries(&self, link: &AniListLink) -> Result<Vec<RemoteEntry>, AniListError> {
+        let mut entries = vec![];
+        for media_type in [MediaType::Anime, MediaType::Manga] {
+            let mut chunk = 1;
+            loop {
+                let response = self
+                    .anilist
+                    .query_as::<MediaListCollection>(
+                        media_list_collection::Variables {
+                            user_id: Some(link.anilist_user_id),
+                            media_type: Some(media_type.into()),
+                            chunk: Some(chunk),
+                        },
+                        &link.token,
+                    )
+                    .await?;
+                if let Some(errors) = response.errors {
+                    return Err(AniListError::from_graphql(&errors));
+                }
+
+                let collection = match response.data.and_then(|data| data.media_list_collection) {
+                    Some(collection) => collection,
+                    None => break,
+                };
+                for entry in collection
+                    .lists
+                    .unwrap_or_default()
+                    .into_iter()
+                    .flatten()
+                    // entries in custom lists are in their status list too
+                    .filter(|list| list.is_custom_list != Some(true))
+                    .flat_map(|list| list.entries.unwrap_or_default())
+                    .flatten()
+                {
+                    let status = match entry.status.and_then(local_status) {
+                        Some(status) => status,
+                        None => continue,
+                    };
+                    entries.push(RemoteEntry {
+                        media_id: entry.media_id,
+                        media_type,
+                        status,
+                        progress: entry.progress.unwrap_or_default(),
+                        progress_volumes: entry.progress_volumes.unwrap_or_default(),
+                        score: entry
+                            .score
+                            .map(|score| score.round() as u8)
+                            .filter(|score| *score > 0),
+                        repeat: entry.repeat.unwrap_or_default(),
+                        updated_at: entry.updated_at.unwrap_or_default(),
+                    });
+                }
+                if collection.has_next_chunk != Some(true) {
+                    break;
+                }
+                chunk += 1;
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Brings the watch list of `link` and its AniList list in line, returns how many entries
+    /// changed here and on AniList
+    pub async fn pull(&self, link: &AniListLink) -> Result<(usize, usize)> {
+        let user_id = link.discord_user_id;
+        let pulled_at = BsonDateTime::now();
+        let remote = match self.remote_entries(link).await {
+            Err(AniListError::Unauthorized) => {
+                self.unlink(link).await?;
+                return Ok((0, 0));
+            }
+            remote => remote?,
+        };
+        let local = self.watch_list.find_all(user_id).await?;
+        let plan = plan_sync(local, remote, user_id, link.pulled_at);
+
+        let pulled = plan.pull.len();
+        self.watch_list.save_entries(plan.pull).await?;
+        let mut pushed = 0;
+        for info in &plan.push {
+            match self.push_to(link, info).await {
+                Ok(()) => pushed += 1,
+                Err(why) => warn!(
+                    "Could not push {} of {} to AniList: {:?}",
+                    info.anilist_media_id, user_id, why
+                ),
+            }
+        }
+        // entries that did not make it are pushed again next time instead of looking deleted
+        if pushed == plan.push.len() {
+            self.links.set_pulled_at(user_id, pulled_at).await?;
+        }
+        Ok((pulled, pushed))
+    }
+
+    /// AniList refuses tokens users revoked or that expired, the link is useless after that
+    async fn unlink(&self, link: &AniListLink) -> Result<()> {
+        info!(
+            "AniList refused the token of {}, unlinking {}",
+            link.discord_user_id, link.anilist_name
+        );
+        self.links.delete_link(link.discord_user_id).await?;
+        Ok(())
+    }
+
+    /// Pulls every linked list, every `PULL_INTERVAL`
+    pub async fn run(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(PULL_INTERVAL);
+        loop {
+            interval.tick().await;
+            let links = match self.links.find_links().await {
+                Ok(links) => links,
+                Err(why) => {
+                    error!("Could not find AniList links: {:?}", why);
+                    continue;
+                }
+            };
+            for link in links {
+                if let Err(why) = self.pull(&link).await {
+                    warn!(
+                        "Could not sync the AniList list of {}: {:?}",
+                        link.discord_user_id, why
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// A watch list store that mirrors every change to linked AniList accounts, in the background
+/// so commands don't wait on AniList
+pub struct SyncedWatchListStore {
+    inner: Arc<dyn WatchListStore>,
+    sync: Arc<AniListSync>,
+}
+
+impl SyncedWatchListStore {
+    pub fn new(inner: Arc<dyn WatchListStore>, sync: Arc<AniListSync>) -> Self {
+        SyncedWatchListStore { inner, sync }
+    }
+
+    fn push(&self, info: &Result<Option<WatchInfo>>) {
+        if let Ok(Some(info)) = info {
+            let sync = self.sync.clone();
+            let info = info.clone();
+            tokio::spawn(async move {
+                if let Err(why) = sync.push(&info).await {
+                    warn!(
+                        "Could not push {} of {} to AniList: {:?}",
+                        info.anilist_media_id, info.discord_user_id, why
+                    );
+                }
+            });
+        }
+    }
+}
+
+#[async_trait]
+impl WatchListStore for SyncedWatchListStore {
+    async fn toggle_suggestion(&self, media: &Media, user_id: UserId) -> Result<Option<WatchInfo>> {
+        // AniList has no suggestions
+        self.inner.toggle_suggestion(media, user_id).await
+    }
+
+    async fn toggle_finish(&self, media: &Media, user_id: UserId) -> Result<Option<WatchInfo>> {
+        let info = self.inner.toggle_finish(media, user_id).await;
+        self.push(&info);
+        info
+    }
+
+    async fn toggle_consuming(&self, media: &Media, user_id: UserId) -> Result<Option<WatchInfo>> {
+        let info = self.inner.toggle_consuming(media, user_id).await;
+        self.push(&info);
+        info
+    }
+
+    async fn set_status(
+        &self,
+        media: &Media,
+        user_id: UserId,
+        status: WatchStatus,
+    ) -> Result<Option<WatchInfo>> {
+        let info = self.inner.set_status(media, user_id, status).await;
+        self.push(&info);
+        info
+    }
+
+    async fn set_rating(
+        &self,
+        media: &Media,
+        user_id: UserId,
+        rating: u8,
+    ) -> Result<Option<WatchInfo>> {
+        let info = self.inner.set_rating(media, user_id, rating).await;
+        self.push(&info);
+        info
+    }
+
+    async fn set_progress(
+        &self,
+        media: &Media,
+        user_id: UserId,
+        progress: i64,
+        volumes: Option<i64>,
+    ) -> Result<Option<WatchInfo>> {
+        let info = self
+            .inner
+            .set_progress(media, user_id, progress, volumes)
+            .await;
+        self.push(&info);
+        info
+    }
+
+    async fn add_progress(
+        &self,
+        media: &Media,
+        user_id: UserId,
+        amount: i64,
+    ) -> Result<Option<WatchInfo>> {
+        let info = self.inner.add_progress(media, user_id, amount).await;
+        self.push(&info);
+        info
+    }
+
+    async fn find_watch_info(&self, media_id: i64, user_id: UserId) -> Result<Option<WatchInfo>> {
+        self.inner.find_watch_info(media_id, user_id).await
+    }
+
+    async fn count_watch_list(&self, user_id: UserId, filter: WatchListFilter) -> Result<u64> {
+        self.inner.count_watch_list(user_id, filter).await
+    }
+
+    async fn find_watch_list(
+        &self,
+        user_id: UserId,
+        filter: WatchListFilter,
+        sort: WatchListSort,
+        skip: u64,
+        limit: i64,
+    ) -> Result<Vec<WatchInfo>> {
+        self.inner
+            .find_watch_list(user_id, filter, sort, skip, limit)
+            .await
+    }
+
+    async fn find_consuming(
+        &self,
+        media_type: MediaType,
+        user_ids: &[UserId],
+    ) -> Result<Vec<WatchInfo>> {
+        self.inner.find_consuming(media_type, user_ids).await
+    }
+
+    async fn find_suggested(&self) -> Result<Vec<WatchInfo>> {
+        self.inner.find_suggested().await
+    }
+
+    async fn find_finished(&self, media_ids: &[i64]) -> Result<Vec<WatchInfo>> {
+        self.inner.find_finished(media_ids).await
+    }
+
+    async fn find_listed_media(&self, media_ids: &[i64]) -> Result<Vec<i64>> {
+        self.inner.find_listed_media(media_ids).await
+    }
+
+    async fn find_all(&self, user_id: UserId) -> Result<Vec<WatchInfo>> {
+        self.inner.find_all(user_id).await
+    }
+
+    async fn find_every_entry(&self) -> Result<Vec<WatchInfo>> {
+        self.inner.find_every_entry().await
+    }
+
+    async fn save_entries(&self, entries: Vec<WatchInfo>) -> Result<()> {
+        self.inner.save_entries(entries.clone()).await?;
+        let sync = self.sync.clone();
+        tokio::spawn(async move {
+            for info in entries {
+                if let Err(why) = sync.push(&info).await {
+                    warn!(
+                        "Could not push {} of {} to AniList: {:?}",
+                        info.anilist_media_id, info.discord_user_id, why
+                    );
+                }
+            }
+        });
+        Ok(())
+    }
+}
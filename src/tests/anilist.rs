@@ -0,0 +1,95 @@
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+
+use crate::anilist::{RateLimiter, ResponseCache};
+
+const SECOND: Duration = Duration::from_secs(1);
+
+#[test]
+fn a_full_bucket_sends_right_away() {
+    let mut limiter = RateLimiter::new();
+    let now = Instant::now();
+    for _ in 0..90 {
+        assert_eq!(limiter.reserve(now, None), Some(Duration::from_secs(0)));
+    }
+    // the 91st request waits for the next token, 90 come in a minute
+    let wait = limiter.reserve(now, None).unwrap();
+    assert!(wait > Duration::from_millis(600) && wait <= Duration::from_millis(700));
+}
+
+#[test]
+fn the_bucket_refills_over_time() {
+    let mut limiter = RateLimiter::new();
+    let now = Instant::now();
+    limiter.observe_remaining(0.0);
+    assert!(limiter.reserve(now, None).unwrap() > Duration::from_secs(0));
+
+    // two seconds refill three tokens, one of them is owed already
+    let later = now + 2 * SECOND;
+    assert_eq!(limiter.reserve(later, None), Some(Duration::from_secs(0)));
+    assert_eq!(limiter.reserve(later, None), Some(Duration::from_secs(0)));
+    assert!(limiter.reserve(later, None).unwrap() > Duration::from_secs(0));
+}
+
+#[test]
+fn waits_longer_than_the_maximum_fail_fast() {
+    let mut limiter = RateLimiter::new();
+    let now = Instant::now();
+    limiter.block_for(now, 60 * SECOND);
+
+    assert_eq!(limiter.reserve(now, Some(2 * SECOND)), None);
+    assert_eq!(limiter.reserve(now, None), Some(60 * SECOND));
+}
+
+#[test]
+fn refused_requests_take_no_token() {
+    let mut limiter = RateLimiter::new();
+    let now = Instant::now();
+    limiter.observe_remaining(1.0);
+    let no_wait = Some(Duration::from_secs(0));
+
+    assert_eq!(limiter.reserve(now, no_wait), Some(Duration::from_secs(0)));
+    assert_eq!(limiter.reserve(now, no_wait), None);
+    assert_eq!(limiter.reserve(now, no_wait), None);
+    // a token refills every 667ms, it would still be owed if the refused requests took one
+    assert_eq!(
+        limiter.reserve(now + Duration::from_millis(700), no_wait),
+        Some(Duration::from_secs(0))
+    );
+}
+
+#[test]
+fn cached_responses_expire() {
+    let mut cache = ResponseCache::new(10, 60 * SECOND);
+    let now = Instant::now();
+    cache.insert("frieren".to_owned(), json!({ "id": 154587 }), now);
+
+    assert_eq!(
+        cache.get("frieren", now + 59 * SECOND),
+        Some(json!({ "id": 154587 }))
+    );
+    assert_eq!(cache.get("frieren", now + 60 * SECOND), None);
+    assert_eq!(cache.get("bocchi", now), None);
+}
+
+#[test]
+fn full_caches_drop_expired_then_oldest_entries() {
+    let mut cache = ResponseCache::new(2, 60 * SECOND);
+    let now = Instant::now();
+    cache.insert("a".to_owned(), json!(1), now);
+    cache.insert("b".to_owned(), json!(2), now + 30 * SECOND);
+
+    // "a" expired, it makes room
+    let later = now + 61 * SECOND;
+    cache.insert("c".to_owned(), json!(3), later);
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.get("b", later), Some(json!(2)));
+
+    // everything is fresh, "b" expires first
+    cache.insert("d".to_owned(), json!(4), later);
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.get("b", later), None);
+    assert_eq!(cache.get("c", later), Some(json!(3)));
+    assert_eq!(cache.get("d", later), Some(json!(4)));
+}
@@ -0,0 +1,93 @@
+use mongodm::prelude::BsonDateTime;
+
+use crate::{
+    db::watchlist::{WatchInfo, WatchStatus},
+    export::{to_csv, to_json, JsonExport, EXPORT_VERSION},
+    mal::{parse_export, write_export},
+    media::{Media, MediaType},
+};
+
+use super::{entry, USER};
+
+fn media(id: i64, id_mal: Option<i64>, title: &str) -> Media {
+    Media {
+        id_mal,
+        episodes: Some(26),
+        ..Media::new(id, MediaType::Anime, title)
+    }
+}
+
+#[test]
+fn json_exports_keep_every_field() {
+    let mut info = entry(1, WatchStatus::Finished);
+    info.rating = Some(9);
+    info.suggests = true;
+    info.repeat = 2;
+
+    let export: JsonExport = serde_json::from_slice(&to_json(&[info.clone()]).unwrap()).unwrap();
+    assert_eq!(export.version, EXPORT_VERSION);
+    let exported = &export.entries[0];
+    assert_eq!(exported.discord_user_id, USER);
+    assert_eq!(exported.watch_status, WatchStatus::Finished);
+    assert_eq!(exported.last_watch_status, WatchStatus::NotSeen);
+    assert_eq!(exported.rating, Some(9));
+    assert!(exported.suggests);
+    assert_eq!(exported.repeat, 2);
+    assert_eq!(
+        BsonDateTime::parse_rfc3339_str(&exported.created_at).unwrap(),
+        info.created_at
+    );
+}
+
+#[test]
+fn csv_exports_have_titles() {
+    let entries = vec![(
+        entry(1, WatchStatus::Consuming),
+        Some(media(1, Some(1), "Cowboy Bebop, the movie")),
+    )];
+    let csv = String::from_utf8(to_csv(&entries).unwrap()).unwrap();
+    let mut lines = csv.lines();
+    assert!(lines
+        .next()
+        .unwrap()
+        .starts_with("anilist_id,mal_id,title,"));
+    assert!(lines
+        .next()
+        .unwrap()
+        .starts_with("1,1,\"Cowboy Bebop, the movie\",anime,Watching,0,26,"));
+}
+
+#[test]
+fn mal_exports_can_be_imported_again() {
+    let mut rewatched = entry(1, WatchStatus::Repeating);
+    rewatched.progress = 3;
+    rewatched.rating = Some(8);
+    let entries = vec![
+        (rewatched, Some(media(1, Some(11), "Odd ]]> title"))),
+        // not on MAL
+        (entry(2, WatchStatus::Planning), Some(media(2, None, "Web"))),
+        // only suggested
+        (
+            entry(3, WatchStatus::NotSeen),
+            Some(media(3, Some(13), "Other")),
+        ),
+        (
+            WatchInfo {
+                media_type: MediaType::Manga,
+                ..entry(4, WatchStatus::Planning)
+            },
+            Some(media(4, Some(14), "Manga")),
+        ),
+    ];
+
+    let (xml, written) = write_export(&MediaType::Anime, &entries);
+    assert_eq!(written, 1);
+    let export = parse_export(xml.as_bytes()).unwrap();
+    assert_eq!(export.entries.len(), 1);
+    let imported = &export.entries[0];
+    assert_eq!(imported.mal_id, 11);
+    assert_eq!(imported.title, "Odd ]]> title");
+    assert_eq!(imported.status, WatchStatus::Repeating);
+    assert_eq!(imported.progress, 3);
+    assert_eq!(imported.score, Some(8));
+}
@@ -0,0 +1,116 @@
+use mongodm::prelude::BsonDateTime;
+use serenity::model::id::UserId;
+
+use crate::{
+    db::watchlist::{WatchInfo, WatchStatus},
+    mal::{parse_export, plan_import, MalError, MalIds},
+    media::MediaType,
+};
+
+const USER: UserId = UserId(1);
+
+const EXPORT: &str = r#"<?xml version="1.0" encoding="UTF-8" ?>
+<!-- Created by XML Export feature at MyAnimeList.net -->
+<myanimelist>
+    <myinfo>
+        <user_export_type>1</user_export_type>
+    </myinfo>
+    <anime>
+        <series_animedb_id>1</series_animedb_id>
+        <series_title><![CDATA[Cowboy Bebop]]></series_title>
+        <my_watched_episodes>26</my_watched_episodes>
+        <my_score>9</my_score>
+        <my_status>Completed</my_status>
+        <my_times_watched>1</my_times_watched>
+        <my_rewatching>0</my_rewatching>
+    </anime>
+    <anime>
+        <series_animedb_id>5</series_animedb_id>
+        <series_title><![CDATA[Cowboy Bebop: Tengoku no Tobira]]></series_title>
+        <my_watched_episodes>0</my_watched_episodes>
+        <my_score>0</my_score>
+        <my_status>Plan to Watch</my_status>
+    </anime>
+    <anime>
+        <series_animedb_id>6</series_animedb_id>
+        <series_title><![CDATA[Trigun]]></series_title>
+        <my_status>Abandoned</my_status>
+    </anime>
+    <manga>
+        <manga_mangadb_id>2</manga_mangadb_id>
+        <manga_title><![CDATA[Berserk]]></manga_title>
+        <my_read_volumes>12</my_read_volumes>
+        <my_read_chapters>100</my_read_chapters>
+        <my_score>10</my_score>
+        <my_status>Reading</my_status>
+        <my_rereading>1</my_rereading>
+    </manga>
+</myanimelist>
+"#;
+
+#[test]
+fn exports_are_parsed() {
+    let export = parse_export(EXPORT.as_bytes()).unwrap();
+    assert_eq!(export.entries.len(), 3);
+    assert_eq!(export.invalid, 1);
+
+    let bebop = &export.entries[0];
+    assert_eq!(bebop.mal_id, 1);
+    assert_eq!(bebop.media_type, MediaType::Anime);
+    assert_eq!(bebop.title, "Cowboy Bebop");
+    assert_eq!(bebop.status, WatchStatus::Finished);
+    assert_eq!(bebop.score, Some(9));
+    assert_eq!(bebop.progress, 26);
+    assert_eq!(bebop.repeat, 1);
+
+    assert_eq!(export.entries[1].status, WatchStatus::Planning);
+    assert_eq!(export.entries[1].score, None);
+
+    let berserk = &export.entries[2];
+    assert_eq!(berserk.media_type, MediaType::Manga);
+    assert_eq!(berserk.status, WatchStatus::Repeating);
+    assert_eq!(berserk.progress, 100);
+    assert_eq!(berserk.progress_volumes, 12);
+}
+
+#[test]
+fn other_files_are_refused() {
+    assert_eq!(
+        parse_export(b"<rss></rss>").unwrap_err(),
+        MalError::NotAnExport
+    );
+    assert!(matches!(
+        parse_export(b"<myanimelist><anime>").unwrap_err(),
+        MalError::InvalidXml(_)
+    ));
+}
+
+#[test]
+fn imports_are_diffed_against_the_watch_list() {
+    let export = parse_export(EXPORT.as_bytes()).unwrap();
+    let mut ids = MalIds::default();
+    ids.insert(&MediaType::Anime, 1, 101);
+    ids.insert(&MediaType::Anime, 5, 105);
+    let now = BsonDateTime::now();
+
+    // Bebop is already suggested, the movie is already planned
+    let mut bebop = WatchInfo::new(101, USER, MediaType::Anime, now);
+    bebop.suggests = true;
+    bebop.rating = Some(7);
+    let mut movie = WatchInfo::new(105, USER, MediaType::Anime, now);
+    movie.change_status(WatchStatus::Planning);
+
+    let plan = plan_import(export, &ids, vec![bebop, movie], USER, now);
+    assert_eq!(plan.imported(), 0);
+    assert_eq!(plan.updated(), 1);
+    // the planned movie is the same, the invalid entry is skipped too
+    assert_eq!(plan.skipped, 2);
+    assert_eq!(plan.unresolved.len(), 1);
+    assert_eq!(plan.unresolved[0].title, "Berserk");
+
+    let bebop = &plan.changes[0].after;
+    assert_eq!(bebop.watch_status, WatchStatus::Finished);
+    assert_eq!(bebop.rating, Some(9));
+    assert_eq!(bebop.progress, 26);
+    assert!(bebop.suggests);
+}
@@ -0,0 +1,88 @@
+use mongodm::prelude::BsonDateTime;
+
+use crate::{
+    db::media::CachedMedia,
+    media::{Media, MediaStatus, MediaType, NextAiring},
+};
+
+const HOUR: i64 = 60 * 60;
+const DAY: i64 = 24 * HOUR;
+/// Unix seconds
+const NOW: i64 = 1_700_000_000;
+
+fn media(status: Option<MediaStatus>, next_airing_in: Option<i64>) -> Media {
+    Media {
+        status,
+        next_airing: next_airing_in.map(|secs| NextAiring {
+            episode: 5,
+            airing_at: NOW + secs,
+        }),
+        ..Media::new(1, MediaType::Anime, "Frieren")
+    }
+}
+
+/// Seconds from `NOW` until the media is refreshed
+fn refreshed_in(media: &Media) -> i64 {
+    let fetched_at = BsonDateTime::from_millis(NOW * 1000);
+    CachedMedia::refresh_after(media, fetched_at).timestamp_millis() / 1000 - NOW
+}
+
+#[test]
+fn airing_anime_are_refreshed_after_their_next_episode() {
+    let airing = media(Some(MediaStatus::Releasing), Some(3 * HOUR));
+    assert_eq!(refreshed_in(&airing), 4 * HOUR);
+}
+
+#[test]
+fn airing_refreshes_are_at_least_an_hour_and_at_most_a_day_away() {
+    let overdue = media(Some(MediaStatus::Releasing), Some(-2 * HOUR));
+    assert_eq!(refreshed_in(&overdue), HOUR);
+
+    let next_week = media(Some(MediaStatus::Releasing), Some(7 * DAY));
+    assert_eq!(refreshed_in(&next_week), DAY);
+
+    let unscheduled = media(Some(MediaStatus::Releasing), None);
+    assert_eq!(refreshed_in(&unscheduled), DAY);
+}
+
+#[test]
+fn settled_media_are_refreshed_rarely() {
+    assert_eq!(
+        refreshed_in(&media(Some(MediaStatus::NotYetReleased), None)),
+        DAY
+    );
+    assert_eq!(
+        refreshed_in(&media(Some(MediaStatus::Hiatus), None)),
+        7 * DAY
+    );
+    assert_eq!(refreshed_in(&media(None, None)), 7 * DAY);
+    assert_eq!(
+        refreshed_in(&media(Some(MediaStatus::Finished), None)),
+        30 * DAY
+    );
+    assert_eq!(
+        refreshed_in(&media(Some(MediaStatus::Cancelled), None)),
+        30 * DAY
+    );
+}
+
+#[test]
+fn cached_media_are_keyed_by_their_anilist_id() {
+    let fetched_at = BsonDateTime::from_millis(NOW * 1000);
+    let cached = CachedMedia::new(media(Some(MediaStatus::Finished), None), fetched_at);
+    assert_eq!(cached.anilist_media_id, 1);
+    assert_eq!(cached.fetched_at, fetched_at);
+    assert_eq!(cached.read_at, fetched_at);
+}
+
+#[test]
+fn listed_or_recently_read_media_are_worth_refreshing() {
+    let read_at = BsonDateTime::from_millis(NOW * 1000);
+    let cached = CachedMedia::new(media(Some(MediaStatus::Finished), None), read_at);
+    let soon = BsonDateTime::from_millis((NOW + 13 * DAY) * 1000);
+    let later = BsonDateTime::from_millis((NOW + 15 * DAY) * 1000);
+
+    assert!(cached.worth_refreshing(false, soon));
+    assert!(!cached.worth_refreshing(false, later));
+    assert!(cached.worth_refreshing(true, later));
+}
@@ -0,0 +1,184 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use reqwest::StatusCode;
+use serenity::async_trait;
+
+use crate::{
+    anilist::AniListError,
+    media::{AiredEpisode, Media, MediaPage, MediaType},
+    metadata::{FallbackProvider, MediaLookup, MediaSearch, MetadataError, MetadataProvider},
+};
+
+/// Answers every call the same way and counts how often it was asked
+struct FakeProvider {
+    name: &'static str,
+    answer: fn(&'static str) -> Result<Media, MetadataError>,
+    calls: AtomicUsize,
+}
+
+impl FakeProvider {
+    fn new(
+        name: &'static str,
+        answer: fn(&'static str) -> Result<Media, MetadataError>,
+    ) -> Arc<Self> {
+        Arc::new(FakeProvider {
+            name,
+            answer,
+            calls: AtomicUsize::new(0),
+        })
+    }
+
+    fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+
+    fn answer(&self) -> Result<Media, MetadataError> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        (self.answer)(self.name)
+    }
+}
+
+#[async_trait]
+impl MetadataProvider for FakeProvider {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    async fn search(&self, _search: &MediaSearch) -> Result<MediaPage, MetadataError> {
+        self.answer().map(|media| MediaPage {
+            media: vec![media],
+            ..MediaPage::default()
+        })
+    }
+
+    async fn media(&self, _lookup: &MediaLookup) -> Result<Option<Media>, MetadataError> {
+        self.answer().map(Some)
+    }
+
+    async fn media_by_ids(&self, _ids: &[i64]) -> Result<Vec<Media>, MetadataError> {
+        self.answer().map(|media| vec![media])
+    }
+
+    async fn aired_episodes(
+        &self,
+        _media_ids: &[i64],
+        _aired_after: i64,
+        _aired_before: i64,
+    ) -> Result<Vec<AiredEpisode>, MetadataError> {
+        self.answer().map(|_| vec![])
+    }
+}
+
+fn found(name: &'static str) -> Result<Media, MetadataError> {
+    Ok(Media::new(1, MediaType::Anime, name))
+}
+
+fn down(name: &'static str) -> Result<Media, MetadataError> {
+    Err(MetadataError::Unavailable(name, StatusCode::BAD_GATEWAY))
+}
+
+fn rate_limited(name: &'static str) -> Result<Media, MetadataError> {
+    Err(MetadataError::RateLimited(name))
+}
+
+fn unsupported(name: &'static str) -> Result<Media, MetadataError> {
+    Err(MetadataError::Unsupported(name, "search"))
+}
+
+fn fallback(providers: &[&Arc<FakeProvider>]) -> FallbackProvider {
+    FallbackProvider::new(
+        providers
+            .iter()
+            .map(|&provider| provider.clone() as Arc<dyn MetadataProvider>)
+            .collect(),
+    )
+}
+
+async fn title(provider: &FallbackProvider) -> Result<String, MetadataError> {
+    provider
+        .media(&MediaLookup::by_id(1, MediaType::Anime))
+        .await
+        .map(|media| media.unwrap().title)
+}
+
+#[tokio::test]
+async fn the_primary_answers_first() {
+    let primary = FakeProvider::new("primary", found);
+    let secondary = FakeProvider::new("secondary", found);
+    let provider = fallback(&[&primary, &secondary]);
+
+    assert_eq!(title(&provider).await.unwrap(), "primary");
+    assert_eq!(provider.name(), "primary");
+    assert_eq!(secondary.calls(), 0);
+}
+
+#[tokio::test]
+async fn outages_fall_back_on_the_next_provider() {
+    let primary = FakeProvider::new("primary", down);
+    let secondary = FakeProvider::new("secondary", rate_limited);
+    let tertiary = FakeProvider::new("tertiary", found);
+    let provider = fallback(&[&primary, &secondary, &tertiary]);
+
+    assert_eq!(title(&provider).await.unwrap(), "tertiary");
+    assert_eq!(primary.calls(), 1);
+    assert_eq!(secondary.calls(), 1);
+}
+
+#[tokio::test]
+async fn unsupported_requests_fall_back_on_the_next_provider() {
+    let primary = FakeProvider::new("primary", unsupported);
+    let secondary = FakeProvider::new("secondary", found);
+    let provider = fallback(&[&primary, &secondary]);
+
+    let page = provider.search(&MediaSearch::default()).await.unwrap();
+    assert_eq!(page.media[0].title, "secondary");
+}
+
+#[tokio::test]
+async fn refusals_are_not_retried_elsewhere() {
+    let primary = FakeProvider::new("primary", |_| Err(AniListError::NotFound.into()));
+    let secondary = FakeProvider::new("secondary", found);
+    let provider = fallback(&[&primary, &secondary]);
+
+    assert!(title(&provider).await.is_err());
+    assert_eq!(secondary.calls(), 0);
+}
+
+#[tokio::test]
+async fn the_primary_error_is_kept_when_every_provider_fails() {
+    let primary = FakeProvider::new("primary", down);
+    let secondary = FakeProvider::new("secondary", unsupported);
+    let provider = fallback(&[&primary, &secondary]);
+
+    match title(&provider).await {
+        Err(MetadataError::Unavailable(name, _)) => assert_eq!(name, "primary"),
+        other => panic!("Expected the primary outage, got {:?}", other),
+    }
+}
+
+#[test]
+fn lookups_parse_ids_and_urls() {
+    assert_eq!(
+        MediaLookup::parse(" 21 ", MediaType::Anime),
+        Some(MediaLookup::by_id(21, MediaType::Anime))
+    );
+    assert_eq!(
+        MediaLookup::parse("https://anilist.co/manga/30013/One-Piece", MediaType::Anime),
+        Some(MediaLookup::by_id(30013, MediaType::Manga))
+    );
+    let mal = MediaLookup::parse("mal:5114", MediaType::Anime).unwrap();
+    assert_eq!((mal.id, mal.id_mal), (None, Some(5114)));
+}
+
+#[test]
+fn searches_are_not_lookups() {
+    assert_eq!(MediaLookup::parse("one piece", MediaType::Anime), None);
+    assert_eq!(MediaLookup::parse("mal:one", MediaType::Anime), None);
+    assert_eq!(
+        MediaLookup::parse("https://anilist.co/user/21", MediaType::Anime),
+        None
+    );
+}
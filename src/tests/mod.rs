@@ -1 +1,37 @@
+#[cfg(test)]
+mod anilist;
+#[cfg(test)]
+mod export;
+#[cfg(test)]
+mod mal;
+#[cfg(test)]
+mod media_cache;
+#[cfg(test)]
+mod metadata;
+#[cfg(test)]
+mod query;
+#[cfg(test)]
+mod suggestions;
+#[cfg(test)]
+mod sync;
+#[cfg(test)]
+mod watch_list_store;
 
+#[cfg(test)]
+const USER: serenity::model::id::UserId = serenity::model::id::UserId(80351110224678912);
+
+/// An anime entry of `USER` in `status`, updated at a fixed time
+#[cfg(test)]
+fn entry(
+    media_id: i64,
+    status: crate::db::watchlist::WatchStatus,
+) -> crate::db::watchlist::WatchInfo {
+    let mut info = crate::db::watchlist::WatchInfo::new(
+        media_id,
+        USER,
+        crate::media::MediaType::Anime,
+        mongodm::prelude::BsonDateTime::from_millis(1_600_000_000_123),
+    );
+    info.change_status(status);
+    info
+}
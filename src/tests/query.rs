@@ -0,0 +1,93 @@
+use crate::{
+    media::{MediaFormat, MediaSort, MediaType},
+    metadata::{MediaLookup, MediaSearch},
+    query::{MediaQuery, QueryError},
+};
+
+fn search(input: &str) -> MediaSearch {
+    match MediaQuery::parse(input, MediaType::Anime) {
+        Ok(MediaQuery::Search(search)) => search,
+        other => panic!("Expected a search for {:?}, got {:?}", input, other),
+    }
+}
+
+#[test]
+fn titles_with_colons_are_searched() {
+    assert_eq!(search("Re:Zero").search.as_deref(), Some("Re:Zero"));
+    assert_eq!(
+        search("Kaguya-sama: Love is War").search.as_deref(),
+        Some("Kaguya-sama: Love is War")
+    );
+    assert_eq!(
+        search("Steins;Gate 0 year:2018").search.as_deref(),
+        Some("Steins;Gate 0")
+    );
+}
+
+#[test]
+fn lowercase_titles_with_colons_are_searched() {
+    assert_eq!(search("re:zero").search.as_deref(), Some("re:zero"));
+    assert_eq!(
+        search("steins;gate:0").search.as_deref(),
+        Some("steins;gate:0")
+    );
+    assert_eq!(
+        search("season:2 genre:action").search.as_deref(),
+        Some("season:2")
+    );
+}
+
+#[test]
+fn quoted_text_is_never_a_filter() {
+    let search = search("\"re:zero\" year:2016");
+    assert_eq!(search.search.as_deref(), Some("re:zero"));
+    assert_eq!(search.year, Some(2016));
+}
+
+#[test]
+fn filters_alone_search_the_most_popular() {
+    let search = search("year:2023 format:tv,movie genre:slice-of-life Status:releasing");
+    assert_eq!(search.search, None);
+    assert_eq!(search.year, Some(2023));
+    assert_eq!(search.formats, vec![MediaFormat::Tv, MediaFormat::Movie]);
+    assert_eq!(search.genres, vec!["Slice of Life".to_owned()]);
+    assert_eq!(search.sort, Some(MediaSort::Popularity));
+}
+
+#[test]
+fn ids_are_looked_up() {
+    assert_eq!(
+        MediaQuery::parse("21", MediaType::Anime),
+        Ok(MediaQuery::Find(MediaLookup::by_id(21, MediaType::Anime)))
+    );
+}
+
+#[test]
+fn bad_filters_are_refused() {
+    assert!(matches!(
+        MediaQuery::parse("frieren year:twenty", MediaType::Anime),
+        Err(QueryError::InvalidValue { key, .. }) if key == "year"
+    ));
+    assert!(matches!(
+        MediaQuery::parse("frieren sort:best", MediaType::Anime),
+        Err(QueryError::InvalidValue { key, .. }) if key == "sort"
+    ));
+    assert_eq!(
+        MediaQuery::parse("frieren yaer:2023", MediaType::Anime),
+        Err(QueryError::UnknownKey {
+            key: "yaer".to_owned(),
+            known: "year"
+        })
+    );
+    assert_eq!(
+        MediaQuery::parse("frieren genres:fantasy", MediaType::Anime),
+        Err(QueryError::UnknownKey {
+            key: "genres".to_owned(),
+            known: "genre"
+        })
+    );
+    assert_eq!(
+        MediaQuery::parse("  ", MediaType::Anime),
+        Err(QueryError::Empty)
+    );
+}
@@ -0,0 +1,64 @@
+use std::collections::HashSet;
+
+use serenity::model::id::UserId;
+
+use crate::{
+    db::watchlist::{WatchInfo, WatchStatus},
+    suggestions::rank_suggestions,
+};
+
+use super::entry;
+
+fn suggested(media_id: i64, user: u64) -> WatchInfo {
+    WatchInfo {
+        discord_user_id: UserId(user),
+        suggests: true,
+        ..entry(media_id, WatchStatus::NotSeen)
+    }
+}
+
+fn finished(media_id: i64, user: u64) -> WatchInfo {
+    WatchInfo {
+        discord_user_id: UserId(user),
+        ..entry(media_id, WatchStatus::Finished)
+    }
+}
+
+#[test]
+fn suggestions_are_ranked_by_suggesters_then_finishers() {
+    let members = vec![UserId(1), UserId(2), UserId(3)]
+        .into_iter()
+        .collect::<HashSet<_>>();
+    let suggestions = rank_suggestions(
+        vec![
+            suggested(10, 1),
+            suggested(20, 1),
+            suggested(20, 2),
+            suggested(30, 3),
+        ],
+        vec![finished(30, 1), finished(30, 2), finished(10, 2)],
+        &members,
+    );
+
+    let ranked = suggestions
+        .iter()
+        .map(|suggestion| (suggestion.media_id, suggestion.finished))
+        .collect::<Vec<_>>();
+    assert_eq!(ranked, vec![(20, 0), (30, 2), (10, 1)]);
+    assert_eq!(suggestions[0].suggested_by, vec![UserId(1), UserId(2)]);
+}
+
+#[test]
+fn only_members_are_counted() {
+    let members = vec![UserId(1)].into_iter().collect::<HashSet<_>>();
+    let suggestions = rank_suggestions(
+        vec![suggested(10, 1), suggested(20, 2)],
+        vec![finished(10, 1), finished(10, 2)],
+        &members,
+    );
+
+    assert_eq!(suggestions.len(), 1);
+    assert_eq!(suggestions[0].media_id, 10);
+    assert_eq!(suggestions[0].suggested_by, vec![UserId(1)]);
+    assert_eq!(suggestions[0].finished, 1);
+}
@@ -0,0 +1,162 @@
+use mongodm::prelude::BsonDateTime;
+
+use crate::{
+    db::watchlist::{WatchInfo, WatchStatus},
+    media::MediaType,
+    sync::{plan_sync, RemoteEntry},
+};
+
+use super::{entry, USER};
+
+fn local(media_id: i64, status: WatchStatus, progress: i64, updated_at: i64) -> WatchInfo {
+    let mut info = entry(media_id, status);
+    info.progress = progress;
+    info.updated_at = BsonDateTime::from_millis(updated_at * 1000);
+    info.created_at = info.updated_at;
+    info
+}
+
+fn remote(media_id: i64, status: WatchStatus, progress: i64, updated_at: i64) -> RemoteEntry {
+    RemoteEntry {
+        media_id,
+        media_type: MediaType::Anime,
+        status,
+        progress,
+        progress_volumes: 0,
+        score: None,
+        repeat: 0,
+        updated_at,
+    }
+}
+
+#[test]
+fn the_side_updated_last_wins() {
+    let plan = plan_sync(
+        vec![
+            local(1, WatchStatus::Consuming, 3, 100),
+            local(2, WatchStatus::Consuming, 8, 300),
+        ],
+        vec![
+            remote(1, WatchStatus::Consuming, 5, 200),
+            remote(2, WatchStatus::Consuming, 6, 200),
+        ],
+        USER,
+        Some(BsonDateTime::from_millis(50_000)),
+    );
+
+    assert_eq!(plan.pull.len(), 1);
+    assert_eq!(plan.pull[0].anilist_media_id, 1);
+    assert_eq!(plan.pull[0].progress, 5);
+    assert_eq!(plan.pull[0].updated_at.timestamp_millis(), 200_000);
+    assert_eq!(plan.push.len(), 1);
+    assert_eq!(plan.push[0].anilist_media_id, 2);
+    assert_eq!(plan.push[0].progress, 8);
+}
+
+#[test]
+fn matching_entries_are_left_alone() {
+    let mut info = local(1, WatchStatus::Finished, 12, 100);
+    info.rating = Some(8);
+    let mut entry = remote(1, WatchStatus::Finished, 12, 500);
+    entry.score = Some(8);
+    // clearing a score on AniList keeps the rating
+    let unscored = remote(2, WatchStatus::Planning, 0, 500);
+    let mut rated = local(2, WatchStatus::Planning, 0, 100);
+    rated.rating = Some(6);
+
+    let plan = plan_sync(vec![info, rated], vec![entry, unscored], USER, None);
+
+    assert!(plan.pull.is_empty());
+    assert!(plan.push.is_empty());
+}
+
+#[test]
+fn entries_only_on_anilist_are_pulled() {
+    let mut entry = remote(7, WatchStatus::Paused, 4, 400);
+    entry.media_type = MediaType::Manga;
+    entry.progress_volumes = 1;
+    entry.score = Some(9);
+
+    let plan = plan_sync(vec![], vec![entry], USER, None);
+
+    assert_eq!(plan.pull.len(), 1);
+    let info = &plan.pull[0];
+    assert_eq!(info.discord_user_id, USER);
+    assert_eq!(info.media_type, MediaType::Manga);
+    assert_eq!(info.watch_status, WatchStatus::Paused);
+    assert_eq!(info.progress, 4);
+    assert_eq!(info.progress_volumes, 1);
+    assert_eq!(info.rating, Some(9));
+    assert_eq!(info.created_at.timestamp_millis(), 400_000);
+}
+
+#[test]
+fn entries_listed_twice_on_anilist_keep_their_local_fields() {
+    let mut info = local(3, WatchStatus::Consuming, 2, 100);
+    info.suggests = true;
+    info.rating = Some(8);
+
+    let plan = plan_sync(
+        vec![info],
+        vec![
+            remote(3, WatchStatus::Consuming, 5, 200),
+            remote(3, WatchStatus::Consuming, 5, 200),
+        ],
+        USER,
+        None,
+    );
+
+    assert_eq!(plan.pull.len(), 1);
+    assert!(plan.push.is_empty());
+    let info = &plan.pull[0];
+    assert_eq!(info.progress, 5);
+    assert!(info.suggests);
+    assert_eq!(info.rating, Some(8));
+    assert_eq!(info.created_at.timestamp_millis(), 100_000);
+}
+
+#[test]
+fn entries_only_kept_here_are_pushed_or_were_deleted_on_anilist() {
+    let pulled_at = BsonDateTime::from_millis(200_000);
+    let plan = plan_sync(
+        vec![
+            local(1, WatchStatus::Consuming, 2, 100),
+            local(2, WatchStatus::Planning, 0, 300),
+            local(3, WatchStatus::NotSeen, 0, 50),
+        ],
+        vec![],
+        USER,
+        Some(pulled_at),
+    );
+
+    assert_eq!(plan.pull.len(), 1);
+    assert_eq!(plan.pull[0].anilist_media_id, 1);
+    assert_eq!(plan.pull[0].watch_status, WatchStatus::NotSeen);
+    assert_eq!(plan.pull[0].last_watch_status, WatchStatus::Consuming);
+    assert_eq!(plan.push.len(), 1);
+    assert_eq!(plan.push[0].anilist_media_id, 2);
+
+    // nothing was pulled before, so nothing can have been deleted
+    let plan = plan_sync(
+        vec![local(1, WatchStatus::Consuming, 2, 100)],
+        vec![],
+        USER,
+        None,
+    );
+    assert!(plan.pull.is_empty());
+    assert_eq!(plan.push.len(), 1);
+}
+
+#[test]
+fn ties_go_to_the_local_entry() {
+    let plan = plan_sync(
+        vec![local(1, WatchStatus::Dropped, 3, 100)],
+        vec![remote(1, WatchStatus::Consuming, 3, 100)],
+        USER,
+        None,
+    );
+
+    assert!(plan.pull.is_empty());
+    assert_eq!(plan.push.len(), 1);
+    assert_eq!(plan.push[0].watch_status, WatchStatus::Dropped);
+}
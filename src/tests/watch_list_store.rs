@@ -0,0 +1,434 @@
+use serenity::model::id::UserId;
+
+use crate::{
+    db::watchlist::{WatchInfo, WatchListFilter, WatchListSort, WatchListStore, WatchStatus},
+    media::{Media, MediaType},
+};
+
+const USER: UserId = UserId(1);
+const OTHER_USER: UserId = UserId(2);
+
+fn anime(id: i64, episodes: Option<i64>) -> Media {
+    Media {
+        episodes,
+        ..Media::new(id, MediaType::Anime, "?")
+    }
+}
+
+fn manga(id: i64, chapters: Option<i64>) -> Media {
+    Media {
+        chapters,
+        ..Media::new(id, MediaType::Manga, "?")
+    }
+}
+
+async fn new_entries_start_empty(store: &dyn WatchListStore) {
+    let info = store
+        .set_rating(&anime(1, Some(12)), USER, 7)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(info.rating, Some(7));
+    assert_eq!(info.watch_status, WatchStatus::NotSeen);
+    assert_eq!(info.last_watch_status, WatchStatus::NotSeen);
+    assert_eq!(info.media_type, MediaType::Anime);
+    assert!(!info.suggests);
+    assert_eq!(info.created_at, info.updated_at);
+}
+
+async fn toggle_finish_restores_the_last_status(store: &dyn WatchListStore) {
+    let media = anime(1, Some(12));
+    store
+        .set_status(&media, USER, WatchStatus::Paused)
+        .await
+        .unwrap();
+
+    let info = store.toggle_finish(&media, USER).await.unwrap().unwrap();
+    assert_eq!(info.watch_status, WatchStatus::Finished);
+    assert_eq!(info.last_watch_status, WatchStatus::Paused);
+
+    let info = store.toggle_finish(&media, USER).await.unwrap().unwrap();
+    assert_eq!(info.watch_status, WatchStatus::Paused);
+    assert_eq!(info.last_watch_status, WatchStatus::Finished);
+}
+
+async fn toggle_consuming_on_a_new_entry_toggles_back_to_not_seen(store: &dyn WatchListStore) {
+    let media = anime(1, None);
+
+    let info = store.toggle_consuming(&media, USER).await.unwrap().unwrap();
+    assert_eq!(info.watch_status, WatchStatus::Consuming);
+    assert_eq!(info.last_watch_status, WatchStatus::NotSeen);
+
+    let info = store.toggle_consuming(&media, USER).await.unwrap().unwrap();
+    assert_eq!(info.watch_status, WatchStatus::NotSeen);
+}
+
+async fn setting_the_same_status_keeps_the_last_status(store: &dyn WatchListStore) {
+    let media = anime(1, Some(12));
+    store
+        .set_status(&media, USER, WatchStatus::Planning)
+        .await
+        .unwrap();
+    store
+        .set_status(&media, USER, WatchStatus::Dropped)
+        .await
+        .unwrap();
+
+    let info = store
+        .set_status(&media, USER, WatchStatus::Dropped)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(info.watch_status, WatchStatus::Dropped);
+    assert_eq!(info.last_watch_status, WatchStatus::Planning);
+}
+
+async fn every_started_rewatch_is_counted(store: &dyn WatchListStore) {
+    let media = anime(1, Some(12));
+    for status in [
+        WatchStatus::Repeating,
+        WatchStatus::Repeating,
+        WatchStatus::Finished,
+        WatchStatus::Repeating,
+    ] {
+        store.set_status(&media, USER, status).await.unwrap();
+    }
+
+    let info = store.find_watch_info(1, USER).await.unwrap().unwrap();
+    assert_eq!(info.repeat, 2);
+}
+
+async fn rewatches_start_over(store: &dyn WatchListStore) {
+    let media = anime(1, Some(12));
+    store.set_progress(&media, USER, 12, None).await.unwrap();
+
+    let info = store
+        .set_status(&media, USER, WatchStatus::Repeating)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(info.progress, 0);
+    assert_eq!(info.repeat, 1);
+
+    let info = store.add_progress(&media, USER, 1).await.unwrap().unwrap();
+    assert_eq!(info.progress, 1);
+    assert_eq!(info.watch_status, WatchStatus::Repeating);
+}
+
+async fn progress_starts_and_finishes_entries(store: &dyn WatchListStore) {
+    let media = anime(1, Some(12));
+    store
+        .set_status(&media, USER, WatchStatus::Planning)
+        .await
+        .unwrap();
+
+    let info = store.add_progress(&media, USER, 1).await.unwrap().unwrap();
+    assert_eq!(info.progress, 1);
+    assert_eq!(info.watch_status, WatchStatus::Consuming);
+    assert_eq!(info.last_watch_status, WatchStatus::Planning);
+
+    let info = store
+        .set_progress(&media, USER, 20, None)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(info.progress, 12);
+    assert_eq!(info.watch_status, WatchStatus::Finished);
+    assert_eq!(info.last_watch_status, WatchStatus::Consuming);
+}
+
+async fn progress_never_goes_below_zero_or_pauses_entries(store: &dyn WatchListStore) {
+    let media = manga(1, None);
+    store
+        .set_status(&media, USER, WatchStatus::Paused)
+        .await
+        .unwrap();
+
+    let info = store
+        .set_progress(&media, USER, 5, Some(2))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(info.watch_status, WatchStatus::Paused);
+    assert_eq!(info.progress_volumes, 2);
+    assert_eq!(info.media_type, MediaType::Manga);
+
+    let info = store
+        .add_progress(&media, USER, -10)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(info.progress, 0);
+    assert_eq!(info.progress_volumes, 2);
+}
+
+async fn ratings_out_of_range_are_refused(store: &dyn WatchListStore) {
+    let media = anime(1, None);
+
+    assert!(store.set_rating(&media, USER, 0).await.is_err());
+    assert!(store.set_rating(&media, USER, 11).await.is_err());
+    assert_eq!(store.find_watch_info(1, USER).await.unwrap(), None);
+}
+
+async fn entries_toggled_back_to_empty_are_not_listed(store: &dyn WatchListStore) {
+    let media = anime(1, None);
+
+    store.toggle_suggestion(&media, USER).await.unwrap();
+    assert_eq!(
+        store
+            .count_watch_list(USER, WatchListFilter::All)
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(
+        store
+            .count_watch_list(USER, WatchListFilter::Suggested)
+            .await
+            .unwrap(),
+        1
+    );
+
+    store.toggle_suggestion(&media, USER).await.unwrap();
+    assert_eq!(
+        store
+            .count_watch_list(USER, WatchListFilter::All)
+            .await
+            .unwrap(),
+        0
+    );
+}
+
+async fn watch_lists_are_sorted_and_paged(store: &dyn WatchListStore) {
+    for (id, rating) in [(1, 5), (2, 9), (3, 7)] {
+        store
+            .set_rating(&anime(id, None), USER, rating)
+            .await
+            .unwrap();
+        store
+            .set_status(&anime(id, None), USER, WatchStatus::Planning)
+            .await
+            .unwrap();
+    }
+    store
+        .set_status(&anime(4, None), OTHER_USER, WatchStatus::Planning)
+        .await
+        .unwrap();
+
+    let ids = |list: Vec<WatchInfo>| {
+        list.into_iter()
+            .map(|info| info.anilist_media_id)
+            .collect::<Vec<_>>()
+    };
+    let by_rating = store
+        .find_watch_list(USER, WatchListFilter::All, WatchListSort::Rating, 0, 10)
+        .await
+        .unwrap();
+    assert_eq!(ids(by_rating), vec![2, 3, 1]);
+
+    let second_page = store
+        .find_watch_list(USER, WatchListFilter::All, WatchListSort::CreatedAt, 1, 1)
+        .await
+        .unwrap();
+    assert_eq!(ids(second_page), vec![2]);
+}
+
+async fn consuming_entries_are_found_per_media_type(store: &dyn WatchListStore) {
+    store
+        .set_status(&anime(1, None), USER, WatchStatus::Consuming)
+        .await
+        .unwrap();
+    store
+        .set_status(&manga(2, None), USER, WatchStatus::Consuming)
+        .await
+        .unwrap();
+    store
+        .set_status(&anime(3, None), OTHER_USER, WatchStatus::Consuming)
+        .await
+        .unwrap();
+
+    let watching = store
+        .find_consuming(MediaType::Anime, &[USER])
+        .await
+        .unwrap();
+    assert_eq!(watching.len(), 1);
+    assert_eq!(watching[0].anilist_media_id, 1);
+}
+
+async fn suggestions_and_finished_entries_are_found_for_everyone(store: &dyn WatchListStore) {
+    store
+        .toggle_suggestion(&anime(1, None), USER)
+        .await
+        .unwrap();
+    store
+        .toggle_suggestion(&anime(1, None), OTHER_USER)
+        .await
+        .unwrap();
+    store
+        .toggle_suggestion(&manga(2, None), USER)
+        .await
+        .unwrap();
+    store
+        .set_status(&anime(1, None), OTHER_USER, WatchStatus::Finished)
+        .await
+        .unwrap();
+    store
+        .set_status(&anime(3, None), USER, WatchStatus::Finished)
+        .await
+        .unwrap();
+
+    let suggested = store.find_suggested().await.unwrap();
+    assert_eq!(suggested.len(), 3);
+
+    let finished = store.find_finished(&[1, 2]).await.unwrap();
+    assert_eq!(finished.len(), 1);
+    assert_eq!(finished[0].discord_user_id, OTHER_USER);
+    assert!(store.find_finished(&[]).await.unwrap().is_empty());
+}
+
+async fn listed_media_leave_out_empty_entries(store: &dyn WatchListStore) {
+    store
+        .set_status(&anime(1, None), USER, WatchStatus::Planning)
+        .await
+        .unwrap();
+    store
+        .set_status(&anime(1, None), OTHER_USER, WatchStatus::Finished)
+        .await
+        .unwrap();
+    store
+        .toggle_suggestion(&anime(2, None), USER)
+        .await
+        .unwrap();
+    store
+        .toggle_suggestion(&anime(3, None), USER)
+        .await
+        .unwrap();
+    store
+        .toggle_suggestion(&anime(3, None), USER)
+        .await
+        .unwrap();
+
+    let mut listed = store.find_listed_media(&[1, 2, 3, 4]).await.unwrap();
+    listed.sort_unstable();
+    assert_eq!(listed, vec![1, 2]);
+    assert!(store.find_listed_media(&[]).await.unwrap().is_empty());
+}
+
+async fn saved_entries_replace_and_are_found_for_their_user(store: &dyn WatchListStore) {
+    store
+        .set_status(&anime(1, None), USER, WatchStatus::Planning)
+        .await
+        .unwrap();
+    store
+        .toggle_suggestion(&anime(1, None), OTHER_USER)
+        .await
+        .unwrap();
+
+    let mut entries = store.find_all(USER).await.unwrap();
+    assert_eq!(entries.len(), 1);
+    entries[0].change_status(WatchStatus::Finished);
+    entries[0].progress = 12;
+    let mut new_info = entries[0].clone();
+    new_info.anilist_media_id = 2;
+    entries.push(new_info);
+    store.save_entries(entries).await.unwrap();
+
+    let entries = store.find_all(USER).await.unwrap();
+    assert_eq!(entries.len(), 2);
+    let info = store.find_watch_info(1, USER).await.unwrap().unwrap();
+    assert_eq!(info.watch_status, WatchStatus::Finished);
+    assert_eq!(info.last_watch_status, WatchStatus::Planning);
+    assert_eq!(info.progress, 12);
+    assert_eq!(store.find_all(OTHER_USER).await.unwrap().len(), 1);
+    assert_eq!(store.find_every_entry().await.unwrap().len(), 3);
+}
+
+/// Runs every test against each backend, they all have to behave like the MongoDB pipelines
+macro_rules! backend_tests {
+    ($backend:ident, $store:expr) => {
+        mod $backend {
+            #[tokio::test]
+            async fn new_entries_start_empty() {
+                super::new_entries_start_empty(&$store).await
+            }
+
+            #[tokio::test]
+            async fn toggle_finish_restores_the_last_status() {
+                super::toggle_finish_restores_the_last_status(&$store).await
+            }
+
+            #[tokio::test]
+            async fn toggle_consuming_on_a_new_entry_toggles_back_to_not_seen() {
+                super::toggle_consuming_on_a_new_entry_toggles_back_to_not_seen(&$store).await
+            }
+
+            #[tokio::test]
+            async fn setting_the_same_status_keeps_the_last_status() {
+                super::setting_the_same_status_keeps_the_last_status(&$store).await
+            }
+
+            #[tokio::test]
+            async fn every_started_rewatch_is_counted() {
+                super::every_started_rewatch_is_counted(&$store).await
+            }
+
+            #[tokio::test]
+            async fn rewatches_start_over() {
+                super::rewatches_start_over(&$store).await
+            }
+
+            #[tokio::test]
+            async fn progress_starts_and_finishes_entries() {
+                super::progress_starts_and_finishes_entries(&$store).await
+            }
+
+            #[tokio::test]
+            async fn progress_never_goes_below_zero_or_pauses_entries() {
+                super::progress_never_goes_below_zero_or_pauses_entries(&$store).await
+            }
+
+            #[tokio::test]
+            async fn ratings_out_of_range_are_refused() {
+                super::ratings_out_of_range_are_refused(&$store).await
+            }
+
+            #[tokio::test]
+            async fn entries_toggled_back_to_empty_are_not_listed() {
+                super::entries_toggled_back_to_empty_are_not_listed(&$store).await
+            }
+
+            #[tokio::test]
+            async fn watch_lists_are_sorted_and_paged() {
+                super::watch_lists_are_sorted_and_paged(&$store).await
+            }
+
+            #[tokio::test]
+            async fn consuming_entries_are_found_per_media_type() {
+                super::consuming_entries_are_found_per_media_type(&$store).await
+            }
+
+            #[tokio::test]
+            async fn suggestions_and_finished_entries_are_found_for_everyone() {
+                super::suggestions_and_finished_entries_are_found_for_everyone(&$store).await
+            }
+
+            #[tokio::test]
+            async fn listed_media_leave_out_empty_entries() {
+                super::listed_media_leave_out_empty_entries(&$store).await
+            }
+
+            #[tokio::test]
+            async fn saved_entries_replace_and_are_found_for_their_user() {
+                super::saved_entries_replace_and_are_found_for_their_user(&$store).await
+            }
+        }
+    };
+}
+
+backend_tests!(memory, crate::db::memory::MemoryWatchListStore::new());
+#[cfg(feature = "sqlite")]
+backend_tests!(
+    sqlite,
+    crate::db::sqlite::SqliteWatchListStore::open(":memory:").unwrap()
+);
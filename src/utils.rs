@@ -1,18 +1,76 @@
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
-use mongodm::prelude::MongoClient;
+use mongodm::prelude::MongoDatabase;
 use serenity::{
     client::bridge::gateway::ShardManager,
+    model::id::{GuildId, UserId},
     prelude::{Mutex, TypeMapKey},
 };
 
+use crate::{
+    anilist::AniListClient, db::watchlist::WatchListStore, metadata::MetadataProvider,
+    owner_log::OwnerLog, suggestions::SuggestionCache, sync::AniListSync,
+};
+
 pub struct ShardManagerContainer;
 pub struct DatabaseContainer;
+pub struct WatchListContainer;
+pub struct AniListContainer;
+/// Where media are looked up, AniList with the configured fallbacks
+pub struct MetadataContainer;
+pub struct AniListSyncContainer;
+pub struct OwnerLogContainer;
+pub struct SuggestionCacheContainer;
+/// The bot owners, as configured on the framework
+pub struct OwnersContainer;
+/// `BOT_PREFIX`, for guilds that did not set their own
+pub struct PrefixContainer;
+/// Guild prefixes read so far, so messages don't each cost a database round trip
+pub struct GuildPrefixContainer;
 
 impl TypeMapKey for ShardManagerContainer {
     type Value = Arc<Mutex<ShardManager>>;
 }
 
 impl TypeMapKey for DatabaseContainer {
-    type Value = MongoClient;
+    type Value = MongoDatabase;
+}
+
+impl TypeMapKey for WatchListContainer {
+    type Value = Arc<dyn WatchListStore>;
+}
+
+impl TypeMapKey for GuildPrefixContainer {
+    type Value = HashMap<GuildId, Option<String>>;
+}
+
+impl TypeMapKey for AniListContainer {
+    type Value = Arc<AniListClient>;
+}
+
+impl TypeMapKey for MetadataContainer {
+    type Value = Arc<dyn MetadataProvider>;
+}
+
+impl TypeMapKey for AniListSyncContainer {
+    type Value = Arc<AniListSync>;
+}
+
+impl TypeMapKey for OwnerLogContainer {
+    type Value = Arc<OwnerLog>;
+}
+
+impl TypeMapKey for SuggestionCacheContainer {
+    type Value = Arc<SuggestionCache>;
+}
+
+impl TypeMapKey for OwnersContainer {
+    type Value = HashSet<UserId>;
+}
+
+impl TypeMapKey for PrefixContainer {
+    type Value = String;
 }